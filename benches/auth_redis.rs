@@ -0,0 +1,122 @@
+//! Latency of `/auth` end to end: the full `app()` with the blacklist, challenges, sessions and
+//! the `/auth` cache on Redis, and the repositories in memory so only Redis and the handler count.
+//!
+//! Needs a running Redis: `REDIS_URL=redis://127.0.0.1:6379 cargo bench --bench auth_redis`
+//! (`BENCH_ITERATIONS` sets the sample count, default 2000). Skips if Redis is unreachable.
+
+use actix_web::{test, web};
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use authit::AppState;
+use authit::auth::{self, jwt};
+use authit::cache::AuthCache;
+use authit::config::Config;
+use authit::handlers::account::Role;
+use authit::repository::{HwidPolicy, MemoryRepository};
+
+const USER_ID: &str = "bench-user";
+
+fn report(name: &str, mut samples: Vec<Duration>) {
+    samples.sort();
+    let total: Duration = samples.iter().sum();
+    let percentile = |p: f64| samples[((samples.len() - 1) as f64 * p) as usize];
+
+    println!(
+        "{:<24} mean {:>9.1?}  p50 {:>9.1?}  p99 {:>9.1?}",
+        name,
+        total / samples.len() as u32,
+        percentile(0.50),
+        percentile(0.99),
+    );
+}
+
+/// The server `main` builds on the Redis backend, with the repositories in memory
+fn state(redis: ConnectionManager) -> Result<AppState, Box<dyn std::error::Error>> {
+    let repository = Arc::new(MemoryRepository::new());
+    repository.add_user(USER_ID, "bench@example.com", "hash", Role::User, 1);
+    repository.add_product("game", "Game", HwidPolicy::PerAccount, None);
+    repository.add_license(USER_ID, "game", chrono::Utc::now().timestamp() + 24 * 3600);
+
+    let mut state = AppState::in_memory(Config::default(), repository)?;
+    state.blacklist = Arc::new(auth::RedisBlacklist::new(redis.clone()));
+    state.challenges = Arc::new(auth::RedisChallenges::new(redis.clone()));
+    state.sessions = Arc::new(auth::RedisSessions::new(redis.clone()));
+    state.redis = Some(redis);
+    Ok(state)
+}
+
+async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+    let iterations: usize = std::env::var("BENCH_ITERATIONS").ok().and_then(|value| value.parse().ok()).unwrap_or(2000);
+
+    // The default config keeps retrying an unreachable Redis
+    let config = ConnectionManagerConfig::new().set_number_of_retries(1).set_connection_timeout(Duration::from_secs(2));
+    let redis = match ConnectionManager::new_with_config(redis::Client::open(url.as_str())?, config).await {
+        Ok(redis) => redis,
+        Err(err) => {
+            println!("Skipping, Redis at {} is unreachable: {}", url, err);
+            return Ok(());
+        }
+    };
+    let cache = AuthCache::new(Some(redis.clone()));
+    let state = web::Data::new(state(redis)?);
+    let token = jwt::generate_token(&state.jwt_keys, USER_ID.to_string(), "bench@example.com".to_string(), Role::User)?;
+    let app = test::init_service(authit::app(state, false)).await;
+
+    // Fetch a nonce, then time `/auth` with it and close the session it opened
+    let auth = || async {
+        let challenge = test::TestRequest::post().uri("/api/v1/auth/challenge").insert_header(("Authorization", format!("Bearer {}", token)));
+        let challenge: Value = test::call_and_read_body_json(&app, challenge.to_request()).await;
+
+        let request = test::TestRequest::post()
+            .uri("/api/v1/auth")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({ "product_id": "game", "hwid": "bench-hwid", "nonce": challenge["nonce"] }))
+            .to_request();
+        let started = Instant::now();
+        let response = test::call_service(&app, request).await;
+        let elapsed = started.elapsed();
+
+        let status = response.status();
+        let body: Value = test::read_body_json(response).await;
+        if !status.is_success() {
+            return Err(format!("/auth answered {}: {}", status, body));
+        }
+        let close = test::TestRequest::post()
+            .uri("/api/v1/session/close")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({ "session_id": body["session_id"] }));
+        test::call_service(&app, close.to_request()).await;
+
+        Ok(elapsed)
+    };
+
+    // Warm up, this also binds the HWID
+    for _ in 0..50 {
+        auth().await?;
+    }
+
+    let mut cold = Vec::with_capacity(iterations);
+    let mut cached = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        cache.invalidate_user(USER_ID).await?;
+        cold.push(auth().await?);
+        cached.push(auth().await?);
+    }
+
+    println!("POST /api/v1/auth against {} ({} iterations)", url, iterations);
+    report("user state not cached", cold);
+    report("user state cached", cached);
+
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = actix_web::rt::System::new().block_on(run()) {
+        eprintln!("Benchmark failed: {}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,25 @@
+use std::process::Command;
+
+/// Bake the git hash into the binary for the health endpoints, `GIT_HASH` overrides it (e.g. in Docker builds without .git)
+fn main() {
+    let hash = std::env::var("GIT_HASH")
+        .ok()
+        .filter(|hash| !hash.trim().is_empty())
+        .or_else(|| {
+            Command::new("git")
+                .args(["rev-parse", "--short", "HEAD"])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=AUTHIT_GIT_HASH={}", hash);
+    println!("cargo:rerun-if-env-changed=GIT_HASH");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+
+    // Migrations are embedded with sqlx::migrate!, rebuild when one is added
+    println!("cargo:rerun-if-changed=migrations");
+}
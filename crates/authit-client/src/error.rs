@@ -0,0 +1,60 @@
+use authit_types::ErrorCode;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The server answered with an error envelope, branch on `code`
+    Api {
+        status: u16,
+        code: ErrorCode,
+        message: String,
+        details: Option<serde_json::Value>,
+    },
+    /// The request never got an answer (connection refused, timeout, TLS...)
+    Http(reqwest::Error),
+    /// An authenticated call was made before `login` or `set_token`
+    NotLoggedIn,
+    /// An `/auth` response that doesn't carry a valid signature for the request, never trust it
+    InvalidSignature(String),
+    /// A response that isn't what the API documents
+    InvalidResponse(String),
+}
+
+impl Error {
+    /// The API error code, `None` for errors that didn't come from the server
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            Error::Api { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Api { status, code, message, .. } => write!(f, "{} ({}): {}", code, status, message),
+            Error::Http(err) => write!(f, "Request failed: {}", err),
+            Error::NotLoggedIn => write!(f, "Not logged in"),
+            Error::InvalidSignature(reason) => write!(f, "Invalid /auth signature: {}", reason),
+            Error::InvalidResponse(reason) => write!(f, "Invalid response: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Http(err)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
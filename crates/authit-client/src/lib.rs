@@ -0,0 +1,422 @@
+//! Async client for the authit API, wrapping what a game loader needs: login, `/auth` with a
+//! HWID, the products the account owns and redeeming keys.
+//!
+//! ```no_run
+//! # async fn run() -> authit_client::Result<()> {
+//! let client = authit_client::Client::new("https://auth.example.com");
+//! client.login("player@example.com", "hunter2").await?;
+//!
+//! // Requests a challenge, calls /auth and verifies the response signature
+//! let auth = client.auth("marvel-rivals", "HWID-1234", None).await?;
+//! match auth.time_remaining {
+//!     Some(seconds) => println!("{} seconds left", seconds),
+//!     None => println!("Lifetime license"),
+//! }
+//!
+//! client.heartbeat(&auth.session_id).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The login token is kept by the client and replaced by logging in again with the same
+//! credentials shortly before it expires, or when the server rejects it (e.g. after a role change).
+
+use authit_types::{
+    AuthRequest, AuthResponse, AuthSignature, ChallengeResponse, CloseSessionRequest, CompensateRequest, CompensateResponse,
+    DevicesResponse, ErrorBody, GenerateKeyRequest, GenerateKeyResponse, HeartbeatRequest, HeartbeatResponse, HwidComponents,
+    LoginRequest, LoginResponse, ProductLicense, ProductsResponse, PublicResponseKey, RedeemRequest, Role, SetRoleRequest,
+    SigningKeysResponse, signing_message,
+};
+use authit_types::ErrorCode;
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD};
+use ed25519_dalek::{Signature, VerifyingKey};
+use parking_lot::Mutex;
+use reqwest::Method;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub mod error;
+pub use error::*;
+pub use authit_types as types;
+
+/// Log in again once the token has less than this left
+const REFRESH_MARGIN_SECONDS: i64 = 60;
+
+struct Credentials {
+    email: String,
+    password: String,
+}
+
+/// The stored login, `user_id` and `expires_at` are read from the token and `None` if it can't be decoded
+#[derive(Default)]
+struct Login {
+    token: Option<String>,
+    user_id: Option<String>,
+    expires_at: Option<i64>,
+    credentials: Option<Credentials>,
+}
+
+/// Only the claims the client needs, the server is the one verifying the token
+#[derive(Deserialize)]
+struct TokenClaims {
+    sub: String,
+    exp: i64,
+}
+
+/// Responses that only carry a message
+#[derive(Deserialize)]
+struct Message {
+    message: Option<String>,
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+fn decode_claims(token: &str) -> Option<TokenClaims> {
+    let payload = token.split('.').nth(1)?;
+    let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+
+    serde_json::from_slice(&payload).ok()
+}
+
+/// `time_remaining` isn't signed, so it's taken from the signed expiry as of the signing time
+fn time_remaining(signed: &AuthSignature) -> Option<i64> {
+    signed.claims.expires_at.map(|expires_at| match expires_at {
+        i64::MAX => i64::MAX,
+        expires_at => expires_at - signed.timestamp,
+    })
+}
+
+fn parse_signing_key(key: &PublicResponseKey) -> Result<VerifyingKey> {
+    if key.alg != "EdDSA" {
+        return Err(Error::InvalidResponse(format!("signing key '{}' uses unsupported algorithm '{}'", key.kid, key.alg)));
+    }
+
+    BASE64
+        .decode(&key.public_key)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| Error::InvalidResponse(format!("signing key '{}' is not a base64 Ed25519 public key", key.kid)))
+}
+
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    login: Mutex<Login>,
+    /// kid -> key `/auth` responses may be signed with
+    signing_keys: Mutex<HashMap<String, VerifyingKey>>,
+    /// Pinned keys are never replaced by the ones the server publishes
+    pinned_keys: bool,
+}
+
+impl Client {
+    /// A client for the server at `base_url`, e.g. `https://auth.example.com`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            login: Mutex::new(Login::default()),
+            signing_keys: Mutex::new(HashMap::new()),
+            pinned_keys: false,
+        }
+    }
+
+    /// Use a preconfigured HTTP client (timeouts, proxy, user agent...)
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Only accept `/auth` responses signed with these keys instead of trusting the ones the server publishes
+    /// Loaders should ship the keys from `/.well-known/authit-signing-keys`, so a spoofed server can't sign its own answers
+    pub fn with_signing_keys(mut self, keys: &[PublicResponseKey]) -> Result<Self> {
+        let keys = keys
+            .iter()
+            .map(|key| Ok((key.kid.clone(), parse_signing_key(key)?)))
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        self.signing_keys = Mutex::new(keys);
+        self.pinned_keys = true;
+        Ok(self)
+    }
+
+    /// Log in and keep the token, the credentials are kept too so it can be renewed
+    pub async fn login(&self, email: &str, password: &str) -> Result<()> {
+        let token = self.request_token(email, password).await?;
+
+        self.store_token(token, Some(Credentials {
+            email: email.to_string(),
+            password: password.to_string(),
+        }));
+        Ok(())
+    }
+
+    /// The current login token, e.g. to persist it between runs
+    pub fn token(&self) -> Option<String> {
+        self.login.lock().token.clone()
+    }
+
+    /// Use a token obtained elsewhere (e.g. persisted by a previous run)
+    /// It can't be renewed, calls fail with `TOKEN_EXPIRED` once it runs out until `login` is called
+    pub fn set_token(&self, token: impl Into<String>) {
+        self.store_token(token.into(), None);
+    }
+
+    /// Forget the token and credentials
+    pub fn logout(&self) {
+        *self.login.lock() = Login::default();
+    }
+
+    /// The products the account owns and the time left on each
+    pub async fn products(&self) -> Result<Vec<ProductLicense>> {
+        let response: ProductsResponse = self.authed(Method::GET, "/api/v1/account/products", None).await?;
+
+        Ok(response.products)
+    }
+
+    /// The devices bound to the account and its slot count
+    pub async fn devices(&self) -> Result<DevicesResponse> {
+        self.authed(Method::GET, "/api/v1/account/devices", None).await
+    }
+
+    /// Redeem a key, returns the server's confirmation message
+    pub async fn redeem(&self, key: &str) -> Result<String> {
+        let body = serde_json::to_value(RedeemRequest { key: key.to_string() }).unwrap_or_default();
+        let response: Message = self.authed(Method::POST, "/api/v1/account/redeem", Some(body)).await?;
+
+        Ok(response.message.unwrap_or_default())
+    }
+
+    /// Authorize this machine for a product, opening a session that must be kept alive with `heartbeat`
+    /// A fresh challenge is requested for every call, and the response is rejected unless its signature
+    /// verifies and covers this exact request
+    pub async fn auth(&self, product_id: &str, hwid: &str, components: Option<HwidComponents>) -> Result<AuthResponse> {
+        let challenge: ChallengeResponse = self.authed(Method::POST, "/api/v1/auth/challenge", None).await?;
+
+        let request = AuthRequest {
+            product_id: product_id.to_string(),
+            hwid: hwid.to_string(),
+            components,
+            nonce: challenge.nonce,
+        };
+        let body = serde_json::to_value(&request).unwrap_or_default();
+        let mut response: AuthResponse = self.authed(Method::POST, "/api/v1/auth", Some(body)).await?;
+
+        self.verify(&request, &response).await?;
+        response.time_remaining = time_remaining(&response.signature);
+        Ok(response)
+    }
+
+    /// Keep a session opened by `auth` alive
+    pub async fn heartbeat(&self, session_id: &str) -> Result<HeartbeatResponse> {
+        let body = serde_json::to_value(HeartbeatRequest { session_id: session_id.to_string() }).unwrap_or_default();
+
+        self.authed(Method::POST, "/api/v1/session/heartbeat", Some(body)).await
+    }
+
+    /// End a session opened by `auth` once the product exits, so it stops counting towards `max_sessions`
+    pub async fn close_session(&self, session_id: &str) -> Result<()> {
+        let body = serde_json::to_value(CloseSessionRequest { session_id: session_id.to_string() }).unwrap_or_default();
+        let _: Message = self.authed(Method::POST, "/api/v1/session/close", Some(body)).await?;
+
+        Ok(())
+    }
+
+    /// Change a user's role, admin only, their existing tokens are revoked
+    pub async fn set_role(&self, user_id: &str, role: Role) -> Result<String> {
+        let body = serde_json::to_value(SetRoleRequest { user_id: user_id.to_string(), role }).unwrap_or_default();
+        let response: Message = self.authed(Method::POST, "/api/v1/account/set-role", Some(body)).await?;
+
+        Ok(response.message.unwrap_or_default())
+    }
+
+    /// Generate `count` keys worth `time_days` each, or lifetime keys for `None`, granting `tier` if given, admin only
+    pub async fn generate_keys(&self, product_id: &str, time_days: Option<i64>, count: i32, tier: Option<&str>) -> Result<Vec<String>> {
+        let body = serde_json::to_value(GenerateKeyRequest {
+            product_id: product_id.to_string(),
+            time_days,
+            lifetime: time_days.is_none(),
+            count,
+            tier: tier.map(str::to_string),
+        })
+        .unwrap_or_default();
+        let response: GenerateKeyResponse = self.authed(Method::POST, "/api/v1/product/generate-key", Some(body)).await?;
+
+        Ok(response.keys)
+    }
+
+    /// Extend every license for a product, admin only, returns how many users were compensated
+    pub async fn compensate(&self, product_id: &str, time_hours: i64) -> Result<i32> {
+        let body = serde_json::to_value(CompensateRequest { product_id: product_id.to_string(), time_hours })
+            .unwrap_or_default();
+        let response: CompensateResponse = self.authed(Method::POST, "/api/v1/product/compensate", Some(body)).await?;
+
+        Ok(response.users_compensated)
+    }
+
+    fn store_token(&self, token: String, credentials: Option<Credentials>) {
+        let claims = decode_claims(&token);
+
+        *self.login.lock() = Login {
+            token: Some(token),
+            user_id: claims.as_ref().map(|claims| claims.sub.clone()),
+            expires_at: claims.map(|claims| claims.exp),
+            credentials,
+        };
+    }
+
+    async fn request_token(&self, email: &str, password: &str) -> Result<String> {
+        let body = serde_json::to_value(LoginRequest {
+            email: email.to_string(),
+            password: password.to_string(),
+        })
+        .unwrap_or_default();
+        let response: LoginResponse = self.request(Method::POST, "/api/v1/account/login", None, Some(body)).await?;
+
+        Ok(response.token)
+    }
+
+    /// Log in again with the stored credentials, `None` if there are none
+    async fn renew(&self) -> Result<Option<String>> {
+        let credentials = self
+            .login
+            .lock()
+            .credentials
+            .as_ref()
+            .map(|credentials| (credentials.email.clone(), credentials.password.clone()));
+        let Some((email, password)) = credentials else {
+            return Ok(None);
+        };
+
+        let token = self.request_token(&email, &password).await?;
+        self.store_token(token.clone(), Some(Credentials { email, password }));
+
+        Ok(Some(token))
+    }
+
+    /// The token to send, renewed first if it is about to expire
+    async fn current_token(&self) -> Result<String> {
+        let (token, expires_at) = {
+            let login = self.login.lock();
+            (login.token.clone(), login.expires_at)
+        };
+        let Some(token) = token else {
+            return Err(Error::NotLoggedIn);
+        };
+
+        if expires_at.is_some_and(|expires_at| expires_at - now() < REFRESH_MARGIN_SECONDS) {
+            // Without credentials the old token is sent anyway and the server says why it's rejected
+            return Ok(self.renew().await?.unwrap_or(token));
+        }
+
+        Ok(token)
+    }
+
+    /// An authenticated request, retried once with a new token if the server rejects the current one
+    async fn authed<T: DeserializeOwned>(&self, method: Method, path: &str, body: Option<serde_json::Value>) -> Result<T> {
+        let token = self.current_token().await?;
+
+        let result = self.request(method.clone(), path, Some(&token), body.clone()).await;
+        if let Err(Error::Api { code: ErrorCode::TokenExpired | ErrorCode::TokenInvalid, .. }) = &result
+            && let Some(token) = self.renew().await?
+        {
+            return self.request(method, path, Some(&token), body).await;
+        }
+
+        result
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        token: Option<&str>,
+        body: Option<serde_json::Value>,
+    ) -> Result<T> {
+        let mut request = self.http.request(method, format!("{}{}", self.base_url, path));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let bytes = response.bytes().await?;
+
+        if status.is_success() {
+            return serde_json::from_slice(&bytes).map_err(|err| Error::InvalidResponse(format!("{} from {}", err, path)));
+        }
+
+        match serde_json::from_slice::<ErrorBody>(&bytes) {
+            Ok(error) => Err(Error::Api {
+                status: status.as_u16(),
+                code: error.code,
+                message: error.message,
+                details: error.details,
+            }),
+            Err(_) => Err(Error::InvalidResponse(format!("{} from {} without an error body", status, path))),
+        }
+    }
+
+    /// The key a response was signed with, fetching the published keys if it isn't known yet (e.g. after a rotation)
+    async fn signing_key(&self, kid: &str) -> Result<VerifyingKey> {
+        if let Some(key) = self.signing_keys.lock().get(kid) {
+            return Ok(*key);
+        }
+        if self.pinned_keys {
+            return Err(Error::InvalidSignature(format!("signed with unknown key '{}'", kid)));
+        }
+
+        let response: SigningKeysResponse = self.request(Method::GET, "/.well-known/authit-signing-keys", None, None).await?;
+        let keys = response
+            .keys
+            .iter()
+            .map(|key| Ok((key.kid.clone(), parse_signing_key(key)?)))
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        let mut signing_keys = self.signing_keys.lock();
+        *signing_keys = keys;
+        signing_keys
+            .get(kid)
+            .copied()
+            .ok_or_else(|| Error::InvalidSignature(format!("signed with unknown key '{}'", kid)))
+    }
+
+    /// Check the signature and that it vouches for this request, not a replayed or redirected one
+    async fn verify(&self, request: &AuthRequest, response: &AuthResponse) -> Result<()> {
+        let signed = &response.signature;
+        let claims = &signed.claims;
+
+        let user_id = self.login.lock().user_id.clone();
+        if claims.product_id != request.product_id
+            || claims.hwid != request.hwid
+            || claims.nonce != request.nonce
+            || claims.session_id != response.session_id
+            || claims.tier != response.tier
+            || claims.entitlements != response.entitlements
+            || user_id.is_some_and(|user_id| claims.user_id != user_id)
+        {
+            return Err(Error::InvalidSignature("signed claims don't match the request".to_string()));
+        }
+
+        let key = self.signing_key(&signed.kid).await?;
+        let signature = BASE64
+            .decode(&signed.signature)
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or_else(|| Error::InvalidSignature("malformed signature".to_string()))?;
+
+        key.verify_strict(&signing_message(claims, signed.timestamp), &signature)
+            .map_err(|_| Error::InvalidSignature("signature does not verify".to_string()))
+    }
+}
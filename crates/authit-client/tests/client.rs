@@ -0,0 +1,319 @@
+//! Runs the client against a real server started in process on the in-memory backends
+
+use actix_web::{HttpServer, web};
+use argon2::password_hash::{PasswordHasher, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use std::net::TcpListener;
+use std::sync::Arc;
+
+use authit::AppState;
+use authit::config::Config;
+use authit::handlers::account::Role;
+use authit::repository::HwidPolicy;
+use authit::repository::{KeyRepository, LicenseRepository, MemoryRepository, WebhookRepository};
+use authit::webhook::WebhookEvent;
+use authit_client::types::{ErrorCode, HwidComponents, PublicResponseKey};
+use authit_client::{Client, Error};
+
+const PASSWORD: &str = "correct horse battery staple";
+
+/// Cheap parameters keep the tests fast, the hash records them so the server verifies with the same
+fn password_hash() -> String {
+    let salt = SaltString::from_b64("c29tZXNhbHRzb21lc2FsdA").unwrap();
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, Params::new(8, 1, 1, None).unwrap());
+    argon2.hash_password(PASSWORD.as_bytes(), &salt).unwrap().to_string()
+}
+
+fn now() -> i64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64
+}
+
+/// A user with one account wide device slot and a day left on `game`
+fn seed() -> Arc<MemoryRepository> {
+    let repository = Arc::new(MemoryRepository::new());
+    repository.add_user("user-1", "player@example.com", &password_hash(), Role::User, 1);
+    repository.add_product("game", "Game", HwidPolicy::PerAccount, None);
+    repository.add_product("tool", "Tool", HwidPolicy::None, None);
+    repository.add_license("user-1", "game", now() + 24 * 3600);
+    repository
+}
+
+/// Serve the API on a free port, returns its URL and the state to poke at
+fn start(repository: Arc<MemoryRepository>) -> (String, web::Data<AppState>) {
+    let state = web::Data::new(AppState::in_memory(Config::default(), repository).unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let server_state = state.clone();
+    let server = HttpServer::new(move || authit::app(server_state.clone(), false))
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+    actix_web::rt::spawn(server);
+
+    (url, state)
+}
+
+async fn logged_in() -> (Client, web::Data<AppState>, Arc<MemoryRepository>) {
+    let repository = seed();
+    let (url, state) = start(repository.clone());
+    let client = Client::new(url);
+    client.login("player@example.com", PASSWORD).await.unwrap();
+    (client, state, repository)
+}
+
+#[actix_web::test]
+async fn login_and_list_products() {
+    let (client, _, _) = logged_in().await;
+    assert!(client.token().is_some());
+
+    let products = client.products().await.unwrap();
+    assert_eq!(products.len(), 1);
+    assert_eq!(products[0].product_id, "game");
+    assert!(products[0].time_remaining_seconds.is_some_and(|seconds| seconds > 23 * 3600));
+}
+
+#[actix_web::test]
+async fn wrong_password_is_a_typed_error() {
+    let (url, _) = start(seed());
+    let client = Client::new(url);
+
+    let err = client.login("player@example.com", "wrong").await.unwrap_err();
+    assert!(matches!(err, Error::Api { status: 401, code: ErrorCode::InvalidCredentials, .. }), "{:?}", err);
+    assert!(matches!(client.products().await, Err(Error::NotLoggedIn)));
+}
+
+#[actix_web::test]
+async fn redeem_adds_the_product_once() {
+    let (client, _, repository) = logged_in().await;
+    repository.insert("TOOL-KEY", "tool", Some(48), None).await.unwrap();
+
+    client.redeem("TOOL-KEY").await.unwrap();
+    let products = client.products().await.unwrap();
+    assert!(products.iter().any(|product| product.product_id == "tool"));
+
+    let err = client.redeem("TOOL-KEY").await.unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::KeyInvalid));
+}
+
+#[actix_web::test]
+async fn redeem_queues_a_webhook_delivery() {
+    let (client, _, repository) = logged_in().await;
+    repository.create("http://127.0.0.1:9/hook", "whsec_test_secret", &[WebhookEvent::KeyRedeemed]).await.unwrap();
+    repository.insert("GAME-KEY", "game", Some(24), None).await.unwrap();
+
+    client.redeem("GAME-KEY").await.unwrap();
+
+    let deliveries = repository.deliveries(None, None, 10).await.unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].event, WebhookEvent::KeyRedeemed);
+}
+
+#[actix_web::test]
+async fn higher_tier_keys_upgrade_the_license_and_auth_signs_its_entitlements() {
+    let (client, _, repository) = logged_in().await;
+    repository.add_tier("game", "basic", 1, &["esp"]);
+    repository.add_tier("game", "premium", 2, &["esp", "aimbot"]);
+    repository.insert("PREMIUM-KEY", "game", Some(24), Some("premium")).await.unwrap();
+    repository.insert("BASIC-KEY", "game", Some(24), Some("basic")).await.unwrap();
+
+    client.redeem("PREMIUM-KEY").await.unwrap();
+    let auth = client.auth("game", "hwid-a", None).await.unwrap();
+    assert_eq!(auth.tier.as_deref(), Some("premium"));
+    assert_eq!(auth.entitlements, ["esp", "aimbot"]);
+    // The day left before the upgrade is kept
+    assert!(auth.time_remaining.is_some_and(|seconds| seconds > 47 * 3600));
+
+    let err = client.redeem("BASIC-KEY").await.unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::TierDowngrade));
+}
+
+#[actix_web::test]
+async fn keys_without_a_tier_add_time_and_keep_the_tier() {
+    let (client, _, repository) = logged_in().await;
+    repository.add_tier("game", "premium", 2, &["aimbot"]);
+    repository.add_tier("tool", "premium", 2, &["export"]);
+    repository.insert("PREMIUM-KEY", "game", Some(24), Some("premium")).await.unwrap();
+    repository.insert("GAME-KEY", "game", Some(24), None).await.unwrap();
+    repository.insert("TOOL-KEY", "tool", Some(24), None).await.unwrap();
+    // An expired premium license for tool
+    repository.add_license("user-1", "tool", now() - 60);
+    LicenseRepository::set_tier(&*repository, "user-1", "tool", Some("premium")).await.unwrap();
+
+    client.redeem("PREMIUM-KEY").await.unwrap();
+    client.redeem("GAME-KEY").await.unwrap();
+    client.redeem("TOOL-KEY").await.unwrap();
+
+    let products = client.products().await.unwrap();
+    let game = products.iter().find(|product| product.product_id == "game").unwrap();
+    assert_eq!(game.tier.as_deref(), Some("premium"));
+    assert!(game.time_remaining_seconds.is_some_and(|seconds| seconds > 71 * 3600));
+    let tool = products.iter().find(|product| product.product_id == "tool").unwrap();
+    assert_eq!(tool.tier.as_deref(), Some("premium"));
+    assert_eq!(tool.entitlements, ["export"]);
+}
+
+#[actix_web::test]
+async fn keys_for_an_unknown_tier_are_refused() {
+    let (client, _, repository) = logged_in().await;
+    repository.insert("GONE-KEY", "game", Some(24), Some("removed")).await.unwrap();
+
+    let err = client.redeem("GONE-KEY").await.unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::InternalError));
+    let products = client.products().await.unwrap();
+    assert!(products[0].tier.is_none());
+    assert!(products[0].time_remaining_seconds.is_some_and(|seconds| seconds < 25 * 3600));
+}
+
+#[actix_web::test]
+async fn lifetime_keys_convert_the_license_and_refuse_more_time() {
+    let (client, _, repository) = logged_in().await;
+    repository.insert("LIFETIME-KEY", "game", None, None).await.unwrap();
+    repository.insert("GAME-KEY", "game", Some(24), None).await.unwrap();
+
+    client.redeem("LIFETIME-KEY").await.unwrap();
+    let auth = client.auth("game", "hwid-a", None).await.unwrap();
+    assert_eq!(auth.time_remaining, None);
+    assert_eq!(auth.signature.claims.expires_at, None);
+
+    let products = client.products().await.unwrap();
+    assert_eq!(products[0].expires_at, None);
+    assert_eq!(products[0].time_remaining_seconds, None);
+
+    let err = client.redeem("GAME-KEY").await.unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::LicenseLifetime));
+}
+
+#[actix_web::test]
+async fn auth_binds_the_hwid_and_verifies_the_signature() {
+    let (client, _, _) = logged_in().await;
+
+    let auth = client.auth("game", "hwid-a", None).await.unwrap();
+    assert!(auth.time_remaining.is_some_and(|seconds| seconds > 23 * 3600));
+    assert_eq!(auth.signature.claims.hwid, "hwid-a");
+    client.heartbeat(&auth.session_id).await.unwrap();
+
+    // The only slot is taken now
+    let err = client.auth("game", "hwid-b", None).await.unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::HwidMismatch));
+
+    let err = client.auth("other", "hwid-a", None).await.unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::LicenseNotFound));
+}
+
+#[actix_web::test]
+async fn component_matches_keep_bans_and_need_changed_hardware() {
+    let (client, _, repository) = logged_in().await;
+    let machine: HwidComponents = [("disk", "d1"), ("board", "b1"), ("cpu", "c1"), ("mac", "m1"), ("gpu", "g1")]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    client.auth("game", "hwid-a", Some(machine.clone())).await.unwrap();
+
+    // Replayed components don't move the slot to another HWID
+    let err = client.auth("game", "hwid-b", Some(machine.clone())).await.unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::HwidMismatch));
+
+    // A new GPU does, unless the machine is banned
+    let mut upgraded = machine.clone();
+    upgraded.insert("gpu".to_string(), "g2".to_string());
+    repository.ban_hwid("hwid-a");
+    let err = client.auth("game", "hwid-b", Some(upgraded)).await.unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::HwidBanned));
+}
+
+#[actix_web::test]
+async fn closed_sessions_free_their_slot() {
+    let (client, _, repository) = logged_in().await;
+    authit::repository::ProductRepository::set_max_sessions(&*repository, "game", Some(1)).await.unwrap();
+
+    let auth = client.auth("game", "hwid-a", None).await.unwrap();
+    let err = client.auth("game", "hwid-a", None).await.unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::SessionLimitReached));
+
+    client.close_session(&auth.session_id).await.unwrap();
+    let err = client.heartbeat(&auth.session_id).await.unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::SessionNotFound));
+    client.auth("game", "hwid-a", None).await.unwrap();
+}
+
+#[actix_web::test]
+async fn responses_signed_with_other_keys_are_rejected() {
+    let (url, state) = start(seed());
+    // The server's key id with someone else's key, as a spoofed server would sign
+    let client = Client::new(url)
+        .with_signing_keys(&[PublicResponseKey {
+            kid: state.response_signer.public_keys()[0].kid.clone(),
+            alg: "EdDSA".to_string(),
+            public_key: "11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=".to_string(),
+            active: true,
+        }])
+        .unwrap();
+    client.login("player@example.com", PASSWORD).await.unwrap();
+
+    let err = client.auth("game", "hwid-a", None).await.unwrap_err();
+    assert!(matches!(err, Error::InvalidSignature(_)), "{:?}", err);
+}
+
+#[actix_web::test]
+async fn time_remaining_comes_from_the_signed_expiry() {
+    let (client, state, _) = logged_in().await;
+    let genuine = client.auth("game", "hwid-a", None).await.unwrap();
+
+    // Replays the genuine signed response with more time claimed next to it
+    let mut forged = genuine.clone();
+    forged.time_remaining = Some(10 * 365 * 24 * 3600);
+    let nonce = genuine.signature.claims.nonce.clone();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = HttpServer::new(move || {
+        let (nonce, forged) = (nonce.clone(), forged.clone());
+        actix_web::App::new()
+            .route("/api/v1/auth/challenge", web::post().to(move || {
+                let nonce = nonce.clone();
+                async move { web::Json(serde_json::json!({ "nonce": nonce, "expires_in": 60 })) }
+            }))
+            .route("/api/v1/auth", web::post().to(move || {
+                let forged = forged.clone();
+                async move { web::Json(forged) }
+            }))
+    })
+    .workers(1)
+    .listen(listener)
+    .unwrap()
+    .run();
+    actix_web::rt::spawn(server);
+
+    let spoofed = Client::new(url).with_signing_keys(&state.response_signer.public_keys()).unwrap();
+    spoofed.set_token(client.token().unwrap());
+    let auth = spoofed.auth("game", "hwid-a", None).await.unwrap();
+    let expires_at = genuine.signature.claims.expires_at.unwrap();
+    assert_eq!(auth.time_remaining, Some(expires_at - genuine.signature.timestamp));
+    assert!(auth.time_remaining.is_some_and(|seconds| seconds <= 24 * 3600));
+}
+
+#[actix_web::test]
+async fn revoked_token_is_renewed_with_the_stored_credentials() {
+    let (client, state, _) = logged_in().await;
+    let old_token = client.token().unwrap();
+
+    // Tokens carry whole seconds, the renewed one must be issued after the revocation
+    actix_web::rt::time::sleep(std::time::Duration::from_millis(1100)).await;
+    state.blacklist.blacklist_user_before_timestamp("user-1", now(), 60).await.unwrap();
+
+    assert_eq!(client.products().await.unwrap().len(), 1);
+    assert_ne!(client.token().unwrap(), old_token);
+
+    // A token set by hand has no credentials to renew it with
+    client.set_token(old_token);
+    assert_eq!(client.products().await.unwrap_err().code(), Some(ErrorCode::TokenInvalid));
+}
+
+#[actix_web::test]
+async fn jwks_is_a_bare_jwk_set() {
+    let (url, _) = start(seed());
+
+    let body: serde_json::Value = reqwest::get(format!("{}/.well-known/jwks.json", url)).await.unwrap().json().await.unwrap();
+    assert_eq!(body, serde_json::json!({ "keys": [] }));
+}
@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Stable machine-readable error codes, clients branch on these and never on the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum ErrorCode {
+    // Malformed requests
+    InvalidRequest,
+    RouteNotFound,
+
+    // JWT
+    TokenMissing,
+    TokenInvalid,
+    TokenExpired,
+    PermissionDenied,
+
+    // Accounts
+    InvalidCredentials,
+    UserNotFound,
+    SelfDemotion,
+    AccountBanned,
+
+    // OAuth account linking, renamed since serde would split "OAuth"
+    #[serde(rename = "OAUTH_PROVIDER_NOT_FOUND")]
+    OAuthProviderNotFound,
+    #[serde(rename = "OAUTH_STATE_INVALID")]
+    OAuthStateInvalid,
+    #[serde(rename = "OAUTH_PROVIDER_ERROR")]
+    OAuthProviderError,
+    AccountNotLinked,
+    AccountAlreadyLinked,
+    LinkNotFound,
+
+    // Hardware
+    HwidBanned,
+    HwidMismatch,
+    HwidBindFailed,
+    DeviceNotFound,
+    DeviceReleaseCooldown,
+
+    // /auth protocol
+    ChallengeInvalid,
+    SessionLimitReached,
+    SessionNotFound,
+
+    // Products, licenses and keys
+    ProductNotFound,
+    LicenseNotFound,
+    LicenseExpired,
+    KeyInvalid,
+    KeyGenerationFailed,
+    TierNotFound,
+    TierDowngrade,
+    LicenseLifetime,
+
+    // Webhooks
+    WebhookNotFound,
+    DeliveryNotFound,
+
+    ServiceUnavailable,
+    InternalError,
+
+    /// A code added by a newer server, never sent by this version
+    #[serde(other, skip_serializing)]
+    Unknown,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidRequest => "INVALID_REQUEST",
+            ErrorCode::RouteNotFound => "ROUTE_NOT_FOUND",
+            ErrorCode::TokenMissing => "TOKEN_MISSING",
+            ErrorCode::TokenInvalid => "TOKEN_INVALID",
+            ErrorCode::TokenExpired => "TOKEN_EXPIRED",
+            ErrorCode::PermissionDenied => "PERMISSION_DENIED",
+            ErrorCode::InvalidCredentials => "INVALID_CREDENTIALS",
+            ErrorCode::UserNotFound => "USER_NOT_FOUND",
+            ErrorCode::SelfDemotion => "SELF_DEMOTION",
+            ErrorCode::AccountBanned => "ACCOUNT_BANNED",
+            ErrorCode::OAuthProviderNotFound => "OAUTH_PROVIDER_NOT_FOUND",
+            ErrorCode::OAuthStateInvalid => "OAUTH_STATE_INVALID",
+            ErrorCode::OAuthProviderError => "OAUTH_PROVIDER_ERROR",
+            ErrorCode::AccountNotLinked => "ACCOUNT_NOT_LINKED",
+            ErrorCode::AccountAlreadyLinked => "ACCOUNT_ALREADY_LINKED",
+            ErrorCode::LinkNotFound => "LINK_NOT_FOUND",
+            ErrorCode::HwidBanned => "HWID_BANNED",
+            ErrorCode::HwidMismatch => "HWID_MISMATCH",
+            ErrorCode::HwidBindFailed => "HWID_BIND_FAILED",
+            ErrorCode::DeviceNotFound => "DEVICE_NOT_FOUND",
+            ErrorCode::DeviceReleaseCooldown => "DEVICE_RELEASE_COOLDOWN",
+            ErrorCode::ChallengeInvalid => "CHALLENGE_INVALID",
+            ErrorCode::SessionLimitReached => "SESSION_LIMIT_REACHED",
+            ErrorCode::SessionNotFound => "SESSION_NOT_FOUND",
+            ErrorCode::ProductNotFound => "PRODUCT_NOT_FOUND",
+            ErrorCode::LicenseNotFound => "LICENSE_NOT_FOUND",
+            ErrorCode::LicenseExpired => "LICENSE_EXPIRED",
+            ErrorCode::KeyInvalid => "KEY_INVALID",
+            ErrorCode::KeyGenerationFailed => "KEY_GENERATION_FAILED",
+            ErrorCode::TierNotFound => "TIER_NOT_FOUND",
+            ErrorCode::TierDowngrade => "TIER_DOWNGRADE",
+            ErrorCode::LicenseLifetime => "LICENSE_LIFETIME",
+            ErrorCode::WebhookNotFound => "WEBHOOK_NOT_FOUND",
+            ErrorCode::DeliveryNotFound => "DELIVERY_NOT_FOUND",
+            ErrorCode::ServiceUnavailable => "SERVICE_UNAVAILABLE",
+            ErrorCode::InternalError => "INTERNAL_ERROR",
+            ErrorCode::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Error envelope, every non-2xx response has this shape
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ErrorBody {
+    pub success: bool,
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_round_trip_and_match_as_str() {
+        for code in [ErrorCode::HwidMismatch, ErrorCode::InternalError, ErrorCode::DeviceReleaseCooldown, ErrorCode::OAuthStateInvalid] {
+            let json = serde_json::to_value(code).unwrap();
+            assert_eq!(json, code.as_str());
+            assert_eq!(serde_json::from_value::<ErrorCode>(json).unwrap(), code);
+        }
+
+        assert_eq!(serde_json::from_str::<ErrorCode>("\"SOMETHING_NEW\"").unwrap(), ErrorCode::Unknown);
+    }
+}
@@ -0,0 +1,21 @@
+//! Wire types of the authit API, shared by the server and the client SDK
+//!
+//! Success responses are `{"success": true, "message"?: "...", ...fields}`, the structs here are
+//! the fields. Errors are an `ErrorBody`.
+
+pub mod account;
+pub mod error;
+pub mod oauth;
+pub mod product;
+pub mod public;
+pub mod session;
+pub mod signing;
+pub mod webhook;
+pub use account::*;
+pub use error::*;
+pub use oauth::*;
+pub use product::*;
+pub use public::*;
+pub use session::*;
+pub use signing::*;
+pub use webhook::*;
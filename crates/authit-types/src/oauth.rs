@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+
+/// An external account (e.g. Discord) linked to a user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct LinkedAccount {
+    /// Provider name from the server's configuration
+    pub provider: String,
+    /// The account's id at the provider
+    pub external_id: String,
+    /// Display name at the provider, as of the last link
+    pub username: Option<String>,
+    pub linked_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct LinkedAccountsResponse {
+    pub accounts: Vec<LinkedAccount>,
+}
+
+/// Starts a link or login, its result only goes back to the loader that started it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct OAuthStartRequest {
+    /// Loopback URL the loader listens on (`http://127.0.0.1:<port>/...`), the browser is sent there
+    /// with a one-time `code`, or with `error` if the provider didn't complete the sign-in
+    pub return_to: String,
+    /// Base64url (no padding) SHA-256 of a random verifier only the loader knows, like PKCE's S256
+    pub code_challenge: String,
+}
+
+/// Where to send the user's browser, the provider redirects back to the callback with `state`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct OAuthAuthorizeResponse {
+    pub authorize_url: String,
+    /// Single use, valid for ten minutes
+    pub state: String,
+}
+
+/// What the provider appends to the redirect URI
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct OAuthCallbackQuery {
+    pub state: String,
+    /// Missing when the user declined, `error` says why
+    pub code: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Redeems the `code` the browser brought to `return_to`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct OAuthExchangeRequest {
+    pub code: String,
+    /// The verifier `code_challenge` was made from
+    pub code_verifier: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct OAuthExchangeResponse {
+    pub account: LinkedAccount,
+    /// Set when the flow was a login, a JWT like `/account/login` returns
+    pub token: Option<String>,
+}
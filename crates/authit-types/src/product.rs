@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+/// How a product binds hardware
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[cfg_attr(feature = "sqlx", sqlx(type_name = "hwid_policy"))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum HwidPolicy {
+    /// Hardware is not checked
+    None,
+    /// Uses the account wide device slots
+    PerAccount,
+    /// One device per account for this product
+    PerProduct,
+    /// Up to `hwid_device_limit` devices per account for this product
+    Devices,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct GenerateKeyRequest {
+    pub product_id: String,
+    /// Required unless `lifetime` is set
+    #[serde(default)]
+    pub time_days: Option<i64>,
+    /// Keys for licenses that never expire, these take no `time_days`
+    #[serde(default)]
+    pub lifetime: bool,
+    #[serde(default = "default_count")]
+    pub count: i32,
+    /// One of the product's tiers, keys without a tier only add time
+    #[serde(default)]
+    pub tier: Option<String>,
+}
+
+fn default_count() -> i32 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct GenerateKeyResponse {
+    pub keys: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CompensateRequest {
+    pub product_id: String,
+    pub time_hours: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CompensateResponse {
+    pub users_compensated: i32,
+}
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::signing::AuthSignature;
+
+/// Named hardware components reported by the client, e.g. `disk` -> serial
+pub type HwidComponents = BTreeMap<String, String>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AuthRequest {
+    pub product_id: String,
+    pub hwid: String,
+    /// Optional named hardware components, enables fuzzy matching when the HWID changes
+    #[serde(default)]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<std::collections::HashMap<String, String>>))]
+    pub components: Option<HwidComponents>,
+    pub nonce: String, // from POST /auth/challenge
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AuthResponse {
+    /// Seconds until the license expires, `None` for lifetime licenses
+    pub time_remaining: Option<i64>,
+    /// Must be kept alive with POST /session/heartbeat
+    pub session_id: String,
+    /// `None` for licenses without a tier
+    pub tier: Option<String>,
+    /// Features the license unlocks, covered by the signature
+    pub entitlements: Vec<String>,
+    /// Clients must reject responses without a valid signature
+    pub signature: AuthSignature,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ChallengeResponse {
+    pub nonce: String,
+    pub expires_in: u64,
+}
+
+/// Public half of a signing key, as published at the well-known endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PublicResponseKey {
+    pub kid: String,
+    pub alg: String,
+    pub public_key: String, // base64
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SigningKeysResponse {
+    pub keys: Vec<PublicResponseKey>,
+}
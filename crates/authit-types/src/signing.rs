@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+/// Prefix of every signed message, bumped if the format ever changes
+pub const SIGNATURE_VERSION: &str = "authit-auth-v3";
+
+/// The facts a signed `/auth` response vouches for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SignedClaims {
+    pub user_id: String,
+    pub product_id: String,
+    pub hwid: String,
+    pub nonce: String,
+    pub session_id: String,
+    /// Unix timestamp, i64::MAX for unlimited access and `None` for lifetime licenses
+    pub expires_at: Option<i64>,
+    pub tier: Option<String>,
+    pub entitlements: Vec<String>,
+}
+
+/// Signature block attached to `/auth` responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AuthSignature {
+    #[serde(flatten)]
+    pub claims: SignedClaims,
+    pub timestamp: i64,
+    pub kid: String,
+    pub signature: String, // base64
+}
+
+/// Build the exact bytes covered by the signature
+/// Every field is length prefixed so client controlled values (like the HWID) can't shift fields around
+/// The entitlements come last, after their count
+pub fn signing_message(claims: &SignedClaims, timestamp: i64) -> Vec<u8> {
+    let mut fields = vec![
+        SIGNATURE_VERSION.to_string(),
+        claims.user_id.clone(),
+        claims.product_id.clone(),
+        claims.hwid.clone(),
+        claims.nonce.clone(),
+        claims.session_id.clone(),
+        claims.expires_at.map(|expires_at| expires_at.to_string()).unwrap_or_default(),
+        timestamp.to_string(),
+        claims.tier.clone().unwrap_or_default(),
+        claims.entitlements.len().to_string(),
+    ];
+    fields.extend(claims.entitlements.iter().cloned());
+
+    let mut message = Vec::new();
+    for field in fields {
+        message.extend_from_slice(format!("{}:", field.len()).as_bytes());
+        message.extend_from_slice(field.as_bytes());
+    }
+
+    message
+}
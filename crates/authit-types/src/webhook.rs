@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Something a webhook can subscribe to, sent as e.g. `"key.redeemed"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum WebhookEvent {
+    #[serde(rename = "key.redeemed")]
+    KeyRedeemed,
+    #[serde(rename = "license.expired")]
+    LicenseExpired,
+    #[serde(rename = "user.banned")]
+    UserBanned,
+    #[serde(rename = "user.unbanned")]
+    UserUnbanned,
+    /// A bound device was released, freeing its slot for new hardware
+    #[serde(rename = "hwid.reset")]
+    HwidReset,
+    #[serde(rename = "role.changed")]
+    RoleChanged,
+    #[serde(rename = "product.frozen")]
+    ProductFrozen,
+    #[serde(rename = "product.unfrozen")]
+    ProductUnfrozen,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 8] = [
+        WebhookEvent::KeyRedeemed,
+        WebhookEvent::LicenseExpired,
+        WebhookEvent::UserBanned,
+        WebhookEvent::UserUnbanned,
+        WebhookEvent::HwidReset,
+        WebhookEvent::RoleChanged,
+        WebhookEvent::ProductFrozen,
+        WebhookEvent::ProductUnfrozen,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::KeyRedeemed => "key.redeemed",
+            WebhookEvent::LicenseExpired => "license.expired",
+            WebhookEvent::UserBanned => "user.banned",
+            WebhookEvent::UserUnbanned => "user.unbanned",
+            WebhookEvent::HwidReset => "hwid.reset",
+            WebhookEvent::RoleChanged => "role.changed",
+            WebhookEvent::ProductFrozen => "product.frozen",
+            WebhookEvent::ProductUnfrozen => "product.unfrozen",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|event| event.as_str() == name)
+    }
+}
+
+impl fmt::Display for WebhookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The JSON body POSTed to a webhook
+///
+/// Every delivery carries `X-Authit-Event`, `X-Authit-Delivery` and
+/// `X-Authit-Signature: t=<unix seconds>,v1=<hex HMAC-SHA256 of "<t>.<body>" keyed with the secret>`.
+/// `id` is the same for every webhook notified of one event, and on redeliveries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct WebhookPayload {
+    pub id: String,
+    pub event: WebhookEvent,
+    pub created_at: String,
+    pub data: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[cfg_attr(feature = "sqlx", sqlx(type_name = "webhook_delivery_status"))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum DeliveryStatus {
+    /// Waiting for its first attempt or a retry
+    Pending,
+    Delivered,
+    /// Every attempt failed, only a redelivery sends it again
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    /// Generated when not given
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreateWebhookResponse {
+    pub webhook: Webhook,
+    /// Only returned here, keep it to verify signatures
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct WebhooksResponse {
+    pub webhooks: Vec<Webhook>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: WebhookEvent,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    /// HTTP status of the last attempt, `None` if it got no response
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    /// `None` unless it is pending
+    pub next_attempt_at: Option<String>,
+    pub created_at: String,
+    pub delivered_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct DeliveriesQuery {
+    pub webhook_id: Option<String>,
+    pub status: Option<DeliveryStatus>,
+    /// Newest first, 50 by default and at most 500
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct WebhookDeliveriesResponse {
+    pub deliveries: Vec<WebhookDelivery>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_names_match_serde() {
+        for event in WebhookEvent::ALL {
+            assert_eq!(serde_json::to_value(event).unwrap(), event.as_str());
+            assert_eq!(WebhookEvent::parse(event.as_str()), Some(event));
+        }
+        assert_eq!(WebhookEvent::parse("key.stolen"), None);
+    }
+}
@@ -0,0 +1,19 @@
+-- Seed the product referenced by the test keys in 002
+INSERT INTO products (id, name)
+VALUES
+    ('arena-breakout', 'Arena Breakout')
+ON CONFLICT (id) DO NOTHING;
+
+-- Add the cd_keys foreign key where 003 couldn't because of those keys
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM information_schema.table_constraints
+        WHERE constraint_name = 'fk_cd_keys_product'
+    ) THEN
+        ALTER TABLE cd_keys
+        ADD CONSTRAINT fk_cd_keys_product
+        FOREIGN KEY (product_id) REFERENCES products(id)
+        ON DELETE RESTRICT;
+    END IF;
+END $$;
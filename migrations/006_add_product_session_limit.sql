@@ -0,0 +1,15 @@
+-- Limit how many machines can run a product at once per account
+-- NULL means unlimited
+ALTER TABLE products ADD COLUMN IF NOT EXISTS max_sessions INTEGER;
+
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM information_schema.table_constraints
+        WHERE constraint_name = 'check_max_sessions_positive'
+    ) THEN
+        ALTER TABLE products
+        ADD CONSTRAINT check_max_sessions_positive
+        CHECK (max_sessions IS NULL OR max_sessions > 0);
+    END IF;
+END $$;
@@ -0,0 +1,34 @@
+-- Bound hardware moves from users.hwid to its own table so an account can have several devices
+CREATE TABLE IF NOT EXISTS user_devices (
+    id TEXT PRIMARY KEY DEFAULT gen_random_uuid()::text,
+    user_id TEXT NOT NULL,
+    hwid TEXT NOT NULL,
+    name TEXT,
+    bound_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+
+    UNIQUE (user_id, hwid),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_devices_user_id ON user_devices(user_id);
+
+-- How many devices an account can have bound at once
+ALTER TABLE users ADD COLUMN IF NOT EXISTS device_slots INTEGER NOT NULL DEFAULT 2;
+
+-- Releasing a device slot is rate limited by this timestamp
+ALTER TABLE users ADD COLUMN IF NOT EXISTS last_device_release_at TIMESTAMP WITH TIME ZONE;
+
+-- Move existing bindings over, only runs while the old column still exists
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_name = 'users' AND column_name = 'hwid'
+    ) THEN
+        INSERT INTO user_devices (user_id, hwid)
+        SELECT id, hwid FROM users WHERE hwid IS NOT NULL
+        ON CONFLICT (user_id, hwid) DO NOTHING;
+
+        ALTER TABLE users DROP COLUMN hwid;
+    END IF;
+END $$;
@@ -0,0 +1,2 @@
+-- Structured HWID components (disk serial, board id, ...) used for fuzzy device matching
+ALTER TABLE user_devices ADD COLUMN IF NOT EXISTS components JSONB;
@@ -0,0 +1,31 @@
+-- How a product binds hardware
+--   None       - hardware is not checked
+--   PerAccount - uses the account wide device slots (users.device_slots)
+--   PerProduct - one device per account for this product
+--   Devices    - up to hwid_device_limit devices per account for this product
+DO $$ BEGIN
+    CREATE TYPE hwid_policy AS ENUM ('None', 'PerAccount', 'PerProduct', 'Devices');
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+ALTER TABLE products ADD COLUMN IF NOT EXISTS hwid_policy hwid_policy NOT NULL DEFAULT 'PerAccount';
+ALTER TABLE products ADD COLUMN IF NOT EXISTS hwid_device_limit INTEGER;
+
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM information_schema.table_constraints
+        WHERE constraint_name = 'check_hwid_device_limit'
+    ) THEN
+        ALTER TABLE products
+        ADD CONSTRAINT check_hwid_device_limit
+        CHECK (hwid_policy <> 'Devices' OR hwid_device_limit > 0);
+    END IF;
+END $$;
+
+-- Devices bound for a single product, NULL for account wide devices
+ALTER TABLE user_devices ADD COLUMN IF NOT EXISTS product_id TEXT REFERENCES products(id) ON DELETE CASCADE;
+
+ALTER TABLE user_devices DROP CONSTRAINT IF EXISTS user_devices_user_id_hwid_key;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_user_devices_scope_hwid ON user_devices (user_id, product_id, hwid) NULLS NOT DISTINCT;
@@ -0,0 +1,40 @@
+-- Outgoing webhooks, `events` holds event names such as 'key.redeemed'
+CREATE TABLE IF NOT EXISTS webhooks (
+    id TEXT PRIMARY KEY DEFAULT gen_random_uuid()::text,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    events TEXT[] NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+DO $$ BEGIN
+    CREATE TYPE webhook_delivery_status AS ENUM ('Pending', 'Delivered', 'Failed');
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+-- One row per event and webhook, written in the same place as the change it reports
+-- The server's dispatcher works through the pending rows, so events from authit-admin are delivered too
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id TEXT PRIMARY KEY DEFAULT gen_random_uuid()::text,
+    webhook_id TEXT NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+    event TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    status webhook_delivery_status NOT NULL DEFAULT 'Pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    last_status_code INTEGER,
+    last_error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    delivered_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due ON webhook_deliveries (next_attempt_at) WHERE status = 'Pending';
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_created_at ON webhook_deliveries (created_at);
+
+-- Set once license.expired went out for the current expiry, extending the license clears it
+ALTER TABLE user_licenses ADD COLUMN IF NOT EXISTS expiry_notified BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE INDEX IF NOT EXISTS idx_user_licenses_expiry_pending ON user_licenses (expires_at) WHERE NOT expiry_notified;
+
+-- Licenses that expired before webhooks existed aren't announced
+UPDATE user_licenses SET expiry_notified = TRUE WHERE expires_at <= NOW() AND NOT expiry_notified;
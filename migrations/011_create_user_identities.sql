@@ -0,0 +1,13 @@
+-- External accounts (Discord, ...) linked through OAuth2, at most one per provider for each user
+CREATE TABLE IF NOT EXISTS user_identities (
+    user_id TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    external_id TEXT NOT NULL,
+    username TEXT,
+    linked_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    PRIMARY KEY (user_id, provider),
+    -- An external account belongs to one user, it's what logging in through the provider looks up
+    UNIQUE (provider, external_id),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,40 @@
+-- Named tiers of a product (e.g. basic and premium), a higher level ranks above the lower ones
+-- `entitlements` holds the feature names `/auth` returns for licenses of the tier
+CREATE TABLE IF NOT EXISTS product_tiers (
+    product_id TEXT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    level INTEGER NOT NULL,
+    entitlements TEXT[] NOT NULL DEFAULT '{}',
+
+    PRIMARY KEY (product_id, name),
+    UNIQUE (product_id, level),
+
+    CONSTRAINT check_tier_name_format CHECK (name ~ '^[a-z0-9-]+$')  -- Enforce kebab-case
+);
+
+-- NULL for keys and licenses without a tier, which rank below every tier
+ALTER TABLE cd_keys ADD COLUMN IF NOT EXISTS tier TEXT;
+ALTER TABLE user_licenses ADD COLUMN IF NOT EXISTS tier TEXT;
+
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM information_schema.table_constraints
+        WHERE constraint_name = 'fk_cd_keys_tier'
+    ) THEN
+        ALTER TABLE cd_keys
+        ADD CONSTRAINT fk_cd_keys_tier
+        FOREIGN KEY (product_id, tier) REFERENCES product_tiers(product_id, name)
+        ON DELETE RESTRICT;
+    END IF;
+
+    IF NOT EXISTS (
+        SELECT 1 FROM information_schema.table_constraints
+        WHERE constraint_name = 'fk_user_licenses_tier'
+    ) THEN
+        ALTER TABLE user_licenses
+        ADD CONSTRAINT fk_user_licenses_tier
+        FOREIGN KEY (product_id, tier) REFERENCES product_tiers(product_id, name)
+        ON DELETE RESTRICT;
+    END IF;
+END $$;
@@ -0,0 +1,19 @@
+-- Lifetime keys carry no duration, every other key still needs a positive one
+ALTER TABLE cd_keys ADD COLUMN IF NOT EXISTS lifetime BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE cd_keys ALTER COLUMN time_hours DROP NOT NULL;
+ALTER TABLE cd_keys DROP CONSTRAINT IF EXISTS check_time_positive;
+
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM information_schema.table_constraints
+        WHERE constraint_name = 'check_key_duration'
+    ) THEN
+        ALTER TABLE cd_keys
+        ADD CONSTRAINT check_key_duration
+        CHECK ((lifetime AND time_hours IS NULL) OR (NOT lifetime AND time_hours > 0));
+    END IF;
+END $$;
+
+-- NULL for lifetime licenses, which never expire
+ALTER TABLE user_licenses ALTER COLUMN expires_at DROP NOT NULL;
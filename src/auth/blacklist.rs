@@ -1,19 +1,69 @@
+use async_trait::async_trait;
 use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+use std::fmt;
 use tracing::info;
 
-#[derive(Clone)]
-pub struct TokenBlacklist {
-    redis_client: redis::Client,
+use super::revocations::LocalRevocations;
+
+#[derive(Debug)]
+pub enum BlacklistError {
+    Redis(redis::RedisError),
 }
 
-impl TokenBlacklist {
-    pub fn new(redis_client: redis::Client) -> Self {
-        Self { redis_client }
+impl fmt::Display for BlacklistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlacklistError::Redis(err) => write!(f, "{}", err),
+        }
     }
+}
 
+impl From<redis::RedisError> for BlacklistError {
+    fn from(err: redis::RedisError) -> Self {
+        BlacklistError::Redis(err)
+    }
+}
+
+pub type BlacklistResult<T> = Result<T, BlacklistError>;
+
+/// Where revoked tokens are kept, picked by `BLACKLIST_BACKEND`
+#[async_trait]
+pub trait TokenBlacklist: Send + Sync {
     /// Blacklist a specific token until its expiration
-    pub async fn blacklist_token(&self, token: &str, expires_in_seconds: i64) -> Result<(), redis::RedisError> {
-        let mut conn = self.redis_client.get_multiplexed_async_connection().await?;
+    #[allow(dead_code)] // no logout endpoint yet
+    async fn blacklist_token(&self, token: &str, expires_in_seconds: i64) -> BlacklistResult<()>;
+
+    /// Blacklist all tokens for a user issued before a specific timestamp
+    /// This is used when a user's role changes - we invalidate old tokens but allow new ones
+    /// TTL should match the maximum token lifetime (e.g., 24 hours)
+    async fn blacklist_user_before_timestamp(&self, user_id: &str, timestamp: i64, ttl_seconds: i64) -> BlacklistResult<()>;
+
+    /// Look up both blacklists for a request
+    /// Returns whether the token itself is blacklisted, and the timestamp the user's tokens are blacklisted before (if any)
+    async fn check(&self, token: &str, user_id: &str) -> BlacklistResult<(bool, Option<i64>)>;
+
+    /// Remove user from blacklist (if needed for debugging/admin override)
+    #[allow(dead_code)]
+    async fn unblacklist_user(&self, user_id: &str) -> BlacklistResult<()>;
+}
+
+/// Shared by every instance, revocations survive restarts
+#[derive(Clone)]
+pub struct RedisBlacklist {
+    redis: ConnectionManager,
+}
+
+impl RedisBlacklist {
+    pub fn new(redis: ConnectionManager) -> Self {
+        Self { redis }
+    }
+}
+
+#[async_trait]
+impl TokenBlacklist for RedisBlacklist {
+    async fn blacklist_token(&self, token: &str, expires_in_seconds: i64) -> BlacklistResult<()> {
+        let mut conn = self.redis.clone();
         let key = format!("blacklist:token:{}", token);
 
         // Set with TTL matching token expiration
@@ -23,19 +73,8 @@ impl TokenBlacklist {
         Ok(())
     }
 
-    /// Check if a specific token is blacklisted
-    pub async fn is_token_blacklisted(&self, token: &str) -> Result<bool, redis::RedisError> {
-        let mut conn = self.redis_client.get_multiplexed_async_connection().await?;
-        let key = format!("blacklist:token:{}", token);
-
-        conn.exists(&key).await
-    }
-
-    /// Blacklist all tokens for a user issued before a specific timestamp
-    /// This is used when a user's role changes - we invalidate old tokens but allow new ones
-    /// TTL should match the maximum token lifetime (e.g., 24 hours)
-    pub async fn blacklist_user_before_timestamp(&self, user_id: &str, timestamp: i64, ttl_seconds: i64) -> Result<(), redis::RedisError> {
-        let mut conn = self.redis_client.get_multiplexed_async_connection().await?;
+    async fn blacklist_user_before_timestamp(&self, user_id: &str, timestamp: i64, ttl_seconds: i64) -> BlacklistResult<()> {
+        let mut conn = self.redis.clone();
         let key = format!("blacklist:user:{}", user_id);
 
         // Store the timestamp until longest possible token expiration
@@ -45,31 +84,22 @@ impl TokenBlacklist {
         Ok(())
     }
 
-    /// Check if a user's token is blacklisted based on when it was issued
-    /// Returns true if the token was issued before the stored blacklist timestamp
-    pub async fn is_user_token_blacklisted(&self, user_id: &str, token_issued_at: i64) -> Result<bool, redis::RedisError> {
-        let mut conn = self.redis_client.get_multiplexed_async_connection().await?;
-        let key = format!("blacklist:user:{}", user_id);
+    async fn check(&self, token: &str, user_id: &str) -> BlacklistResult<(bool, Option<i64>)> {
+        let mut conn = self.redis.clone();
 
-        // Get the blacklist timestamp (tokens issued before this are invalid)
-        let blacklist_timestamp: Option<String> = conn.get(&key).await?;
-
-        match blacklist_timestamp {
-            Some(timestamp_str) => {
-                // Parse the timestamp and compare
-                if let Ok(blacklist_ts) = timestamp_str.parse::<i64>() {
-                    Ok(token_issued_at < blacklist_ts)
-                } else {
-                    Ok(false)
-                }
-            }
-            None => Ok(false), // No blacklist entry for this user
-        }
+        // Both lookups in one round-trip
+        let (token_blacklisted, user_timestamp): (bool, Option<String>) = redis::pipe()
+            .exists(format!("blacklist:token:{}", token))
+            .get(format!("blacklist:user:{}", user_id))
+            .query_async(&mut conn)
+            .await?;
+
+        // No entry (or an unparseable one) means nothing is blacklisted for the user
+        Ok((token_blacklisted, user_timestamp.and_then(|timestamp| timestamp.parse::<i64>().ok())))
     }
 
-    /// Remove user from blacklist (if needed for debugging/admin override)
-    pub async fn unblacklist_user(&self, user_id: &str) -> Result<(), redis::RedisError> {
-        let mut conn = self.redis_client.get_multiplexed_async_connection().await?;
+    async fn unblacklist_user(&self, user_id: &str) -> BlacklistResult<()> {
+        let mut conn = self.redis.clone();
         let key = format!("blacklist:user:{}", user_id);
 
         let _: () = conn.del(&key).await?;
@@ -78,3 +108,116 @@ impl TokenBlacklist {
         Ok(())
     }
 }
+
+/// Kept in process, for single instance deployments and tests
+/// Revocations are lost on restart and not seen by other instances
+#[derive(Clone, Default)]
+pub struct MemoryBlacklist {
+    revocations: LocalRevocations,
+}
+
+impl MemoryBlacklist {
+    /// Keep the entries in `revocations`, shared with `AppState::revocations` so both see every revocation
+    pub fn new(revocations: LocalRevocations) -> Self {
+        Self { revocations }
+    }
+}
+
+#[async_trait]
+impl TokenBlacklist for MemoryBlacklist {
+    async fn blacklist_token(&self, token: &str, expires_in_seconds: i64) -> BlacklistResult<()> {
+        self.revocations.revoke_token(token, expires_in_seconds);
+        info!("Blacklisted token (expires in {}s)", expires_in_seconds);
+
+        Ok(())
+    }
+
+    async fn blacklist_user_before_timestamp(&self, user_id: &str, timestamp: i64, ttl_seconds: i64) -> BlacklistResult<()> {
+        self.revocations.revoke_user_before(user_id, timestamp, ttl_seconds);
+        info!("Blacklisted tokens for user {} issued before timestamp {} (expires in {}s)", user_id, timestamp, ttl_seconds);
+
+        Ok(())
+    }
+
+    async fn check(&self, token: &str, user_id: &str) -> BlacklistResult<(bool, Option<i64>)> {
+        Ok((self.revocations.is_token_revoked(token), self.revocations.user_revoked_before(user_id)))
+    }
+
+    async fn unblacklist_user(&self, user_id: &str) -> BlacklistResult<()> {
+        self.revocations.forget_user(user_id);
+        info!("Removed blacklist for user {}", user_id);
+
+        Ok(())
+    }
+}
+
+/// Redis during an outage, every call fails
+#[cfg(test)]
+pub struct UnreachableBlacklist;
+
+#[cfg(test)]
+impl UnreachableBlacklist {
+    fn unreachable<T>() -> BlacklistResult<T> {
+        Err(redis::RedisError::from((redis::ErrorKind::IoError, "connection refused")).into())
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl TokenBlacklist for UnreachableBlacklist {
+    async fn blacklist_token(&self, _: &str, _: i64) -> BlacklistResult<()> {
+        Self::unreachable()
+    }
+
+    async fn blacklist_user_before_timestamp(&self, _: &str, _: i64, _: i64) -> BlacklistResult<()> {
+        Self::unreachable()
+    }
+
+    async fn check(&self, _: &str, _: &str) -> BlacklistResult<(bool, Option<i64>)> {
+        Self::unreachable()
+    }
+
+    async fn unblacklist_user(&self, _: &str) -> BlacklistResult<()> {
+        Self::unreachable()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn memory_blacklist_revokes_tokens_and_users() {
+        let blacklist = MemoryBlacklist::default();
+
+        assert_eq!(blacklist.check("token", "user-1").await.unwrap(), (false, None));
+
+        blacklist.blacklist_token("token", 60).await.unwrap();
+        blacklist.blacklist_user_before_timestamp("user-1", 1_000, 60).await.unwrap();
+        assert_eq!(blacklist.check("token", "user-1").await.unwrap(), (true, Some(1_000)));
+        assert_eq!(blacklist.check("other", "user-2").await.unwrap(), (false, None));
+
+        blacklist.unblacklist_user("user-1").await.unwrap();
+        assert_eq!(blacklist.check("other", "user-1").await.unwrap(), (false, None));
+    }
+
+    #[actix_web::test]
+    async fn memory_blacklist_shares_its_revocations() {
+        let revocations = LocalRevocations::default();
+        let blacklist = MemoryBlacklist::new(revocations.clone());
+
+        revocations.revoke_user_before("user-1", 1_000, 60);
+        assert_eq!(blacklist.check("token", "user-1").await.unwrap(), (false, Some(1_000)));
+        blacklist.blacklist_token("token", 60).await.unwrap();
+        assert!(revocations.is_token_revoked("token"));
+    }
+
+    #[actix_web::test]
+    async fn memory_blacklist_entries_expire() {
+        let blacklist = MemoryBlacklist::default();
+
+        blacklist.blacklist_token("token", 0).await.unwrap();
+        blacklist.blacklist_user_before_timestamp("user-1", 1_000, 0).await.unwrap();
+        assert_eq!(blacklist.check("token", "user-1").await.unwrap(), (false, None));
+    }
+}
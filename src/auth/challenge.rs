@@ -0,0 +1,141 @@
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::Utc;
+use parking_lot::Mutex;
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+use std::collections::HashMap;
+
+/// How long a client has to use a nonce after requesting it
+pub const CHALLENGE_TTL_SECONDS: u64 = 30;
+
+fn new_nonce() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+}
+
+/// Single-use nonces that bind an `/auth` response to one request
+#[async_trait]
+pub trait ChallengeStore: Send + Sync {
+    /// Issue a fresh nonce for a user, valid for `CHALLENGE_TTL_SECONDS`
+    async fn issue(&self, user_id: &str) -> Result<String, redis::RedisError>;
+
+    /// Consume a nonce, returns true only if it was issued to this user and not used or expired yet
+    /// A second call with the same nonce always fails
+    async fn consume(&self, user_id: &str, nonce: &str) -> Result<bool, redis::RedisError>;
+}
+
+/// Shared by every instance, so the challenge and `/auth` may hit different ones
+#[derive(Clone)]
+pub struct RedisChallenges {
+    redis: ConnectionManager,
+}
+
+impl RedisChallenges {
+    pub fn new(redis: ConnectionManager) -> Self {
+        Self { redis }
+    }
+}
+
+#[async_trait]
+impl ChallengeStore for RedisChallenges {
+    async fn issue(&self, user_id: &str) -> Result<String, redis::RedisError> {
+        let mut conn = self.redis.clone();
+        let nonce = new_nonce();
+        let key = format!("challenge:{}", nonce);
+
+        let _: () = conn.set_ex(&key, user_id, CHALLENGE_TTL_SECONDS).await?;
+
+        Ok(nonce)
+    }
+
+    async fn consume(&self, user_id: &str, nonce: &str) -> Result<bool, redis::RedisError> {
+        let mut conn = self.redis.clone();
+        let key = format!("challenge:{}", nonce);
+
+        // Deleted in the same command, so concurrent requests can't both spend it
+        let owner: Option<String> = conn.get_del(&key).await?;
+
+        Ok(owner.as_deref() == Some(user_id))
+    }
+}
+
+/// Kept in process, for tests and the in-memory server
+pub struct MemoryChallenges {
+    /// nonce -> (user id, expires at)
+    nonces: Mutex<HashMap<String, (String, i64)>>,
+    ttl_seconds: i64,
+}
+
+impl MemoryChallenges {
+    /// Nonces that expire after `ttl_seconds` instead of `CHALLENGE_TTL_SECONDS`
+    pub fn with_ttl(ttl_seconds: u64) -> Self {
+        Self {
+            nonces: Mutex::default(),
+            ttl_seconds: ttl_seconds as i64,
+        }
+    }
+}
+
+impl Default for MemoryChallenges {
+    fn default() -> Self {
+        Self::with_ttl(CHALLENGE_TTL_SECONDS)
+    }
+}
+
+#[async_trait]
+impl ChallengeStore for MemoryChallenges {
+    async fn issue(&self, user_id: &str) -> Result<String, redis::RedisError> {
+        let now = Utc::now().timestamp();
+        let nonce = new_nonce();
+
+        let mut nonces = self.nonces.lock();
+        nonces.retain(|_, (_, expires_at)| *expires_at > now);
+        nonces.insert(nonce.clone(), (user_id.to_string(), now + self.ttl_seconds));
+
+        Ok(nonce)
+    }
+
+    async fn consume(&self, user_id: &str, nonce: &str) -> Result<bool, redis::RedisError> {
+        let now = Utc::now().timestamp();
+
+        Ok(self
+            .nonces
+            .lock()
+            .remove(nonce)
+            .is_some_and(|(owner, expires_at)| owner == user_id && expires_at > now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn memory_nonces_are_single_use() {
+        let challenges = MemoryChallenges::default();
+
+        let nonce = challenges.issue("user-1").await.unwrap();
+        assert!(challenges.consume("user-1", &nonce).await.unwrap());
+        assert!(!challenges.consume("user-1", &nonce).await.unwrap());
+        assert!(!challenges.consume("user-1", "never-issued").await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn memory_nonces_belong_to_one_user() {
+        let challenges = MemoryChallenges::default();
+
+        // Trying it as someone else spends it too
+        let nonce = challenges.issue("user-1").await.unwrap();
+        assert!(!challenges.consume("user-2", &nonce).await.unwrap());
+        assert!(!challenges.consume("user-1", &nonce).await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn memory_nonces_expire() {
+        let challenges = MemoryChallenges::with_ttl(0);
+
+        let nonce = challenges.issue("user-1").await.unwrap();
+        assert!(!challenges.consume("user-1", &nonce).await.unwrap());
+    }
+}
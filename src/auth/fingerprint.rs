@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+pub use authit_types::HwidComponents;
+
+/// Components without a configured weight still count, just less
+const UNKNOWN_COMPONENT_WEIGHT: f64 = 1.0;
+
+/// Decides whether two sets of HWID components describe the same machine
+///
+/// The similarity is the weight of the components that are equal divided by the weight
+/// of every component either side reported, so a single swapped GPU doesn't lock anyone out.
+#[derive(Debug, Clone)]
+pub struct FingerprintMatcher {
+    weights: HashMap<String, f64>,
+    threshold: f64,
+    max_drift: usize,
+}
+
+impl FingerprintMatcher {
+    pub fn new(weights: HashMap<String, f64>, threshold: f64, max_drift: usize) -> Self {
+        Self { weights, threshold, max_drift }
+    }
+
+    fn weight(&self, component: &str) -> f64 {
+        self.weights.get(component).copied().unwrap_or(UNKNOWN_COMPONENT_WEIGHT)
+    }
+
+    /// Weighted share of components that are identical on both sides, between 0 and 1
+    pub fn similarity(&self, stored: &HwidComponents, presented: &HwidComponents) -> f64 {
+        let mut matching = 0.0;
+        let mut total = 0.0;
+
+        for name in stored.keys().chain(presented.keys().filter(|name| !stored.contains_key(*name))) {
+            let weight = self.weight(name);
+            total += weight;
+            if stored.get(name) == presented.get(name) {
+                matching += weight;
+            }
+        }
+
+        if total == 0.0 { 0.0 } else { matching / total }
+    }
+
+    pub fn is_same_device(&self, stored: &HwidComponents, presented: &HwidComponents) -> bool {
+        self.similarity(stored, presented) >= self.threshold
+    }
+
+    /// The drifted components if `presented` may take over the stored fingerprint under a new HWID
+    ///
+    /// Components are reported by the client, so besides being similar enough at least one of them
+    /// has to have changed to account for the new HWID, and at most `max_drift` may change at once.
+    /// Replaying a bound device's components can't move its slot, and a fingerprint only moves one
+    /// small step per match.
+    pub fn fuzzy_match(&self, stored: &HwidComponents, presented: &HwidComponents) -> Option<Vec<String>> {
+        let drifted = Self::drifted(stored, presented);
+
+        (self.is_same_device(stored, presented) && (1..=self.max_drift).contains(&drifted.len())).then_some(drifted)
+    }
+
+    /// Names of the components that changed, appeared or disappeared
+    pub fn drifted(stored: &HwidComponents, presented: &HwidComponents) -> Vec<String> {
+        stored
+            .keys()
+            .chain(presented.keys().filter(|name| !stored.contains_key(*name)))
+            .filter(|name| stored.get(*name) != presented.get(*name))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn components(pairs: &[(&str, &str)]) -> HwidComponents {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn matcher(threshold: f64) -> FingerprintMatcher {
+        let weights = [("disk", 3.0), ("board", 3.0), ("cpu", 2.0), ("mac", 1.0), ("gpu", 1.0)]
+            .into_iter()
+            .map(|(name, weight)| (name.to_string(), weight))
+            .collect();
+        FingerprintMatcher::new(weights, threshold, 1)
+    }
+
+    fn machine() -> HwidComponents {
+        components(&[("disk", "d1"), ("board", "b1"), ("cpu", "c1"), ("mac", "m1"), ("gpu", "g1")])
+    }
+
+    #[test]
+    fn identical_components_match_exactly() {
+        let matcher = matcher(0.7);
+
+        assert_eq!(matcher.similarity(&machine(), &machine()), 1.0);
+        assert!(matcher.is_same_device(&machine(), &machine()));
+        assert!(FingerprintMatcher::drifted(&machine(), &machine()).is_empty());
+        // Nothing to compare is never a match
+        assert_eq!(matcher.similarity(&HwidComponents::new(), &HwidComponents::new()), 0.0);
+    }
+
+    #[test]
+    fn the_threshold_is_inclusive() {
+        let mut presented = machine();
+        presented.insert("disk".to_string(), "d2".to_string());
+        // 7 of 10 weight still matches
+        assert_eq!(matcher(0.7).similarity(&machine(), &presented), 0.7);
+        assert!(matcher(0.7).is_same_device(&machine(), &presented));
+        assert!(!matcher(0.71).is_same_device(&machine(), &presented));
+    }
+
+    #[test]
+    fn missing_components_count_against_the_match() {
+        let matcher = matcher(0.7);
+        let mut presented = machine();
+        presented.remove("board");
+        presented.insert("tpm".to_string(), "t1".to_string());
+
+        // board (3) and the unknown tpm (1) are only on one side each: 7 of 11
+        assert_eq!(matcher.similarity(&machine(), &presented), 7.0 / 11.0);
+        assert_eq!(FingerprintMatcher::drifted(&machine(), &presented), vec!["board".to_string(), "tpm".to_string()]);
+    }
+
+    #[test]
+    fn weights_are_relative() {
+        let scaled = FingerprintMatcher::new(
+            [("disk", 30.0), ("board", 30.0), ("cpu", 20.0), ("mac", 10.0), ("gpu", 10.0)]
+                .into_iter()
+                .map(|(name, weight)| (name.to_string(), weight))
+                .collect(),
+            0.7,
+            1,
+        );
+        let mut presented = machine();
+        presented.insert("cpu".to_string(), "c2".to_string());
+
+        assert_eq!(scaled.similarity(&machine(), &presented), matcher(0.7).similarity(&machine(), &presented));
+        assert_eq!(scaled.similarity(&machine(), &presented), 0.8);
+    }
+
+    #[test]
+    fn fuzzy_matches_need_a_small_drift() {
+        let matcher = matcher(0.5);
+
+        // Replayed components can't vouch for another HWID
+        assert_eq!(matcher.fuzzy_match(&machine(), &machine()), None);
+
+        let mut presented = machine();
+        presented.insert("gpu".to_string(), "g2".to_string());
+        assert_eq!(matcher.fuzzy_match(&machine(), &presented), Some(vec!["gpu".to_string()]));
+
+        // Similar enough, but more than one component moved at once
+        presented.insert("mac".to_string(), "m2".to_string());
+        assert!(matcher.is_same_device(&machine(), &presented));
+        assert_eq!(matcher.fuzzy_match(&machine(), &presented), None);
+    }
+}
@@ -1,15 +1,21 @@
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 use chrono::{Duration, Utc};
-use actix_web::{FromRequest, HttpRequest, HttpResponse, dev::Payload, error::ResponseError, web};
+use actix_web::{FromRequest, HttpRequest, dev::Payload, web};
 use std::pin::Pin;
-use std::fmt;
 use std::ops::Deref;
 use std::future::Future;
+use tracing::warn;
 
 use crate::handlers::account::Role;
 use crate::AppState;
-use super::blacklist::TokenBlacklist;
+use crate::config::BlacklistFailurePolicy;
+use crate::error::ApiError;
+use crate::telemetry;
+use super::blacklist::BlacklistResult;
+use super::keys::JwtKeys;
+
+/// How long a login token is valid, and so how long a revocation has to be kept
+pub const TOKEN_LIFETIME_SECONDS: i64 = 24 * 60 * 60;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
@@ -23,7 +29,7 @@ pub struct Claims {
 impl Claims {
     pub fn new(user_id: String, email: String, role: Role) -> Self {
         let now = Utc::now();
-        let expires_at = now + Duration::hours(24);
+        let expires_at = now + Duration::seconds(TOKEN_LIFETIME_SECONDS);
 
         Self {
             sub: user_id,
@@ -35,64 +41,42 @@ impl Claims {
     }
 }
 
-pub fn generate_token(user_id: String, email: String, role: Role) -> Result<String, jsonwebtoken::errors::Error> {
+pub fn generate_token(keys: &JwtKeys, user_id: String, email: String, role: Role) -> Result<String, jsonwebtoken::errors::Error> {
     let claims = Claims::new(user_id, email, role);
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "default-insecure-secret".to_string());
-
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    )
-}
-
-pub fn decode_token(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "default-insecure-secret".to_string());
 
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &Validation::default(),
-    )?;
-
-    Ok(token_data.claims)
+    keys.encode(&claims)
 }
 
-// Custom error type for JWT authentication
-#[derive(Debug)]
-pub enum JwtError {
-    Missing,
-    Invalid,
-    Expired,
+pub fn decode_token(keys: &JwtKeys, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+    keys.decode(token)
 }
 
-impl fmt::Display for JwtError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            JwtError::Missing => write!(f, "Missing authorization token"),
-            JwtError::Invalid => write!(f, "Invalid token"),
-            JwtError::Expired => write!(f, "Token expired"),
+/// Record a blacklist lookup, applying the failure policy if Redis couldn't answer
+fn blacklist_hit(
+    result: BlacklistResult<bool>,
+    policy: BlacklistFailurePolicy,
+    local_hit: impl FnOnce() -> bool,
+) -> Result<bool, ApiError> {
+    let err = match result {
+        Ok(hit) => {
+            metrics::counter!("blacklist_checks_total", "result" => if hit { "hit" } else { "miss" }).increment(1);
+            return Ok(hit);
         }
-    }
-}
+        Err(err) => err,
+    };
 
-impl ResponseError for JwtError {
-    fn error_response(&self) -> HttpResponse {
-        match self {
-            JwtError::Missing => HttpResponse::Unauthorized().json(serde_json::json!({
-                "success": false,
-                "message": "Missing authorization token"
-            })),
-            JwtError::Invalid => HttpResponse::Unauthorized().json(serde_json::json!({
-                "success": false,
-                "message": "Invalid token"
-            })),
-            JwtError::Expired => HttpResponse::Unauthorized().json(serde_json::json!({
-                "success": false,
-                "message": "Token expired"
-            })),
-        }
-    }
+    telemetry::record_redis_error();
+    metrics::counter!("blacklist_checks_total", "result" => "error").increment(1);
+
+    let (label, verdict) = match policy {
+        BlacklistFailurePolicy::Open => ("open", Ok(false)),
+        BlacklistFailurePolicy::Closed => ("closed", Err(ApiError::ServiceUnavailable(serde_json::json!({ "dependency": "redis" })))),
+        BlacklistFailurePolicy::Local => ("local", Ok(local_hit())),
+    };
+    metrics::counter!("blacklist_degraded_total", "policy" => label).increment(1);
+    warn!("Token blacklist unavailable, failing {}: {}", label, err);
+
+    verdict
 }
 
 // Actix-web extractor for JWT claims
@@ -109,13 +93,17 @@ impl Deref for JwtClaims {
 }
 
 impl FromRequest for JwtClaims {
-    type Error = JwtError;
+    type Error = ApiError;
     type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
 
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
         let req = req.clone();
 
         Box::pin(async move {
+            let Some(app_state) = req.app_data::<web::Data<AppState>>() else {
+                return Err(ApiError::Internal);
+            };
+
             // Extract token from Authorization header
             let auth_header = req.headers().get("Authorization");
 
@@ -124,46 +112,122 @@ impl FromRequest for JwtClaims {
                     match header_value.to_str() {
                         Ok(header_str) => {
                             // Support both "Bearer <token>" and just "<token>"
-                            if header_str.starts_with("Bearer ") {
-                                header_str[7..].to_string()
-                            } else {
-                                header_str.to_string()
-                            }
+                            header_str.strip_prefix("Bearer ").unwrap_or(header_str).to_string()
                         }
-                        Err(_) => return Err(JwtError::Invalid),
+                        Err(_) => return Err(ApiError::TokenInvalid),
                     }
                 }
-                None => return Err(JwtError::Missing),
+                None => return Err(ApiError::TokenMissing),
             };
 
             // Decode and validate the token
-            let claims = match decode_token(&token) {
+            let claims = match decode_token(&app_state.jwt_keys, &token) {
                 Ok(claims) => claims,
                 Err(err) => {
                     use jsonwebtoken::errors::ErrorKind;
                     return Err(match err.kind() {
-                        ErrorKind::ExpiredSignature => JwtError::Expired,
-                        _ => JwtError::Invalid,
+                        ErrorKind::ExpiredSignature => ApiError::TokenExpired,
+                        _ => ApiError::TokenInvalid,
                     });
                 }
             };
 
-            // Check blacklist if Redis is available
-            if let Some(app_state) = req.app_data::<web::Data<AppState>>() {
-                let blacklist = TokenBlacklist::new(app_state.redis_client.clone());
+            let revocations = &app_state.revocations;
+            let policy = app_state.config.auth.blacklist_failure_policy;
+            let now = Utc::now().timestamp();
 
-                // Check if specific token is blacklisted
-                if blacklist.is_token_blacklisted(&token).await.unwrap_or(false) {
-                    return Err(JwtError::Invalid);
+            // Reject the token if it was blacklisted itself, or issued before the user's
+            // role change (or other invalidation event). Both are checked in one lookup.
+            let check = app_state.blacklist.check(&token, &claims.sub).await.map(|(token_blacklisted, user_before)| {
+                // Remember what Redis knows locally in case it goes away
+                if token_blacklisted {
+                    revocations.revoke_token(&token, claims.exp - now);
                 }
-
-                // Check if token was issued before user's role change (or other invalidation event)
-                if blacklist.is_user_token_blacklisted(&claims.sub, claims.iat).await.unwrap_or(false) {
-                    return Err(JwtError::Invalid);
+                if let Some(before) = user_before {
+                    revocations.revoke_user_before(&claims.sub, before, before + TOKEN_LIFETIME_SECONDS - now);
                 }
+                token_blacklisted || user_before.is_some_and(|before| claims.iat < before)
+            });
+            let local_hit = || revocations.is_token_revoked(&token) || revocations.is_user_token_revoked(&claims.sub, claims.iat);
+            if blacklist_hit(check, policy, local_hit)? {
+                return Err(ApiError::TokenInvalid);
             }
 
+            tracing::Span::current().record("user_id", claims.sub.as_str());
+
             Ok(JwtClaims(claims))
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, test, web};
+    use std::sync::Arc;
+
+    use crate::AppState;
+    use crate::auth::blacklist::UnreachableBlacklist;
+    use crate::config::{BlacklistFailurePolicy, Config};
+    use crate::handlers::account::Role;
+    use crate::repository::MemoryRepository;
+    use super::{JwtClaims, generate_token};
+
+    /// A server whose blacklist can't be reached
+    fn unreachable_blacklist(policy: BlacklistFailurePolicy) -> web::Data<AppState> {
+        let repository = Arc::new(MemoryRepository::new());
+        repository.add_user("user-1", "player@example.com", "hash", Role::User, 1);
+
+        let mut config = Config::default();
+        config.auth.blacklist_failure_policy = policy;
+        let mut state = AppState::in_memory(config, repository).unwrap();
+        state.blacklist = Arc::new(UnreachableBlacklist);
+        web::Data::new(state)
+    }
+
+    /// Status of a request authenticated with `token`
+    async fn status(state: &web::Data<AppState>, token: &str) -> u16 {
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/", web::get().to(|claims: JwtClaims| async move { claims.sub.clone() })),
+        )
+        .await;
+        let request = test::TestRequest::get().uri("/").insert_header(("Authorization", format!("Bearer {}", token)));
+        test::call_service(&app, request.to_request()).await.status().as_u16()
+    }
+
+    fn user_token(state: &AppState) -> String {
+        generate_token(&state.jwt_keys, "user-1".to_string(), "player@example.com".to_string(), Role::User).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn open_lets_tokens_through() {
+        let state = unreachable_blacklist(BlacklistFailurePolicy::Open);
+        let token = user_token(&state);
+
+        // Even one this instance revoked
+        state.revocations.revoke_token(&token, 60);
+        assert_eq!(status(&state, &token).await, 200);
+    }
+
+    #[actix_web::test]
+    async fn closed_refuses_every_token() {
+        let state = unreachable_blacklist(BlacklistFailurePolicy::Closed);
+        assert_eq!(status(&state, &user_token(&state)).await, 503);
+    }
+
+    #[actix_web::test]
+    async fn local_checks_the_revocations_seen_here() {
+        let state = unreachable_blacklist(BlacklistFailurePolicy::Local);
+        let token = user_token(&state);
+        assert_eq!(status(&state, &token).await, 200);
+
+        state.revocations.revoke_token(&token, 60);
+        assert_eq!(status(&state, &token).await, 401);
+
+        // Tokens issued before a role change made here
+        let state = unreachable_blacklist(BlacklistFailurePolicy::Local);
+        state.revocations.revoke_user_before("user-1", chrono::Utc::now().timestamp() + 1, 60);
+        assert_eq!(status(&state, &user_token(&state)).await, 401);
+    }
+}
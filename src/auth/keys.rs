@@ -0,0 +1,290 @@
+use base64::Engine;
+use chrono::Utc;
+use base64::engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD as BASE64_URL};
+use ed25519_dalek::SigningKey;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, decode_header, encode};
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::traits::PublicKeyParts;
+use rsa::RsaPrivateKey;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use utoipa::ToSchema;
+use std::collections::HashMap;
+use std::fmt;
+use tracing::warn;
+
+use crate::config::{AuthConfig, JwtAlgorithm, JwtKeyConfig};
+
+/// PKCS#8 v1 header for a raw 32 byte Ed25519 seed
+const ED25519_PKCS8_PREFIX: [u8; 16] = [
+    0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+
+#[derive(Debug)]
+pub enum JwtKeyError {
+    InvalidKey(String, String),
+    DuplicateKid(String),
+}
+
+impl fmt::Display for JwtKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JwtKeyError::InvalidKey(kid, reason) => write!(f, "JWT key '{}' is invalid: {}", kid, reason),
+            JwtKeyError::DuplicateKid(kid) => write!(f, "JWT key id '{}' is configured more than once", kid),
+        }
+    }
+}
+
+impl std::error::Error for JwtKeyError {}
+
+/// Public half of an asymmetric JWT key, as published in the JWKS
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Jwk {
+    pub kty: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crv: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+    pub kid: String,
+    pub alg: &'static str,
+    #[serde(rename = "use")]
+    pub key_use: &'static str,
+}
+
+#[derive(Clone)]
+struct VerificationKey {
+    algorithm: Algorithm,
+    key: DecodingKey,
+    jwk: Option<Jwk>,
+}
+
+/// Signs and verifies login JWTs
+///
+/// Keys come from `auth.jwt_keys`; the first one signs new tokens and every configured key
+/// verifies, selected by the `kid` header. To rotate, prepend a new key and drop the old one
+/// once the tokens it signed have expired (24h). Without keys, tokens carry no `kid` and are
+/// signed with `auth.jwt_secret`. Once keys are configured, those are only accepted until
+/// `auth.legacy_tokens_until`, so holding the secret no longer lets anyone mint tokens.
+#[derive(Clone)]
+pub struct JwtKeys {
+    active_kid: Option<String>,
+    encoding_key: EncodingKey,
+    algorithm: Algorithm,
+    verification: HashMap<String, VerificationKey>,
+    legacy: DecodingKey,
+    /// Unix timestamp, `None` refuses tokens without a `kid` once keys are configured
+    legacy_until: Option<i64>,
+}
+
+impl JwtKeys {
+    pub fn from_config(config: &AuthConfig) -> Result<Self, JwtKeyError> {
+        let legacy = DecodingKey::from_secret(config.jwt_secret.as_bytes());
+        let legacy_until = config.legacy_tokens_until.map(|until| until.timestamp());
+
+        let Some(first) = config.jwt_keys.first() else {
+            return Ok(Self {
+                active_kid: None,
+                encoding_key: EncodingKey::from_secret(config.jwt_secret.as_bytes()),
+                algorithm: Algorithm::HS256,
+                verification: HashMap::new(),
+                legacy,
+                legacy_until,
+            });
+        };
+
+        let mut verification = HashMap::new();
+        let mut encoding_key = None;
+        for key in &config.jwt_keys {
+            let (encoding, verifying) = load_key(key)?;
+            if verification.insert(key.kid.clone(), verifying).is_some() {
+                return Err(JwtKeyError::DuplicateKid(key.kid.clone()));
+            }
+            encoding_key.get_or_insert(encoding);
+        }
+
+        Ok(Self {
+            active_kid: Some(first.kid.clone()),
+            encoding_key: encoding_key.expect("at least one key was loaded"),
+            algorithm: algorithm(first.algorithm),
+            verification,
+            legacy,
+            legacy_until,
+        })
+    }
+
+    /// Sign claims with the active key
+    pub fn encode<T: Serialize>(&self, claims: &T) -> Result<String, jsonwebtoken::errors::Error> {
+        let mut header = Header::new(self.algorithm);
+        header.kid = self.active_kid.clone();
+
+        encode(&header, claims, &self.encoding_key)
+    }
+
+    /// Verify a token with the key named in its `kid` header
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<T, jsonwebtoken::errors::Error> {
+        use jsonwebtoken::errors::ErrorKind;
+
+        let header = decode_header(token)?;
+        let (algorithm, key) = match &header.kid {
+            Some(kid) => {
+                let key = self.verification.get(kid).ok_or(ErrorKind::InvalidToken)?;
+                (key.algorithm, &key.key)
+            }
+            // Without keys every token is signed with the secret and carries no kid
+            None if self.active_kid.is_none() => (Algorithm::HS256, &self.legacy),
+            None => {
+                if self.legacy_until.is_none_or(|until| Utc::now().timestamp() >= until) {
+                    return Err(ErrorKind::InvalidToken.into());
+                }
+                warn!("Verifying a token without a kid against the legacy secret");
+                metrics::counter!("jwt_legacy_verifications_total").increment(1);
+                (Algorithm::HS256, &self.legacy)
+            }
+        };
+
+        // The algorithm is pinned by the key, never taken from the token header
+        Ok(decode::<T>(token, key, &Validation::new(algorithm))?.claims)
+    }
+
+    /// Public keys for the JWKS endpoint, shared secrets are never published
+    pub fn jwks(&self) -> Vec<Jwk> {
+        let mut keys: Vec<Jwk> = self.verification.values().filter_map(|key| key.jwk.clone()).collect();
+        keys.sort_by(|a, b| a.kid.cmp(&b.kid));
+        keys
+    }
+}
+
+fn algorithm(algorithm: JwtAlgorithm) -> Algorithm {
+    match algorithm {
+        JwtAlgorithm::HS256 => Algorithm::HS256,
+        JwtAlgorithm::RS256 => Algorithm::RS256,
+        JwtAlgorithm::EdDSA => Algorithm::EdDSA,
+    }
+}
+
+fn load_key(config: &JwtKeyConfig) -> Result<(EncodingKey, VerificationKey), JwtKeyError> {
+    let invalid = |reason: String| JwtKeyError::InvalidKey(config.kid.clone(), reason);
+
+    match config.algorithm {
+        JwtAlgorithm::HS256 => Ok((
+            EncodingKey::from_secret(config.key.as_bytes()),
+            VerificationKey {
+                algorithm: Algorithm::HS256,
+                key: DecodingKey::from_secret(config.key.as_bytes()),
+                jwk: None,
+            },
+        )),
+        JwtAlgorithm::EdDSA => {
+            let seed: [u8; 32] = BASE64
+                .decode(&config.key)
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| invalid("expected a base64 encoded 32 byte Ed25519 seed".to_string()))?;
+
+            let der = [ED25519_PKCS8_PREFIX.as_slice(), seed.as_slice()].concat();
+            let x = BASE64_URL.encode(SigningKey::from_bytes(&seed).verifying_key().as_bytes());
+            let key = DecodingKey::from_ed_components(&x).map_err(|err| invalid(err.to_string()))?;
+
+            Ok((
+                EncodingKey::from_ed_der(&der),
+                VerificationKey {
+                    algorithm: Algorithm::EdDSA,
+                    key,
+                    jwk: Some(Jwk {
+                        kty: "OKP",
+                        crv: Some("Ed25519"),
+                        x: Some(x),
+                        n: None,
+                        e: None,
+                        kid: config.kid.clone(),
+                        alg: "EdDSA",
+                        key_use: "sig",
+                    }),
+                },
+            ))
+        }
+        JwtAlgorithm::RS256 => {
+            let pem = std::fs::read_to_string(&config.key)
+                .map_err(|err| invalid(format!("failed to read {}: {}", config.key, err)))?;
+            let private_key = RsaPrivateKey::from_pkcs8_pem(&pem)
+                .or_else(|_| RsaPrivateKey::from_pkcs1_pem(&pem))
+                .map_err(|_| invalid("expected an RSA private key in PKCS#8 or PKCS#1 PEM".to_string()))?;
+
+            let n = BASE64_URL.encode(private_key.n().to_bytes_be());
+            let e = BASE64_URL.encode(private_key.e().to_bytes_be());
+            let encoding = EncodingKey::from_rsa_pem(pem.as_bytes()).map_err(|err| invalid(err.to_string()))?;
+            let key = DecodingKey::from_rsa_components(&n, &e).map_err(|err| invalid(err.to_string()))?;
+
+            Ok((
+                encoding,
+                VerificationKey {
+                    algorithm: Algorithm::RS256,
+                    key,
+                    jwk: Some(Jwk {
+                        kty: "RSA",
+                        crv: None,
+                        x: None,
+                        n: Some(n),
+                        e: Some(e),
+                        kid: config.kid.clone(),
+                        alg: "RS256",
+                        key_use: "sig",
+                    }),
+                },
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use crate::auth::jwt::Claims;
+    use crate::handlers::account::Role;
+
+    fn config(legacy_tokens_until: Option<chrono::DateTime<Utc>>) -> AuthConfig {
+        AuthConfig {
+            jwt_secret: "legacy-secret".to_string(),
+            jwt_keys: vec![JwtKeyConfig {
+                kid: "2026-10".to_string(),
+                algorithm: JwtAlgorithm::EdDSA,
+                key: BASE64.encode([7u8; 32]),
+            }],
+            legacy_tokens_until,
+            ..AuthConfig::default()
+        }
+    }
+
+    fn legacy_token() -> String {
+        let claims = Claims::new("user-1".to_string(), "player@example.com".to_string(), Role::User);
+        encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(b"legacy-secret")).unwrap()
+    }
+
+    #[test]
+    fn tokens_without_a_kid_are_refused_once_keys_are_configured() {
+        let keys = JwtKeys::from_config(&config(None)).unwrap();
+        assert!(keys.decode::<Claims>(&legacy_token()).is_err());
+
+        let expired = JwtKeys::from_config(&config(Some(Utc::now() - Duration::hours(1)))).unwrap();
+        assert!(expired.decode::<Claims>(&legacy_token()).is_err());
+
+        let token = keys.encode(&Claims::new("user-1".to_string(), "player@example.com".to_string(), Role::User)).unwrap();
+        assert_eq!(keys.decode::<Claims>(&token).unwrap().sub, "user-1");
+    }
+
+    #[test]
+    fn legacy_tokens_are_accepted_until_the_cutoff() {
+        let keys = JwtKeys::from_config(&config(Some(Utc::now() + Duration::hours(1)))).unwrap();
+        assert_eq!(keys.decode::<Claims>(&legacy_token()).unwrap().sub, "user-1");
+
+        // Without keys the secret is the only key there is
+        let secret_only = JwtKeys::from_config(&AuthConfig { jwt_secret: "legacy-secret".to_string(), ..AuthConfig::default() }).unwrap();
+        assert_eq!(secret_only.decode::<Claims>(&legacy_token()).unwrap().sub, "user-1");
+    }
+}
@@ -1,6 +1,22 @@
 pub mod jwt;
+pub mod keys;
 pub mod blacklist;
+pub mod signing;
+pub mod challenge;
+pub mod oauth;
+pub mod oauth_state;
+pub mod session;
+pub mod fingerprint;
+pub mod revocations;
 
 // Re-export commonly used items
-pub use jwt::{Claims, JwtClaims, JwtError, decode_token, generate_token};
-pub use blacklist::TokenBlacklist;
+pub use jwt::JwtClaims;
+pub use blacklist::{MemoryBlacklist, RedisBlacklist, TokenBlacklist};
+pub use keys::JwtKeys;
+pub use signing::{AuthSignature, ResponseSigner, SignedClaims};
+pub use fingerprint::{FingerprintMatcher, HwidComponents};
+pub use revocations::LocalRevocations;
+pub use challenge::{ChallengeStore, MemoryChallenges, RedisChallenges};
+pub use oauth::{OAuthError, OAuthProviders};
+pub use oauth_state::{MemoryOAuthStates, OAuthIntent, OAuthStateStore, RedisOAuthStates};
+pub use session::{MemorySessions, RedisSessions, SessionStore};
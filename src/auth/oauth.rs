@@ -0,0 +1,362 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+pub use authit_types::LinkedAccount;
+
+use crate::config::{OAuthConfig, OAuthProviderConfig};
+use crate::repository::ExternalAccount;
+
+#[derive(Debug)]
+pub enum OAuthError {
+    /// The provider couldn't be reached or sent something that isn't JSON
+    Http(reqwest::Error),
+    /// The provider answered with an error, e.g. an expired or reused code
+    Rejected(String),
+    /// The userinfo response has no usable `id_field`
+    MissingId(String),
+    InvalidUrl(String),
+}
+
+impl fmt::Display for OAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OAuthError::Http(err) => write!(f, "{}", err),
+            OAuthError::Rejected(reason) => write!(f, "provider rejected the request: {}", reason),
+            OAuthError::MissingId(field) => write!(f, "userinfo response has no '{}'", field),
+            OAuthError::InvalidUrl(url) => write!(f, "invalid URL '{}'", url),
+        }
+    }
+}
+
+impl From<reqwest::Error> for OAuthError {
+    fn from(err: reqwest::Error) -> Self {
+        OAuthError::Http(err)
+    }
+}
+
+/// Whether `url` points back at the machine the browser runs on, the only place flows hand their result to
+///
+/// A flow started by someone else and finished in a victim's browser then ends on the victim's machine,
+/// so its exchange code never reaches whoever started it.
+pub fn is_loopback(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|url| {
+        url.scheme() == "http"
+            && matches!(url.host_str(), Some("127.0.0.1" | "[::1]" | "localhost"))
+            && url.username().is_empty()
+            && url.password().is_none()
+    })
+}
+
+/// PKCE's S256 check (RFC 7636): the challenge is the base64url SHA-256 of the verifier
+pub fn verifies(code_challenge: &str, code_verifier: &str) -> bool {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes())) == code_challenge
+}
+
+/// `return_to` with `params` added to its query
+pub fn return_url(return_to: &str, params: &[(&str, &str)]) -> Result<String, OAuthError> {
+    let mut url = reqwest::Url::parse(return_to).map_err(|_| OAuthError::InvalidUrl(return_to.to_string()))?;
+    url.query_pairs_mut().extend_pairs(params);
+
+    Ok(url.into())
+}
+
+/// The configured OAuth2 providers and the HTTP client used to talk to them
+pub struct OAuthProviders {
+    providers: HashMap<String, OAuthProviderConfig>,
+    http: reqwest::Client,
+}
+
+impl OAuthProviders {
+    pub fn new(config: &OAuthConfig) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .user_agent(concat!("authit/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("OAuth HTTP client");
+
+        Self { providers: config.providers.clone(), http }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&OAuthProviderConfig> {
+        self.providers.get(name)
+    }
+
+    /// Where the user signs in and approves, the provider then redirects to `redirect_uri` with a code
+    pub fn authorize_url(&self, provider: &OAuthProviderConfig, state: &str) -> Result<String, OAuthError> {
+        let scope = provider.scopes.join(" ");
+        reqwest::Url::parse_with_params(&provider.authorize_url, [
+            ("response_type", "code"),
+            ("client_id", provider.client_id.as_str()),
+            ("redirect_uri", provider.redirect_uri.as_str()),
+            ("scope", scope.as_str()),
+            ("state", state),
+        ])
+        .map(String::from)
+        .map_err(|_| OAuthError::InvalidUrl(provider.authorize_url.clone()))
+    }
+
+    /// Trade the callback's code for an access token and ask the provider whose account it is
+    pub async fn identify(&self, name: &str, provider: &OAuthProviderConfig, code: &str) -> Result<ExternalAccount, OAuthError> {
+        let response = self
+            .http
+            .post(&provider.token_url)
+            .header("Accept", "application/json")
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", provider.redirect_uri.as_str()),
+                ("client_id", provider.client_id.as_str()),
+                ("client_secret", provider.client_secret.as_str()),
+            ])
+            .send()
+            .await?;
+        let status = response.status();
+        let token: serde_json::Value = response.json().await?;
+        let Some(access_token) = token.get("access_token").and_then(|token| token.as_str()).filter(|_| status.is_success()) else {
+            let reason = token.get("error").and_then(|error| error.as_str()).unwrap_or("no access_token");
+            return Err(OAuthError::Rejected(format!("{} ({})", reason, status)));
+        };
+
+        let response = self
+            .http
+            .get(&provider.userinfo_url)
+            .header("Accept", "application/json")
+            .bearer_auth(access_token)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(OAuthError::Rejected(format!("userinfo returned {}", response.status())));
+        }
+        let userinfo: serde_json::Value = response.json().await?;
+
+        // Some providers send numeric ids
+        let external_id = match userinfo.get(&provider.id_field) {
+            Some(serde_json::Value::String(id)) if !id.is_empty() => id.clone(),
+            Some(serde_json::Value::Number(id)) => id.to_string(),
+            _ => return Err(OAuthError::MissingId(provider.id_field.clone())),
+        };
+        let username = provider
+            .username_field
+            .as_ref()
+            .and_then(|field| userinfo.get(field))
+            .and_then(|username| username.as_str())
+            .map(str::to_string);
+
+        Ok(ExternalAccount { provider: name.to_string(), external_id, username })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{HttpRequest, HttpResponse, HttpServer, test, web};
+    use serde_json::{Value, json};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    use crate::AppState;
+    use crate::auth::jwt;
+    use crate::config::{Config, OAuthProviderConfig};
+    use crate::handlers::account::Role;
+    use crate::repository::MemoryRepository;
+    use super::{Digest, Engine};
+
+    /// A provider accepting codes `code-<id>` and describing account `<id>`, anything else is an invalid grant
+    fn mock_provider() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let server = HttpServer::new(|| {
+            actix_web::App::new()
+                .route("/token", web::post().to(|form: web::Form<std::collections::HashMap<String, String>>| async move {
+                    match form.get("code").and_then(|code| code.strip_prefix("code-")) {
+                        Some(id) if form.get("client_secret").map(String::as_str) == Some("mock-secret") => {
+                            HttpResponse::Ok().json(json!({ "access_token": format!("token-{}", id), "token_type": "Bearer" }))
+                        }
+                        _ => HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" })),
+                    }
+                }))
+                .route("/userinfo", web::get().to(|req: HttpRequest| async move {
+                    let authorization = req.headers().get("Authorization").and_then(|value| value.to_str().ok()).unwrap_or("");
+                    match authorization.strip_prefix("Bearer token-") {
+                        Some(id) => HttpResponse::Ok().json(json!({ "id": id.parse::<u64>().unwrap(), "username": format!("player{}", id) })),
+                        None => HttpResponse::Unauthorized().finish(),
+                    }
+                }))
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+
+        url
+    }
+
+    fn state(provider_url: &str) -> web::Data<AppState> {
+        let repository = Arc::new(MemoryRepository::new());
+        repository.add_user("user-1", "player@example.com", "hash", Role::User, 1);
+        repository.add_user("user-2", "other@example.com", "hash", Role::User, 1);
+
+        let mut config = Config::default();
+        config.oauth.providers.insert("mock".to_string(), OAuthProviderConfig {
+            client_id: "mock-client".to_string(),
+            client_secret: "mock-secret".to_string(),
+            authorize_url: format!("{}/authorize", provider_url),
+            token_url: format!("{}/token", provider_url),
+            userinfo_url: format!("{}/userinfo", provider_url),
+            redirect_uri: "http://localhost/api/v1/oauth/mock/callback".to_string(),
+            scopes: vec!["identify".to_string(), "email".to_string()],
+            allow_login: true,
+            ..OAuthProviderConfig::default()
+        });
+
+        web::Data::new(AppState::in_memory(config, repository).unwrap())
+    }
+
+    fn bearer(state: &AppState, user_id: &str) -> (&'static str, String) {
+        let token = jwt::generate_token(&state.jwt_keys, user_id.to_string(), format!("{}@example.com", user_id), Role::User).unwrap();
+        ("Authorization", format!("Bearer {}", token))
+    }
+
+    macro_rules! call {
+        ($app:expr, $request:expr) => {{
+            let response = test::call_service(&$app, $request.to_request()).await;
+            let status = response.status().as_u16();
+            let body: Value = test::read_body_json(response).await;
+            (status, body)
+        }};
+    }
+
+    const VERIFIER: &str = "loader-verifier-loader-verifier-loader-verifier";
+    const RETURN_TO: &str = "http://127.0.0.1:53682/done";
+
+    fn start(path: &str) -> test::TestRequest {
+        let challenge = super::URL_SAFE_NO_PAD.encode(super::Sha256::digest(VERIFIER.as_bytes()));
+        test::TestRequest::post().uri(path).set_json(json!({ "return_to": RETURN_TO, "code_challenge": challenge }))
+    }
+
+    /// Where the callback sent the browser, and its query
+    fn redirect<B>(response: &actix_web::dev::ServiceResponse<B>) -> (String, std::collections::HashMap<String, String>) {
+        assert_eq!(response.status().as_u16(), 302);
+        let location = reqwest::Url::parse(response.headers().get("Location").unwrap().to_str().unwrap()).unwrap();
+        let query = location.query_pairs().into_owned().collect();
+        (format!("{}://{}{}", location.scheme(), location.authority(), location.path()), query)
+    }
+
+    fn exchange(code: &str, verifier: &str) -> test::TestRequest {
+        test::TestRequest::post().uri("/api/v1/oauth/mock/exchange").set_json(json!({ "code": code, "code_verifier": verifier }))
+    }
+
+    #[actix_web::test]
+    async fn link_then_log_in_with_the_provider() {
+        let state = state(&mock_provider());
+        let app = test::init_service(crate::app(state.clone(), false)).await;
+
+        let (status, body) = call!(app, start("/api/v1/oauth/mock/link").insert_header(bearer(&state, "user-1")));
+        assert_eq!(status, 200, "{}", body);
+        let authorize_url = body["authorize_url"].as_str().unwrap();
+        let link_state = body["state"].as_str().unwrap().to_string();
+        assert!(authorize_url.contains("client_id=mock-client") && authorize_url.contains("scope=identify+email"), "{}", authorize_url);
+
+        // The callback only hands a code back to the loader, nothing is linked yet
+        let callback = format!("/api/v1/oauth/mock/callback?code=code-42&state={}", link_state);
+        let response = test::call_service(&app, test::TestRequest::get().uri(&callback).to_request()).await;
+        let (location, query) = redirect(&response);
+        assert_eq!(location, RETURN_TO);
+        assert!(state.repos.identities.for_user("user-1").await.unwrap().is_empty());
+
+        // The state is single use
+        let (status, body) = call!(app, test::TestRequest::get().uri(&callback));
+        assert_eq!((status, body["code"].as_str()), (400, Some("OAUTH_STATE_INVALID")));
+        // And isn't an exchange code
+        let (_, body) = call!(app, exchange(&link_state, VERIFIER));
+        assert_eq!(body["code"], "OAUTH_STATE_INVALID");
+
+        let (status, body) = call!(app, exchange(&query["code"], VERIFIER));
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["account"]["external_id"], "42");
+        assert_eq!(body["account"]["username"], "player42");
+        assert!(body["token"].is_null());
+        let (_, body) = call!(app, exchange(&query["code"], VERIFIER));
+        assert_eq!(body["code"], "OAUTH_STATE_INVALID");
+
+        let (_, body) = call!(app, start("/api/v1/oauth/mock/login"));
+        let login = format!("/api/v1/oauth/mock/callback?code=code-42&state={}", body["state"].as_str().unwrap());
+        let response = test::call_service(&app, test::TestRequest::get().uri(&login).to_request()).await;
+        let (_, query) = redirect(&response);
+        let (status, body) = call!(app, exchange(&query["code"], VERIFIER));
+        assert_eq!(status, 200, "{}", body);
+        let claims: jwt::Claims = state.jwt_keys.decode(body["token"].as_str().unwrap()).unwrap();
+        assert_eq!(claims.sub, "user-1");
+
+        let (_, body) = call!(app, test::TestRequest::get().uri("/api/v1/account/links").insert_header(bearer(&state, "user-1")));
+        assert_eq!(body["accounts"][0]["provider"], "mock");
+    }
+
+    #[actix_web::test]
+    async fn only_the_loader_that_started_a_flow_can_finish_it() {
+        let state = state(&mock_provider());
+        let app = test::init_service(crate::app(state.clone(), false)).await;
+
+        // Results only go back to the machine the browser runs on
+        for return_to in ["https://attacker.example/done", "http://127.0.0.1.attacker.example/", "http://user@localhost/"] {
+            let request = test::TestRequest::post()
+                .uri("/api/v1/oauth/mock/link")
+                .insert_header(bearer(&state, "user-2"))
+                .set_json(json!({ "return_to": return_to, "code_challenge": "x".repeat(43) }));
+            let (status, body) = call!(app, request);
+            assert_eq!((status, body["code"].as_str()), (400, Some("INVALID_REQUEST")), "{}", return_to);
+        }
+
+        // Someone who only learned the code (e.g. from the victim's browser history) lacks the verifier
+        let (_, body) = call!(app, start("/api/v1/oauth/mock/link").insert_header(bearer(&state, "user-2")));
+        let callback = format!("/api/v1/oauth/mock/callback?code=code-9&state={}", body["state"].as_str().unwrap());
+        let response = test::call_service(&app, test::TestRequest::get().uri(&callback).to_request()).await;
+        let (_, query) = redirect(&response);
+        let (_, body) = call!(app, exchange(&query["code"], "guessed-verifier"));
+        assert_eq!(body["code"], "OAUTH_STATE_INVALID");
+        assert!(state.repos.identities.for_user("user-2").await.unwrap().is_empty());
+
+        // A declined sign-in goes back to the loader as an error
+        let (_, body) = call!(app, start("/api/v1/oauth/mock/login"));
+        let callback = format!("/api/v1/oauth/mock/callback?error=access_denied&state={}", body["state"].as_str().unwrap());
+        let response = test::call_service(&app, test::TestRequest::get().uri(&callback).to_request()).await;
+        let (_, query) = redirect(&response);
+        assert_eq!(query["error"], "OAUTH_PROVIDER_ERROR");
+    }
+
+    #[actix_web::test]
+    async fn accounts_belong_to_one_user() {
+        let state = state(&mock_provider());
+        let app = test::init_service(crate::app(state.clone(), false)).await;
+
+        for (user_id, expected) in [("user-1", 200), ("user-2", 409)] {
+            let (_, body) = call!(app, start("/api/v1/oauth/mock/link").insert_header(bearer(&state, user_id)));
+            let callback = format!("/api/v1/oauth/mock/callback?code=code-7&state={}", body["state"].as_str().unwrap());
+            let response = test::call_service(&app, test::TestRequest::get().uri(&callback).to_request()).await;
+            let (_, query) = redirect(&response);
+            let (status, body) = call!(app, exchange(&query["code"], VERIFIER));
+            assert_eq!(status, expected, "{}", body);
+        }
+
+        // Nobody linked account 8, and a code the provider rejects is its error
+        let (_, body) = call!(app, start("/api/v1/oauth/mock/login"));
+        let callback = format!("/api/v1/oauth/mock/callback?code=code-8&state={}", body["state"].as_str().unwrap());
+        let response = test::call_service(&app, test::TestRequest::get().uri(&callback).to_request()).await;
+        let (_, query) = redirect(&response);
+        assert_eq!(call!(app, exchange(&query["code"], VERIFIER)).1["code"], "ACCOUNT_NOT_LINKED");
+
+        let (_, body) = call!(app, start("/api/v1/oauth/mock/login"));
+        let callback = format!("/api/v1/oauth/mock/callback?code=bogus&state={}", body["state"].as_str().unwrap());
+        let response = test::call_service(&app, test::TestRequest::get().uri(&callback).to_request()).await;
+        assert_eq!(redirect(&response).1["error"], "OAUTH_PROVIDER_ERROR");
+
+        let unlink = || test::TestRequest::delete().uri("/api/v1/account/links/mock").insert_header(bearer(&state, "user-1"));
+        assert_eq!(call!(app, unlink()).0, 200);
+        assert_eq!(call!(app, unlink()).1["code"], "LINK_NOT_FOUND");
+    }
+}
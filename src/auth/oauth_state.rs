@@ -0,0 +1,111 @@
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::Utc;
+use parking_lot::Mutex;
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::repository::ExternalAccount;
+
+/// How long the user has to finish signing in at the provider
+pub const OAUTH_STATE_TTL_SECONDS: u64 = 600;
+
+/// What an OAuth flow was started for, looked up again by its `state` on the callback
+/// and by the exchange code the callback hands to `return_to`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OAuthIntent {
+    pub provider: String,
+    /// The user linking their account, `None` when logging in
+    pub user_id: Option<String>,
+    /// The starting loader's loopback URL
+    pub return_to: String,
+    pub code_challenge: String,
+    /// Set once the callback identified the account, only then can the flow be exchanged
+    #[serde(default)]
+    pub account: Option<ExternalAccount>,
+}
+
+fn new_state() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+}
+
+/// Single-use `state` values for the OAuth callback and exchange codes after it, they tie both to the flow this server started
+#[async_trait]
+pub trait OAuthStateStore: Send + Sync {
+    /// Issue a fresh state (or exchange code) for `intent`, valid for `OAUTH_STATE_TTL_SECONDS`
+    async fn issue(&self, intent: &OAuthIntent) -> Result<String, redis::RedisError>;
+
+    /// Consume a state, `None` if it is unknown, expired or already used
+    async fn take(&self, state: &str) -> Result<Option<OAuthIntent>, redis::RedisError>;
+}
+
+/// Shared by every instance, the callback may hit a different one than the flow started on
+#[derive(Clone)]
+pub struct RedisOAuthStates {
+    redis: ConnectionManager,
+}
+
+impl RedisOAuthStates {
+    pub fn new(redis: ConnectionManager) -> Self {
+        Self { redis }
+    }
+}
+
+#[async_trait]
+impl OAuthStateStore for RedisOAuthStates {
+    async fn issue(&self, intent: &OAuthIntent) -> Result<String, redis::RedisError> {
+        let mut conn = self.redis.clone();
+        let state = new_state();
+        let key = format!("oauth_state:{}", state);
+        let value = serde_json::to_string(intent).expect("OAuthIntent serializes");
+
+        let _: () = conn.set_ex(&key, value, OAUTH_STATE_TTL_SECONDS).await?;
+
+        Ok(state)
+    }
+
+    async fn take(&self, state: &str) -> Result<Option<OAuthIntent>, redis::RedisError> {
+        let mut conn = self.redis.clone();
+        let key = format!("oauth_state:{}", state);
+
+        // Deleted in the same command, so a replayed callback finds nothing
+        let value: Option<String> = conn.get_del(&key).await?;
+
+        Ok(value.and_then(|value| serde_json::from_str(&value).ok()))
+    }
+}
+
+/// Kept in process, for tests and the in-memory server
+#[derive(Default)]
+pub struct MemoryOAuthStates {
+    /// state -> (intent, expires at)
+    states: Mutex<HashMap<String, (OAuthIntent, i64)>>,
+}
+
+#[async_trait]
+impl OAuthStateStore for MemoryOAuthStates {
+    async fn issue(&self, intent: &OAuthIntent) -> Result<String, redis::RedisError> {
+        let now = Utc::now().timestamp();
+        let state = new_state();
+
+        let mut states = self.states.lock();
+        states.retain(|_, (_, expires_at)| *expires_at > now);
+        states.insert(state.clone(), (intent.clone(), now + OAUTH_STATE_TTL_SECONDS as i64));
+
+        Ok(state)
+    }
+
+    async fn take(&self, state: &str) -> Result<Option<OAuthIntent>, redis::RedisError> {
+        let now = Utc::now().timestamp();
+
+        Ok(self
+            .states
+            .lock()
+            .remove(state)
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map(|(intent, _)| intent))
+    }
+}
@@ -0,0 +1,78 @@
+use chrono::Utc;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// In-process revocations with a TTL per entry
+///
+/// Backs the `memory` blacklist, sharing the store in `AppState::revocations`. With the Redis
+/// blacklist it is the copy used when Redis can't be reached and the failure policy is `local`.
+/// That copy holds revocations made by this instance and ones it has already seen in Redis, so
+/// a revocation issued elsewhere during an outage is only known once Redis is back.
+#[derive(Clone, Default)]
+pub struct LocalRevocations {
+    inner: Arc<Mutex<Revocations>>,
+}
+
+#[derive(Default)]
+struct Revocations {
+    tokens: HashMap<String, i64>,        // token -> unix expiry of the entry
+    users: HashMap<String, (i64, i64)>,  // user id -> (tokens issued before, unix expiry of the entry)
+}
+
+impl Revocations {
+    fn prune(&mut self, now: i64) {
+        self.tokens.retain(|_, expires_at| *expires_at > now);
+        self.users.retain(|_, (_, expires_at)| *expires_at > now);
+    }
+}
+
+impl LocalRevocations {
+    pub fn revoke_token(&self, token: &str, ttl_seconds: i64) {
+        let now = Utc::now().timestamp();
+        let mut revocations = self.inner.lock();
+        revocations.prune(now);
+        revocations.tokens.insert(token.to_string(), now + ttl_seconds);
+    }
+
+    pub fn revoke_user_before(&self, user_id: &str, timestamp: i64, ttl_seconds: i64) {
+        let now = Utc::now().timestamp();
+        let mut revocations = self.inner.lock();
+        revocations.prune(now);
+
+        // Keep the latest cutoff if the user was already revoked
+        let entry = revocations.users.entry(user_id.to_string()).or_insert((timestamp, now + ttl_seconds));
+        if timestamp >= entry.0 {
+            *entry = (timestamp, now + ttl_seconds);
+        }
+    }
+
+    pub fn is_token_revoked(&self, token: &str) -> bool {
+        let now = Utc::now().timestamp();
+        self.inner.lock().tokens.get(token).is_some_and(|expires_at| *expires_at > now)
+    }
+
+    pub fn is_user_token_revoked(&self, user_id: &str, token_issued_at: i64) -> bool {
+        let now = Utc::now().timestamp();
+        self.inner
+            .lock()
+            .users
+            .get(user_id)
+            .is_some_and(|(before, expires_at)| *expires_at > now && token_issued_at < *before)
+    }
+
+    /// Tokens issued before this timestamp are revoked for the user
+    pub fn user_revoked_before(&self, user_id: &str) -> Option<i64> {
+        let now = Utc::now().timestamp();
+        self.inner
+            .lock()
+            .users
+            .get(user_id)
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map(|(before, _)| *before)
+    }
+
+    pub fn forget_user(&self, user_id: &str) {
+        self.inner.lock().users.remove(user_id);
+    }
+}
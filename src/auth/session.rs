@@ -0,0 +1,322 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use tracing::info;
+
+/// A session is dropped if no heartbeat arrives within this window
+pub const SESSION_TTL_SECONDS: i64 = 90;
+
+const ALL_SESSIONS_KEY: &str = "sessions:all";
+
+/// Atomically prune stale sessions, enforce the limit and register the new session
+/// KEYS: user/product set, global set, session key
+/// ARGV: now, expires_at, limit (0 = unlimited), session id, session json, ttl
+const OPEN_SESSION_SCRIPT: &str = r"
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', ARGV[1])
+local limit = tonumber(ARGV[3])
+if limit > 0 and redis.call('ZCARD', KEYS[1]) >= limit then
+    return 0
+end
+redis.call('SET', KEYS[3], ARGV[5], 'EX', ARGV[6])
+redis.call('ZADD', KEYS[1], ARGV[2], ARGV[4])
+redis.call('EXPIRE', KEYS[1], ARGV[6])
+redis.call('ZADD', KEYS[2], ARGV[2], ARGV[4])
+return 1
+";
+
+/// Atomically refresh a session, a missing (expired or killed) session key is never recreated
+/// KEYS: user/product set, global set, session key
+/// ARGV: user id, product id, now, ttl, session id
+/// The set comes from the stored session, which is checked to still belong to that user and product
+const HEARTBEAT_SCRIPT: &str = r"
+local raw = redis.call('GET', KEYS[3])
+if not raw then
+    return 0
+end
+local session = cjson.decode(raw)
+if session.user_id ~= ARGV[1] or session.product_id ~= ARGV[2] then
+    return 0
+end
+session.last_heartbeat = tonumber(ARGV[3])
+local expires_at = tonumber(ARGV[3]) + tonumber(ARGV[4])
+redis.call('SET', KEYS[3], cjson.encode(session), 'EX', ARGV[4])
+redis.call('ZADD', KEYS[1], expires_at, ARGV[5])
+redis.call('EXPIRE', KEYS[1], ARGV[4])
+redis.call('ZADD', KEYS[2], expires_at, ARGV[5])
+return 1
+";
+
+/// Atomically remove a session, returns false if it's gone or no longer belongs to that user and product
+/// KEYS: user/product set, global set, session key
+/// ARGV: user id, product id, session id
+const CLOSE_SESSION_SCRIPT: &str = r"
+local raw = redis.call('GET', KEYS[3])
+if not raw then
+    return 0
+end
+local session = cjson.decode(raw)
+if session.user_id ~= ARGV[1] or session.product_id ~= ARGV[2] then
+    return 0
+end
+redis.call('DEL', KEYS[3])
+redis.call('ZREM', KEYS[1], ARGV[3])
+redis.call('ZREM', KEYS[2], ARGV[3])
+return 1
+";
+
+pub use authit_types::Session;
+
+fn new_session(user_id: &str, product_id: &str, hwid: &str, now: i64) -> Session {
+    Session {
+        session_id: URL_SAFE_NO_PAD.encode(rand::random::<[u8; 16]>()),
+        user_id: user_id.to_string(),
+        product_id: product_id.to_string(),
+        hwid: hwid.to_string(),
+        started_at: now,
+        last_heartbeat: now,
+    }
+}
+
+/// Live `/auth` sessions, kept alive by heartbeats and dropped when they stop
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    /// Open a session for a user on a product
+    /// Returns `None` if the user already has `max_sessions` live sessions for the product
+    async fn open(&self, user_id: &str, product_id: &str, hwid: &str, max_sessions: Option<i32>) -> Result<Option<Session>, redis::RedisError>;
+
+    /// Keep a session alive, returns false if it expired, was killed or belongs to someone else
+    async fn heartbeat(&self, user_id: &str, session_id: &str) -> Result<bool, redis::RedisError>;
+
+    /// End one of the user's own sessions, freeing its slot, returns false if it doesn't exist or isn't theirs
+    async fn close(&self, user_id: &str, session_id: &str) -> Result<bool, redis::RedisError>;
+
+    /// List every live session
+    async fn list(&self) -> Result<Vec<Session>, redis::RedisError>;
+
+    /// Kill a session, returns false if it doesn't exist
+    async fn kill(&self, session_id: &str) -> Result<bool, redis::RedisError>;
+}
+
+/// Expired by Redis TTLs, shared by every instance
+#[derive(Clone)]
+pub struct RedisSessions {
+    redis: ConnectionManager,
+}
+
+impl RedisSessions {
+    pub fn new(redis: ConnectionManager) -> Self {
+        Self { redis }
+    }
+
+    fn session_key(session_id: &str) -> String {
+        format!("session:{}", session_id)
+    }
+
+    fn user_product_key(user_id: &str, product_id: &str) -> String {
+        format!("sessions:{}:{}", user_id, product_id)
+    }
+
+    /// The stored session, `None` if it expired, was removed or belongs to someone other than `owner` when given
+    /// Its user and product name the set the scripts update, they check it's still the same session
+    async fn stored(&self, session_id: &str, owner: Option<&str>) -> Result<Option<Session>, redis::RedisError> {
+        let mut conn = self.redis.clone();
+        let raw: Option<String> = conn.get(Self::session_key(session_id)).await?;
+
+        Ok(raw
+            .and_then(|raw| serde_json::from_str::<Session>(&raw).ok())
+            .filter(|session| owner.is_none_or(|owner| session.user_id == owner)))
+    }
+
+    /// Remove a session, only if it belongs to `owner` when given, returns the id of its user
+    async fn remove(&self, session_id: &str, owner: Option<&str>) -> Result<Option<String>, redis::RedisError> {
+        let Some(session) = self.stored(session_id, owner).await? else {
+            return Ok(None);
+        };
+        let mut conn = self.redis.clone();
+
+        let removed: i32 = redis::Script::new(CLOSE_SESSION_SCRIPT)
+            .key(Self::user_product_key(&session.user_id, &session.product_id))
+            .key(ALL_SESSIONS_KEY)
+            .key(Self::session_key(session_id))
+            .arg(&session.user_id)
+            .arg(&session.product_id)
+            .arg(session_id)
+            .invoke_async(&mut conn)
+            .await?;
+
+        Ok((removed == 1).then_some(session.user_id))
+    }
+}
+
+#[async_trait]
+impl SessionStore for RedisSessions {
+    async fn open(&self, user_id: &str, product_id: &str, hwid: &str, max_sessions: Option<i32>) -> Result<Option<Session>, redis::RedisError> {
+        let mut conn = self.redis.clone();
+        let now = Utc::now().timestamp();
+
+        let session = new_session(user_id, product_id, hwid, now);
+        let raw = serde_json::to_string(&session).unwrap_or_default();
+
+        let opened: i32 = redis::Script::new(OPEN_SESSION_SCRIPT)
+            .key(Self::user_product_key(user_id, product_id))
+            .key(ALL_SESSIONS_KEY)
+            .key(Self::session_key(&session.session_id))
+            .arg(now)
+            .arg(now + SESSION_TTL_SECONDS)
+            .arg(max_sessions.unwrap_or(0))
+            .arg(&session.session_id)
+            .arg(raw)
+            .arg(SESSION_TTL_SECONDS)
+            .invoke_async(&mut conn)
+            .await?;
+
+        if opened == 1 {
+            info!("Opened session {} for user {} on product {}", session.session_id, user_id, product_id);
+            Ok(Some(session))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn heartbeat(&self, user_id: &str, session_id: &str) -> Result<bool, redis::RedisError> {
+        let Some(session) = self.stored(session_id, Some(user_id)).await? else {
+            return Ok(false);
+        };
+        let mut conn = self.redis.clone();
+
+        let refreshed: i32 = redis::Script::new(HEARTBEAT_SCRIPT)
+            .key(Self::user_product_key(user_id, &session.product_id))
+            .key(ALL_SESSIONS_KEY)
+            .key(Self::session_key(session_id))
+            .arg(user_id)
+            .arg(&session.product_id)
+            .arg(Utc::now().timestamp())
+            .arg(SESSION_TTL_SECONDS)
+            .arg(session_id)
+            .invoke_async(&mut conn)
+            .await?;
+
+        Ok(refreshed == 1)
+    }
+
+    async fn close(&self, user_id: &str, session_id: &str) -> Result<bool, redis::RedisError> {
+        let closed = self.remove(session_id, Some(user_id)).await?.is_some();
+        if closed {
+            info!("User {} closed session {}", user_id, session_id);
+        }
+
+        Ok(closed)
+    }
+
+    async fn list(&self) -> Result<Vec<Session>, redis::RedisError> {
+        let mut conn = self.redis.clone();
+        let now = Utc::now().timestamp();
+
+        let _: () = conn.zrembyscore(ALL_SESSIONS_KEY, "-inf", now).await?;
+        let session_ids: Vec<String> = conn.zrange(ALL_SESSIONS_KEY, 0, -1).await?;
+
+        if session_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let keys: Vec<String> = session_ids.iter().map(|id| Self::session_key(id)).collect();
+        let raw: Vec<Option<String>> = redis::cmd("MGET").arg(keys).query_async(&mut conn).await?;
+
+        Ok(raw
+            .into_iter()
+            .flatten()
+            .filter_map(|raw| serde_json::from_str(&raw).ok())
+            .collect())
+    }
+
+    async fn kill(&self, session_id: &str) -> Result<bool, redis::RedisError> {
+        let Some(user_id) = self.remove(session_id, None).await? else {
+            return Ok(false);
+        };
+        info!("Killed session {} of user {}", session_id, user_id);
+
+        Ok(true)
+    }
+}
+
+/// Kept in process, for tests and the in-memory server
+#[derive(Default)]
+pub struct MemorySessions {
+    /// session id -> (session, expires at)
+    sessions: Mutex<HashMap<String, (Session, i64)>>,
+}
+
+#[async_trait]
+impl SessionStore for MemorySessions {
+    async fn open(&self, user_id: &str, product_id: &str, hwid: &str, max_sessions: Option<i32>) -> Result<Option<Session>, redis::RedisError> {
+        let now = Utc::now().timestamp();
+        let mut sessions = self.sessions.lock();
+        sessions.retain(|_, (_, expires_at)| *expires_at > now);
+
+        let open = sessions
+            .values()
+            .filter(|(session, _)| session.user_id == user_id && session.product_id == product_id)
+            .count();
+        if let Some(limit) = max_sessions.filter(|limit| *limit > 0)
+            && open >= limit as usize
+        {
+            return Ok(None);
+        }
+
+        let session = new_session(user_id, product_id, hwid, now);
+        sessions.insert(session.session_id.clone(), (session.clone(), now + SESSION_TTL_SECONDS));
+        info!("Opened session {} for user {} on product {}", session.session_id, user_id, product_id);
+
+        Ok(Some(session))
+    }
+
+    async fn heartbeat(&self, user_id: &str, session_id: &str) -> Result<bool, redis::RedisError> {
+        let now = Utc::now().timestamp();
+
+        match self.sessions.lock().get_mut(session_id) {
+            Some((session, expires_at)) if session.user_id == user_id && *expires_at > now => {
+                session.last_heartbeat = now;
+                *expires_at = now + SESSION_TTL_SECONDS;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn close(&self, user_id: &str, session_id: &str) -> Result<bool, redis::RedisError> {
+        let mut sessions = self.sessions.lock();
+        if sessions.get(session_id).is_none_or(|(session, _)| session.user_id != user_id) {
+            return Ok(false);
+        }
+        sessions.remove(session_id);
+        info!("User {} closed session {}", user_id, session_id);
+
+        Ok(true)
+    }
+
+    async fn list(&self) -> Result<Vec<Session>, redis::RedisError> {
+        let now = Utc::now().timestamp();
+
+        Ok(self
+            .sessions
+            .lock()
+            .values()
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map(|(session, _)| session.clone())
+            .collect())
+    }
+
+    async fn kill(&self, session_id: &str) -> Result<bool, redis::RedisError> {
+        let Some((session, _)) = self.sessions.lock().remove(session_id) else {
+            return Ok(false);
+        };
+        info!("Killed session {} of user {}", session_id, session.user_id);
+
+        Ok(true)
+    }
+}
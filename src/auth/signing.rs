@@ -0,0 +1,110 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::Utc;
+use ed25519_dalek::{Signer, SigningKey};
+use std::fmt;
+use tracing::warn;
+
+pub use authit_types::{signing_message, AuthSignature, PublicResponseKey, SignedClaims};
+
+/// A key the server signs (or used to sign) `/auth` responses with
+#[derive(Clone)]
+struct ResponseKey {
+    kid: String,
+    signing_key: SigningKey,
+}
+
+#[derive(Debug)]
+pub enum SigningKeyError {
+    InvalidFormat(String),
+    InvalidKey(String),
+}
+
+impl fmt::Display for SigningKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SigningKeyError::InvalidFormat(entry) => write!(f, "Expected <kid>:<base64 seed>, got '{}'", entry),
+            SigningKeyError::InvalidKey(kid) => write!(f, "Key '{}' is not a base64 encoded 32 byte Ed25519 seed", kid),
+        }
+    }
+}
+
+impl std::error::Error for SigningKeyError {}
+
+/// Signs `/auth` responses with Ed25519 so clients can reject forged answers
+///
+/// Keys are configured as a list of `<kid>:<base64 seed>` (`auth.signing_keys`).
+/// The first key signs, the others are only published so responses signed before a
+/// rotation still verify. To rotate, prepend a new key and drop the old one later.
+#[derive(Clone)]
+pub struct ResponseSigner {
+    keys: Vec<ResponseKey>,
+}
+
+impl ResponseSigner {
+    /// Build the signer from the configured keys, falling back to an ephemeral key if there are none
+    pub fn from_keys(entries: &[String]) -> Result<Self, SigningKeyError> {
+        if entries.is_empty() {
+            warn!("No auth signing keys configured, using an ephemeral key - signed responses will not verify after a restart");
+            return Ok(Self {
+                keys: vec![ResponseKey {
+                    kid: format!("ephemeral-{}", Utc::now().timestamp()),
+                    signing_key: SigningKey::from_bytes(&rand::random::<[u8; 32]>()),
+                }],
+            });
+        }
+
+        let keys = entries
+            .iter()
+            .map(|entry| {
+                let (kid, seed) = entry
+                    .split_once(':')
+                    .ok_or_else(|| SigningKeyError::InvalidFormat(entry.to_string()))?;
+
+                let seed: [u8; 32] = BASE64
+                    .decode(seed)
+                    .ok()
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .ok_or_else(|| SigningKeyError::InvalidKey(kid.to_string()))?;
+
+                Ok(ResponseKey {
+                    kid: kid.to_string(),
+                    signing_key: SigningKey::from_bytes(&seed),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { keys })
+    }
+
+    /// Sign a successful authorization with the active key
+    /// The challenge nonce is included so a recorded response can't be replayed against a new challenge
+    pub fn sign(&self, claims: SignedClaims) -> AuthSignature {
+        let active = &self.keys[0];
+        let timestamp = Utc::now().timestamp();
+
+        let message = signing_message(&claims, timestamp);
+        let signature = active.signing_key.sign(&message);
+
+        AuthSignature {
+            claims,
+            timestamp,
+            kid: active.kid.clone(),
+            signature: BASE64.encode(signature.to_bytes()),
+        }
+    }
+
+    /// All public keys clients should accept, the active one first
+    pub fn public_keys(&self) -> Vec<PublicResponseKey> {
+        self.keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| PublicResponseKey {
+                kid: key.kid.clone(),
+                alg: "EdDSA".to_string(),
+                public_key: BASE64.encode(key.signing_key.verifying_key().to_bytes()),
+                active: i == 0,
+            })
+            .collect()
+    }
+}
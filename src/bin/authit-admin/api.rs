@@ -0,0 +1,30 @@
+use authit_client::Client;
+use serde_json::json;
+
+use crate::args::Command;
+use crate::output::{self, Output};
+
+/// Runs the commands the HTTP API has endpoints for, with the admin token already set on `client`
+pub async fn run(client: &Client, command: Command) -> Result<Output, String> {
+    match command {
+        Command::UserSetRole { user, role } => {
+            if user.contains('@') {
+                return Err("The API needs a user id, emails can only be looked up with a database connection".to_string());
+            }
+            let message = client.set_role(&user, role).await.map_err(|err| err.to_string())?;
+
+            Ok(Output::message(message, json!({ "id": user, "role": role })))
+        }
+        Command::KeyGenerate { product, days, count, tier } => {
+            let keys = client.generate_keys(&product, days, count, tier.as_deref()).await.map_err(|err| err.to_string())?;
+
+            Ok(output::generated_keys(&product, days, tier.as_deref(), keys))
+        }
+        Command::Compensate { product, hours } => {
+            let users = client.compensate(&product, hours).await.map_err(|err| err.to_string())?;
+
+            Ok(output::compensated(&product, hours, users as usize))
+        }
+        command => Err(format!("{} needs a database connection, run it without --api", command.name())),
+    }
+}
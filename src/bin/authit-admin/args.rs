@@ -0,0 +1,501 @@
+use std::str::FromStr;
+
+use authit::handlers::account::Role;
+use authit::repository::HwidPolicy;
+
+pub const USAGE: &str = "\
+Usage: authit-admin [--json] [--database-url URL | --api URL --token TOKEN] <command>
+
+Commands:
+  product create <id> <name> [--hwid-policy none|per-account|per-product|devices]
+                             [--device-limit N] [--max-sessions N]
+  product update <id> [--max-sessions N|unlimited]
+                       [--hwid-policy none|per-account|per-product|devices] [--device-limit N]
+  product list
+  product freeze <id>
+  product unfreeze <id>
+  product tier <id> <tier> --level N [--entitlements a,b,...]
+  product tiers <id>
+  user create <email> [--role user|support|dev|admin] [--password PASSWORD]
+  user set-role <user> <role>
+  user ban <user>
+  user unban <user>
+  user licenses <user>
+  hwid ban <hwid> [--reason TEXT]
+  hwid unban <hwid>
+  key generate <product> (--days N | --lifetime) [--count N] [--tier TIER]
+  key export <product>
+  compensate <product> --hours N
+  migrate
+
+<user> is a user id, an email address or a linked account as <provider>:<external id>.
+product tier creates a tier or updates the one with that name. Licenses move up to the tier of
+a redeemed key with a higher level, keys for a lower level are refused while the license is active.
+Keys without --tier only add time, the license keeps its tier.
+Lifetime keys make the license never expire, after that only lifetime keys for a higher tier are
+accepted and compensate leaves the license alone.
+
+Commands run against the server's database, read from DATABASE_URL or authit.toml like the
+server does, unless --database-url is given. With --api (or AUTHIT_API_URL and AUTHIT_TOKEN)
+they go through the HTTP API with an admin token instead, which only supports user set-role,
+key generate and compensate.
+
+Commands refuse to run while the database lacks migrations this binary knows about, migrate
+applies them (so does starting the server). A new database needs it before the first user create.
+
+user create reads the password from --password, then AUTHIT_ADMIN_PASSWORD, then stdin.";
+
+pub struct Args {
+    pub json: bool,
+    pub target: Target,
+    pub command: Command,
+}
+
+pub enum Target {
+    /// `None` uses the database from the server's configuration
+    Database(Option<String>),
+    Api { url: String, token: String },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Help,
+    ProductCreate {
+        id: String,
+        name: String,
+        hwid_policy: HwidPolicy,
+        device_limit: Option<i32>,
+        max_sessions: Option<i32>,
+    },
+    /// Only the settings that are `Some` change, `max_sessions: Some(None)` lifts the limit
+    ProductUpdate {
+        id: String,
+        max_sessions: Option<Option<i32>>,
+        hwid_policy: Option<HwidPolicy>,
+        device_limit: Option<i32>,
+    },
+    ProductList,
+    ProductFreeze { id: String },
+    ProductUnfreeze { id: String },
+    ProductTier { id: String, tier: String, level: i32, entitlements: Vec<String> },
+    ProductTiers { id: String },
+    /// `password` is `None` when it should be read from stdin
+    UserCreate { email: String, role: Role, password: Option<String> },
+    UserSetRole { user: String, role: Role },
+    UserBan { user: String },
+    UserUnban { user: String },
+    UserLicenses { user: String },
+    HwidBan { hwid: String, reason: Option<String> },
+    HwidUnban { hwid: String },
+    /// `None` days for lifetime keys
+    KeyGenerate { product: String, days: Option<i64>, count: i32, tier: Option<String> },
+    KeyExport { product: String },
+    Compensate { product: String, hours: i64 },
+    Migrate,
+}
+
+impl Command {
+    /// How the command is typed, for error messages
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Help => "help",
+            Command::ProductCreate { .. } => "product create",
+            Command::ProductUpdate { .. } => "product update",
+            Command::ProductList => "product list",
+            Command::ProductFreeze { .. } => "product freeze",
+            Command::ProductUnfreeze { .. } => "product unfreeze",
+            Command::ProductTier { .. } => "product tier",
+            Command::ProductTiers { .. } => "product tiers",
+            Command::UserCreate { .. } => "user create",
+            Command::UserSetRole { .. } => "user set-role",
+            Command::UserBan { .. } => "user ban",
+            Command::UserUnban { .. } => "user unban",
+            Command::UserLicenses { .. } => "user licenses",
+            Command::HwidBan { .. } => "hwid ban",
+            Command::HwidUnban { .. } => "hwid unban",
+            Command::KeyGenerate { .. } => "key generate",
+            Command::KeyExport { .. } => "key export",
+            Command::Compensate { .. } => "compensate",
+            Command::Migrate => "migrate",
+        }
+    }
+}
+
+/// Parse the arguments after the program name, `env` looks up environment variables
+pub fn parse(args: Vec<String>, env: impl Fn(&str) -> Option<String>) -> Result<Args, String> {
+    let mut parser = Parser { args };
+
+    let json = parser.flag("--json");
+    let help = parser.flag("--help") || parser.flag("-h");
+    let database_url = parser.option("--database-url")?;
+    let api_url = parser.option("--api")?;
+    let token = parser.option("--token")?;
+
+    let command = if help || parser.args.is_empty() {
+        Command::Help
+    } else {
+        parser.command(&env)?
+    };
+
+    let target = if let Some(url) = database_url {
+        if api_url.is_some() {
+            return Err("--database-url and --api can't be combined".to_string());
+        }
+        Target::Database(Some(url))
+    } else if let Some(url) = api_url.or_else(|| env("AUTHIT_API_URL")) {
+        let token = token
+            .or_else(|| env("AUTHIT_TOKEN"))
+            .ok_or("--api needs an admin token in --token or AUTHIT_TOKEN")?;
+        Target::Api { url, token }
+    } else {
+        Target::Database(None)
+    };
+
+    Ok(Args { json, target, command })
+}
+
+/// Same rule as the products table's check constraint, tier names follow it too
+fn check_kebab_case(what: &str, value: &str) -> Result<(), String> {
+    if value.is_empty() || !value.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+        return Err(format!("{} '{}' must be kebab-case (a-z, 0-9 and -)", what, value));
+    }
+    Ok(())
+}
+
+fn parse_role(value: &str) -> Result<Role, String> {
+    match value.to_lowercase().as_str() {
+        "user" => Ok(Role::User),
+        "support" => Ok(Role::Support),
+        "dev" => Ok(Role::Dev),
+        "admin" => Ok(Role::Admin),
+        _ => Err(format!("Unknown role '{}', expected user, support, dev or admin", value)),
+    }
+}
+
+fn parse_hwid_policy(value: &str) -> Result<HwidPolicy, String> {
+    match value.to_lowercase().as_str() {
+        "none" => Ok(HwidPolicy::None),
+        "per-account" => Ok(HwidPolicy::PerAccount),
+        "per-product" => Ok(HwidPolicy::PerProduct),
+        "devices" => Ok(HwidPolicy::Devices),
+        _ => Err(format!("Unknown HWID policy '{}', expected none, per-account, per-product or devices", value)),
+    }
+}
+
+/// Options are taken out of `args` as they're read, what's left are the positional arguments
+struct Parser {
+    args: Vec<String>,
+}
+
+impl Parser {
+    fn flag(&mut self, name: &str) -> bool {
+        let before = self.args.len();
+        self.args.retain(|arg| arg != name);
+        self.args.len() != before
+    }
+
+    fn option(&mut self, name: &str) -> Result<Option<String>, String> {
+        let Some(index) = self.args.iter().position(|arg| arg == name) else {
+            return Ok(None);
+        };
+        if index + 1 >= self.args.len() {
+            return Err(format!("{} needs a value", name));
+        }
+
+        let value = self.args.remove(index + 1);
+        self.args.remove(index);
+        Ok(Some(value))
+    }
+
+    fn number<T: FromStr>(&mut self, name: &str) -> Result<Option<T>, String> {
+        self.option(name)?
+            .map(|value| value.parse().map_err(|_| format!("{} must be a number, got '{}'", name, value)))
+            .transpose()
+    }
+
+    /// The next command word
+    fn word(&mut self, expected: &str) -> Result<String, String> {
+        match self.args.first() {
+            Some(word) if !word.starts_with('-') => Ok(self.args.remove(0)),
+            _ => Err(format!("Expected {}", expected)),
+        }
+    }
+
+    /// Every remaining argument, once the command's options have been read
+    fn positionals<const N: usize>(&mut self, usage: &str) -> Result<[String; N], String> {
+        if let Some(option) = self.args.iter().find(|arg| arg.starts_with("--")) {
+            return Err(format!("Unknown option {}", option));
+        }
+
+        std::mem::take(&mut self.args)
+            .try_into()
+            .map_err(|_| format!("Usage: authit-admin {}", usage))
+    }
+
+    fn command(&mut self, env: &impl Fn(&str) -> Option<String>) -> Result<Command, String> {
+        let group = self.word("a command")?;
+        if group == "help" {
+            return Ok(Command::Help);
+        }
+        if group == "compensate" {
+            let hours = self.number("--hours")?.ok_or("compensate needs --hours")?;
+            if hours <= 0 {
+                return Err("--hours must be positive".to_string());
+            }
+            let [product] = self.positionals("compensate <product> --hours N")?;
+            return Ok(Command::Compensate { product, hours });
+        }
+        if group == "migrate" {
+            let [] = self.positionals("migrate")?;
+            return Ok(Command::Migrate);
+        }
+        if !matches!(group.as_str(), "product" | "user" | "hwid" | "key") {
+            return Err(format!("Unknown command '{}'", group));
+        }
+
+        let action = self.word(&format!("a {} command", group))?;
+        let command = match (group.as_str(), action.as_str()) {
+            ("product", "create") => {
+                let hwid_policy = match self.option("--hwid-policy")? {
+                    Some(value) => parse_hwid_policy(&value)?,
+                    None => HwidPolicy::PerAccount,
+                };
+                let device_limit = self.number("--device-limit")?;
+                let max_sessions = self.number("--max-sessions")?;
+                let [id, name] = self.positionals("product create <id> <name> [options]")?;
+
+                check_kebab_case("Product id", &id)?;
+                if hwid_policy == HwidPolicy::Devices && device_limit.is_none_or(|limit| limit <= 0) {
+                    return Err("--hwid-policy devices needs a positive --device-limit".to_string());
+                }
+                if max_sessions.is_some_and(|limit| limit <= 0) {
+                    return Err("--max-sessions must be positive".to_string());
+                }
+
+                Command::ProductCreate { id, name, hwid_policy, device_limit, max_sessions }
+            }
+            ("product", "update") => {
+                let max_sessions = match self.option("--max-sessions")?.as_deref() {
+                    Some("unlimited") => Some(None),
+                    Some(value) => match value.parse::<i32>() {
+                        Ok(limit) if limit > 0 => Some(Some(limit)),
+                        _ => return Err(format!("--max-sessions must be positive or 'unlimited', got '{}'", value)),
+                    },
+                    None => None,
+                };
+                let hwid_policy = self.option("--hwid-policy")?.map(|value| parse_hwid_policy(&value)).transpose()?;
+                let device_limit = self.number("--device-limit")?;
+                let [id] = self.positionals("product update <id> [--max-sessions N|unlimited] [--hwid-policy POLICY] [--device-limit N]")?;
+                if max_sessions.is_none() && hwid_policy.is_none() && device_limit.is_none() {
+                    return Err("product update needs a setting to change, e.g. --max-sessions".to_string());
+                }
+                // The limit is stored with the policy, so it's always given with it
+                if device_limit.is_some() && hwid_policy != Some(HwidPolicy::Devices) {
+                    return Err("--device-limit needs --hwid-policy devices".to_string());
+                }
+                if hwid_policy == Some(HwidPolicy::Devices) && device_limit.is_none_or(|limit| limit <= 0) {
+                    return Err("--hwid-policy devices needs a positive --device-limit".to_string());
+                }
+
+                Command::ProductUpdate { id, max_sessions, hwid_policy, device_limit }
+            }
+            ("product", "list") => {
+                let [] = self.positionals("product list")?;
+                Command::ProductList
+            }
+            ("product", "freeze") => {
+                let [id] = self.positionals("product freeze <id>")?;
+                Command::ProductFreeze { id }
+            }
+            ("product", "unfreeze") => {
+                let [id] = self.positionals("product unfreeze <id>")?;
+                Command::ProductUnfreeze { id }
+            }
+            ("product", "tier") => {
+                let level = self.number("--level")?.ok_or("product tier needs --level")?;
+                let entitlements: Vec<String> = self
+                    .option("--entitlements")?
+                    .map(|list| list.split(',').map(str::trim).filter(|name| !name.is_empty()).map(str::to_string).collect())
+                    .unwrap_or_default();
+                let [id, tier] = self.positionals("product tier <id> <tier> --level N [--entitlements a,b,...]")?;
+                check_kebab_case("Tier", &tier)?;
+
+                Command::ProductTier { id, tier, level, entitlements }
+            }
+            ("product", "tiers") => {
+                let [id] = self.positionals("product tiers <id>")?;
+                Command::ProductTiers { id }
+            }
+            ("user", "create") => {
+                let role = match self.option("--role")? {
+                    Some(value) => parse_role(&value)?,
+                    None => Role::User,
+                };
+                let password = self.option("--password")?.or_else(|| env("AUTHIT_ADMIN_PASSWORD"));
+                let [email] = self.positionals("user create <email> [--role ROLE] [--password PASSWORD]")?;
+                Command::UserCreate { email, role, password }
+            }
+            ("user", "set-role") => {
+                let [user, role] = self.positionals("user set-role <user> <role>")?;
+                Command::UserSetRole { user, role: parse_role(&role)? }
+            }
+            ("user", "ban") => {
+                let [user] = self.positionals("user ban <user>")?;
+                Command::UserBan { user }
+            }
+            ("user", "unban") => {
+                let [user] = self.positionals("user unban <user>")?;
+                Command::UserUnban { user }
+            }
+            ("user", "licenses") => {
+                let [user] = self.positionals("user licenses <user>")?;
+                Command::UserLicenses { user }
+            }
+            ("hwid", "ban") => {
+                let reason = self.option("--reason")?;
+                let [hwid] = self.positionals("hwid ban <hwid> [--reason TEXT]")?;
+                Command::HwidBan { hwid, reason }
+            }
+            ("hwid", "unban") => {
+                let [hwid] = self.positionals("hwid unban <hwid>")?;
+                Command::HwidUnban { hwid }
+            }
+            ("key", "generate") => {
+                let days = self.number("--days")?;
+                let lifetime = self.flag("--lifetime");
+                let count = self.number("--count")?.unwrap_or(1);
+                let tier = self.option("--tier")?;
+                match (days, lifetime) {
+                    (None, false) => return Err("key generate needs --days or --lifetime".to_string()),
+                    (Some(_), true) => return Err("--days and --lifetime can't be combined".to_string()),
+                    (Some(days), false) if days <= 0 => return Err("--days must be positive".to_string()),
+                    _ => {}
+                }
+                // Same limit as the API
+                if !(1..=1000).contains(&count) {
+                    return Err("--count must be between 1 and 1000".to_string());
+                }
+                let [product] = self.positionals("key generate <product> (--days N | --lifetime) [--count N] [--tier TIER]")?;
+                Command::KeyGenerate { product, days, count, tier }
+            }
+            ("key", "export") => {
+                let [product] = self.positionals("key export <product>")?;
+                Command::KeyExport { product }
+            }
+            _ => return Err(format!("Unknown command '{} {}'", group, action)),
+        };
+
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_with(args: &str, env: &[(&str, &str)]) -> Result<Args, String> {
+        let env: Vec<(String, String)> = env.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        parse(
+            args.split_whitespace().map(str::to_string).collect(),
+            |name| env.iter().find(|(key, _)| key == name).map(|(_, value)| value.clone()),
+        )
+    }
+
+    #[test]
+    fn options_can_come_anywhere() {
+        let args = parse_with("key generate --count 5 game --json --days 30", &[]).unwrap();
+
+        assert!(args.json);
+        assert!(matches!(args.target, Target::Database(None)));
+        assert_eq!(args.command, Command::KeyGenerate { product: "game".to_string(), days: Some(30), count: 5, tier: None });
+
+        let args = parse_with("key generate game --lifetime", &[]).unwrap();
+        assert_eq!(args.command, Command::KeyGenerate { product: "game".to_string(), days: None, count: 1, tier: None });
+    }
+
+    #[test]
+    fn tiers_take_a_level_and_entitlements() {
+        let args = parse_with("product tier game premium --level 2 --entitlements esp,aimbot,", &[]).unwrap();
+        assert_eq!(args.command, Command::ProductTier {
+            id: "game".to_string(),
+            tier: "premium".to_string(),
+            level: 2,
+            entitlements: vec!["esp".to_string(), "aimbot".to_string()],
+        });
+
+        assert!(parse_with("product tier game premium", &[]).is_err());
+        assert!(parse_with("product tier game Premium --level 2", &[]).is_err());
+
+        let args = parse_with("key generate game --days 30 --tier premium", &[]).unwrap();
+        assert!(matches!(args.command, Command::KeyGenerate { tier: Some(ref tier), .. } if tier == "premium"));
+    }
+
+    #[test]
+    fn product_update_changes_only_what_is_given() {
+        let args = parse_with("product update game --max-sessions 2", &[]).unwrap();
+        assert_eq!(args.command, Command::ProductUpdate {
+            id: "game".to_string(),
+            max_sessions: Some(Some(2)),
+            hwid_policy: None,
+            device_limit: None,
+        });
+
+        let args = parse_with("product update game --max-sessions unlimited --hwid-policy devices --device-limit 3", &[]).unwrap();
+        assert_eq!(args.command, Command::ProductUpdate {
+            id: "game".to_string(),
+            max_sessions: Some(None),
+            hwid_policy: Some(HwidPolicy::Devices),
+            device_limit: Some(3),
+        });
+
+        assert!(parse_with("product update game", &[]).is_err());
+        assert!(parse_with("product update game --max-sessions 0", &[]).is_err());
+        assert!(parse_with("product update game --hwid-policy devices", &[]).is_err());
+        assert!(parse_with("product update game --device-limit 3", &[]).is_err());
+    }
+
+    #[test]
+    fn api_target_needs_a_token() {
+        let err = parse_with("--api http://localhost:8080 product list", &[]).err().unwrap();
+        assert!(err.contains("--token"), "{}", err);
+
+        let args = parse_with("compensate game --hours 2", &[("AUTHIT_API_URL", "http://api"), ("AUTHIT_TOKEN", "t")]).unwrap();
+        assert!(matches!(args.target, Target::Api { ref url, ref token } if url == "http://api" && token == "t"));
+
+        // An explicit database wins over the environment
+        let args = parse_with("--database-url postgres://db product list", &[("AUTHIT_API_URL", "http://api")]).unwrap();
+        assert!(matches!(args.target, Target::Database(Some(_))));
+    }
+
+    #[test]
+    fn migrate_takes_no_arguments() {
+        assert_eq!(parse_with("migrate", &[]).unwrap().command, Command::Migrate);
+        assert!(parse_with("migrate 004", &[]).is_err());
+    }
+
+    #[test]
+    fn invalid_commands_are_rejected() {
+        assert!(parse_with("product create Game \"Game\"", &[]).is_err());
+        assert!(parse_with("product create game Game --hwid-policy devices", &[]).is_err());
+        assert!(parse_with("key generate game --days 30 --count 0", &[]).is_err());
+        assert!(parse_with("key generate game", &[]).is_err());
+        assert!(parse_with("key generate game --days 30 --lifetime", &[]).is_err());
+        assert!(parse_with("user ban", &[]).is_err());
+        assert!(parse_with("user ban someone --force", &[]).is_err());
+        assert!(parse_with("user set-role someone owner", &[]).is_err());
+        assert!(parse_with("license list", &[]).is_err());
+    }
+
+    #[test]
+    fn password_falls_back_to_the_environment() {
+        let args = parse_with("user create admin@example.com --role admin", &[("AUTHIT_ADMIN_PASSWORD", "secret")]).unwrap();
+        assert_eq!(args.command, Command::UserCreate {
+            email: "admin@example.com".to_string(),
+            role: Role::Admin,
+            password: Some("secret".to_string()),
+        });
+
+        let args = parse_with("user create admin@example.com", &[]).unwrap();
+        assert!(matches!(args.command, Command::UserCreate { role: Role::User, password: None, .. }));
+    }
+}
@@ -0,0 +1,647 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHasher, SaltString};
+use argon2::Argon2;
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sqlx::PgPool;
+use sqlx::postgres::PgPoolOptions;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use authit::auth::jwt::TOKEN_LIFETIME_SECONDS;
+use authit::auth::{RedisBlacklist, TokenBlacklist};
+use authit::cache::AuthCache;
+use authit::config::{BlacklistBackend, Config};
+use authit::handlers::account::Role;
+use authit::handlers::product::generate_random_key;
+use authit::migrate;
+use authit::repository::{Product, Repositories, RepositoryError, Tier, User};
+use authit::webhook::{WebhookEvent, Webhooks};
+
+use crate::args::Command;
+use crate::output::{self, Output, Table};
+
+/// Runs commands straight against the repositories, with the same cache and token side effects as the handlers
+pub struct Admin {
+    /// `None` on the in-memory repositories, which have no schema to migrate
+    pub pool: Option<PgPool>,
+    pub repos: Repositories,
+    pub cache: AuthCache,
+    /// `None` when the server keeps revocations in memory (or Redis is down), the CLI can't reach them then
+    pub blacklist: Option<Arc<dyn TokenBlacklist>>,
+    pub key_prefix: String,
+}
+
+fn db_error(err: RepositoryError) -> String {
+    format!("Database error: {}", err)
+}
+
+fn warn(message: String) {
+    eprintln!("warning: {}", message);
+}
+
+fn role_name(role: Role) -> String {
+    format!("{:?}", role)
+}
+
+/// Connect the way the server does, reading its configuration with `database_url` taking precedence
+/// Refuses a database with pending migrations unless the command is going to apply them
+pub async fn connect(database_url: Option<String>, migrating: bool) -> Result<Admin, String> {
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        // Only the database is needed, so a config that doesn't validate (e.g. no JWT_SECRET) is fine here
+        Err(err) if database_url.is_some() => {
+            warn(format!("Invalid configuration ({}), using the defaults", err));
+            Config::default()
+        }
+        Err(err) => return Err(format!("Invalid configuration: {}", err)),
+    };
+    if let Some(url) = database_url {
+        config.database.url = url;
+    }
+
+    let pool = PgPoolOptions::new()
+        .max_connections(2)
+        .connect(&config.database.url)
+        .await
+        .map_err(|err| format!("Failed to connect to database: {}", err))?;
+    // Queries written for a newer schema could fail halfway, migrating is left to an explicit `migrate`
+    if !migrating {
+        let pending = migrate::pending(&pool)
+            .await
+            .map_err(|err| format!("Failed to check database migrations: {}", err))?;
+        if !pending.is_empty() {
+            let versions: Vec<String> = pending.iter().map(|version| format!("{:03}", version)).collect();
+            return Err(format!(
+                "The database is missing migration(s) {}, run `authit-admin migrate` or start the server first",
+                versions.join(", ")
+            ));
+        }
+    }
+
+    let redis_timeout = std::time::Duration::from_millis(config.redis.timeout_ms);
+    let redis_config = redis::aio::ConnectionManagerConfig::new()
+        .set_connection_timeout(redis_timeout)
+        .set_response_timeout(redis_timeout);
+    // A server on the memory backend runs without Redis, there's nothing to invalidate or revoke in it
+    let redis = if config.auth.blacklist_backend == BlacklistBackend::Memory {
+        None
+    } else {
+        match redis::Client::open(config.redis.url.as_str()) {
+            Ok(client) => redis::aio::ConnectionManager::new_with_config(client, redis_config).await.ok(),
+            Err(_) => None,
+        }
+    };
+    if redis.is_none() && config.auth.blacklist_backend == BlacklistBackend::Redis {
+        warn(format!("Redis @ {} is unavailable, cached /auth state is left to expire on its own", config.redis.url));
+    }
+
+    let blacklist: Option<Arc<dyn TokenBlacklist>> = match (&config.auth.blacklist_backend, &redis) {
+        (BlacklistBackend::Redis, Some(redis)) => Some(Arc::new(RedisBlacklist::new(redis.clone()))),
+        _ => None,
+    };
+
+    Ok(Admin {
+        pool: Some(pool.clone()),
+        repos: Repositories::postgres(pool),
+        cache: AuthCache::new(redis),
+        blacklist,
+        key_prefix: config.keys.prefix.clone(),
+    })
+}
+
+impl Admin {
+    pub async fn run(&self, command: Command) -> Result<Output, String> {
+        match command {
+            Command::Help => Err("help has nothing to run".to_string()),
+            Command::Migrate => {
+                let pool = self.pool.as_ref().ok_or("There's no database to migrate")?;
+                let applied = migrate::run(pool)
+                    .await
+                    .map_err(|err| format!("Failed to apply database migrations: {}", err))?;
+
+                Ok(Output::message(format!("Applied {} migration(s), the schema is up to date", applied.len()), json!({ "applied": applied })))
+            }
+            Command::ProductCreate { id, name, hwid_policy, device_limit, max_sessions } => {
+                let product = Product {
+                    id,
+                    name,
+                    frozen: false,
+                    max_sessions,
+                    hwid_policy,
+                    hwid_device_limit: device_limit,
+                };
+                if !self.repos.products.create(&product).await.map_err(db_error)? {
+                    return Err(format!("Product '{}' already exists", product.id));
+                }
+
+                Ok(Output::message(
+                    format!("Created product {} ({})", product.id, product.name),
+                    json!({
+                        "id": product.id,
+                        "name": product.name,
+                        "hwid_policy": product.hwid_policy,
+                        "hwid_device_limit": product.hwid_device_limit,
+                        "max_sessions": product.max_sessions,
+                    }),
+                ))
+            }
+            Command::ProductUpdate { id, max_sessions, hwid_policy, device_limit } => {
+                self.product_exists(&id).await?;
+                // Only the changed settings are echoed back
+                let mut data = serde_json::Map::new();
+                data.insert("id".to_string(), json!(id));
+                if let Some(max_sessions) = max_sessions {
+                    if !self.repos.products.set_max_sessions(&id, max_sessions).await.map_err(db_error)? {
+                        return Err(format!("Product '{}' not found", id));
+                    }
+                    data.insert("max_sessions".to_string(), json!(max_sessions));
+                }
+                if let Some(hwid_policy) = hwid_policy {
+                    if !self.repos.products.set_hwid_policy(&id, hwid_policy, device_limit).await.map_err(db_error)? {
+                        return Err(format!("Product '{}' not found", id));
+                    }
+                    data.insert("hwid_policy".to_string(), json!(hwid_policy));
+                    data.insert("hwid_device_limit".to_string(), json!(device_limit));
+                }
+
+                self.invalidate_holders(&id, None).await;
+                Ok(Output::message(format!("Updated product {}", id), data.into()))
+            }
+            Command::ProductList => {
+                let products = self.repos.products.list().await.map_err(db_error)?;
+
+                let mut table = Table::new(vec!["ID", "NAME", "HWID POLICY", "DEVICE LIMIT", "MAX SESSIONS", "FROZEN"]);
+                let mut rows = Vec::new();
+                for product in products {
+                    table.row(vec![
+                        product.id.clone(),
+                        product.name.clone(),
+                        format!("{:?}", product.hwid_policy),
+                        product.hwid_device_limit.map(|limit| limit.to_string()).unwrap_or_default(),
+                        product.max_sessions.map(|limit| limit.to_string()).unwrap_or_else(|| "unlimited".to_string()),
+                        if product.frozen { "yes" } else { "no" }.to_string(),
+                    ]);
+                    rows.push(json!({
+                        "id": product.id,
+                        "name": product.name,
+                        "frozen": product.frozen,
+                        "hwid_policy": product.hwid_policy,
+                        "hwid_device_limit": product.hwid_device_limit,
+                        "max_sessions": product.max_sessions,
+                    }));
+                }
+
+                Ok(Output::table(table, json!(rows)))
+            }
+            Command::ProductTier { id, tier, level, entitlements } => {
+                self.product_exists(&id).await?;
+                let tier = Tier { name: tier, level, entitlements };
+                if !self.repos.products.set_tier(&id, &tier).await.map_err(db_error)? {
+                    return Err(format!("Another tier of {} already has level {}", id, tier.level));
+                }
+
+                self.invalidate_holders(&id, Some(&tier.name)).await;
+                Ok(Output::message(
+                    format!("Set tier {} of {} to level {} with {} entitlement(s)", tier.name, id, tier.level, tier.entitlements.len()),
+                    json!({ "product_id": id, "name": tier.name, "level": tier.level, "entitlements": tier.entitlements }),
+                ))
+            }
+            Command::ProductTiers { id } => {
+                self.product_exists(&id).await?;
+                let tiers = self.repos.products.tiers(&id).await.map_err(db_error)?;
+
+                let mut table = Table::new(vec!["TIER", "LEVEL", "ENTITLEMENTS"]);
+                let mut rows = Vec::new();
+                for tier in tiers {
+                    table.row(vec![tier.name.clone(), tier.level.to_string(), tier.entitlements.join(", ")]);
+                    rows.push(json!({ "name": tier.name, "level": tier.level, "entitlements": tier.entitlements }));
+                }
+
+                Ok(Output::table(table, json!(rows)))
+            }
+            Command::ProductFreeze { id } => self.set_frozen(&id, true).await,
+            Command::ProductUnfreeze { id } => self.set_frozen(&id, false).await,
+            Command::UserCreate { email, role, password } => {
+                let password = password.filter(|password| !password.is_empty()).ok_or("A password is required")?;
+                let salt = SaltString::generate(&mut OsRng);
+                let password_hash = Argon2::default()
+                    .hash_password(password.as_bytes(), &salt)
+                    .map_err(|err| format!("Failed to hash password: {}", err))?
+                    .to_string();
+
+                let Some(user_id) = self.repos.users.create(&email, &password_hash, role).await.map_err(db_error)? else {
+                    return Err(format!("A user with email {} already exists", email));
+                };
+
+                Ok(Output::message(
+                    format!("Created {} {} ({})", role_name(role), email, user_id),
+                    json!({ "id": user_id, "email": email, "role": role }),
+                ))
+            }
+            Command::UserSetRole { user, role } => {
+                let user = self.user(&user).await?;
+
+                // Revoke first like the API does, so a failed revocation leaves the role as it was
+                match &self.blacklist {
+                    Some(blacklist) => {
+                        blacklist
+                            .blacklist_user_before_timestamp(&user.id, Utc::now().timestamp(), TOKEN_LIFETIME_SECONDS)
+                            .await
+                            .map_err(|err| format!("Failed to revoke the user's tokens, role unchanged: {}", err))?;
+                    }
+                    None => warn("Tokens can't be revoked from here, existing ones keep the old role until they expire".to_string()),
+                }
+
+                if !self.repos.users.set_role(&user.id, role).await.map_err(db_error)? {
+                    return Err(format!("User '{}' not found", user.id));
+                }
+                self.invalidate_user(&user.id).await;
+                self.publish(WebhookEvent::RoleChanged, json!({ "user_id": user.id, "role": role, "changed_by": null }))
+                    .await;
+
+                Ok(Output::message(
+                    format!("Set {} ({}) to {}", user.email, user.id, role_name(role)),
+                    json!({ "id": user.id, "email": user.email, "role": role }),
+                ))
+            }
+            Command::UserBan { user } => self.set_banned(&user, true).await,
+            Command::UserUnban { user } => self.set_banned(&user, false).await,
+            Command::UserLicenses { user } => {
+                let user = self.user(&user).await?;
+                let licenses = self.repos.licenses.for_user(&user.id).await.map_err(db_error)?;
+                let names: HashMap<String, String> = self
+                    .repos
+                    .products
+                    .list()
+                    .await
+                    .map_err(db_error)?
+                    .into_iter()
+                    .map(|product| (product.id, product.name))
+                    .collect();
+
+                let now = Utc::now().timestamp();
+                let mut table = Table::new(vec!["PRODUCT", "NAME", "EXPIRES AT", "REMAINING", "TIER"]);
+                let mut rows = Vec::new();
+                for license in licenses {
+                    let name = names.get(&license.product_id).cloned().unwrap_or_default();
+                    let expires_at = license
+                        .expires_at
+                        .map(|expires_at| DateTime::<Utc>::from_timestamp(expires_at, 0).unwrap_or_default().to_rfc3339());
+                    let remaining = license.expires_at.map(|expires_at| (expires_at - now).max(0));
+                    let tier = license.tier.as_ref().map(|tier| tier.name.clone());
+
+                    table.row(vec![
+                        license.product_id.clone(),
+                        name.clone(),
+                        expires_at.clone().unwrap_or_else(|| "never".to_string()),
+                        match remaining {
+                            Some(remaining) if remaining > 0 => output::duration(remaining),
+                            Some(_) => "expired".to_string(),
+                            None => "lifetime".to_string(),
+                        },
+                        tier.clone().unwrap_or_default(),
+                    ]);
+                    rows.push(json!({
+                        "product_id": license.product_id,
+                        "product_name": name,
+                        "tier": tier,
+                        "entitlements": license.tier.map(|tier| tier.entitlements).unwrap_or_default(),
+                        "expires_at": expires_at,
+                        "time_remaining_seconds": remaining,
+                    }));
+                }
+
+                let linked_accounts = self.repos.identities.for_user(&user.id).await.map_err(db_error)?;
+                let mut identity = format!("{} ({}, {}", user.email, user.id, role_name(user.role));
+                if user.banned {
+                    identity += ", banned";
+                }
+                for account in &linked_accounts {
+                    let name = account.username.as_deref().unwrap_or(&account.external_id);
+                    identity += &format!(", {} {} [{}]", account.provider, name, account.external_id);
+                }
+                identity += ")";
+
+                Ok(Output::table(table, json!({
+                    "user": {
+                        "id": user.id,
+                        "email": user.email,
+                        "role": user.role,
+                        "banned": user.banned,
+                        "device_slots": user.device_slots,
+                        "linked_accounts": linked_accounts,
+                    },
+                    "licenses": rows,
+                }))
+                .with_message(identity))
+            }
+            Command::HwidBan { hwid, reason } => {
+                if !self.repos.bans.ban(&hwid, reason.as_deref(), None).await.map_err(db_error)? {
+                    return Ok(Output::message(format!("HWID {} is already banned", hwid), json!({ "hwid": hwid, "banned": true })));
+                }
+                self.invalidate_banned_hwids().await;
+
+                Ok(Output::message(format!("Banned HWID {}", hwid), json!({ "hwid": hwid, "banned": true, "reason": reason })))
+            }
+            Command::HwidUnban { hwid } => {
+                if !self.repos.bans.unban(&hwid).await.map_err(db_error)? {
+                    return Ok(Output::message(format!("HWID {} isn't banned", hwid), json!({ "hwid": hwid, "banned": false })));
+                }
+                self.invalidate_banned_hwids().await;
+
+                Ok(Output::message(format!("Unbanned HWID {}", hwid), json!({ "hwid": hwid, "banned": false })))
+            }
+            Command::KeyGenerate { product, days, count, tier } => {
+                self.product_exists(&product).await?;
+                if let Some(tier) = &tier {
+                    let tiers = self.repos.products.tiers(&product).await.map_err(db_error)?;
+                    if !tiers.iter().any(|known| &known.name == tier) {
+                        return Err(format!("Product '{}' has no tier '{}', see product tiers {}", product, tier, product));
+                    }
+                }
+
+                // Collisions are retried the same way the API does
+                const MAX_ATTEMPTS_PER_KEY: i32 = 10;
+                let mut keys = Vec::new();
+                let mut attempts = 0;
+                while keys.len() < count as usize {
+                    if attempts >= count * MAX_ATTEMPTS_PER_KEY {
+                        return Err(format!("Only generated {} out of {} keys due to collisions: {}", keys.len(), count, keys.join(", ")));
+                    }
+                    attempts += 1;
+
+                    let key = generate_random_key(&self.key_prefix);
+                    match self.repos.keys.insert(&key, &product, days.map(|days| days * 24), tier.as_deref()).await {
+                        Ok(true) => keys.push(key),
+                        Ok(false) => {}
+                        Err(err) => return Err(format!("{} after generating {} key(s): {}", db_error(err), keys.len(), keys.join(", "))),
+                    }
+                }
+
+                Ok(output::generated_keys(&product, days, tier.as_deref(), keys))
+            }
+            Command::KeyExport { product } => {
+                self.product_exists(&product).await?;
+                let keys = self.repos.keys.unused(&product).await.map_err(db_error)?;
+
+                let mut table = Table::new(vec!["KEY", "HOURS", "TIER"]);
+                for key in &keys {
+                    let hours = key.time_hours.map_or_else(|| "lifetime".to_string(), |hours| hours.to_string());
+                    table.row(vec![key.key.clone(), hours, key.tier.clone().unwrap_or_default()]);
+                }
+                let rows: Vec<_> = keys
+                    .iter()
+                    .map(|key| {
+                        json!({
+                            "key": key.key,
+                            "product_id": key.product_id,
+                            "time_hours": key.time_hours,
+                            "lifetime": key.time_hours.is_none(),
+                            "tier": key.tier,
+                        })
+                    })
+                    .collect();
+
+                Ok(Output::table(table, json!(rows)).with_message(format!("{} unused key(s) for {}", keys.len(), product)))
+            }
+            Command::Compensate { product, hours } => {
+                self.product_exists(&product).await?;
+                let user_ids = self.repos.licenses.extend_all(&product, hours).await.map_err(db_error)?;
+
+                if let Err(err) = self.cache.invalidate_users(&user_ids).await {
+                    warn(format!("Failed to invalidate cached state for compensated users: {}", err));
+                }
+
+                Ok(output::compensated(&product, hours, user_ids.len()))
+            }
+        }
+    }
+
+    /// Look a user up by id, by email when it contains an `@`, or by a linked account as `<provider>:<external id>`
+    async fn user(&self, user: &str) -> Result<User, String> {
+        let user_id = if user.contains('@') {
+            match self.repos.users.find_by_email(user).await.map_err(db_error)? {
+                Some(credentials) => credentials.id,
+                None => return Err(format!("User '{}' not found", user)),
+            }
+        } else if let Some((provider, external_id)) = user.split_once(':') {
+            match self.repos.identities.find_user(provider, external_id).await.map_err(db_error)? {
+                Some(user_id) => user_id,
+                None => return Err(format!("User '{}' not found", user)),
+            }
+        } else {
+            user.to_string()
+        };
+
+        self.repos
+            .users
+            .get(&user_id)
+            .await
+            .map_err(db_error)?
+            .ok_or_else(|| format!("User '{}' not found", user))
+    }
+
+    async fn product_exists(&self, product: &str) -> Result<(), String> {
+        if !self.repos.products.exists(product).await.map_err(db_error)? {
+            return Err(format!("Product '{}' not found", product));
+        }
+        Ok(())
+    }
+
+    async fn set_banned(&self, user: &str, banned: bool) -> Result<Output, String> {
+        let user = self.user(user).await?;
+        if !self.repos.users.set_banned(&user.id, banned).await.map_err(db_error)? {
+            return Err(format!("User '{}' not found", user.id));
+        }
+        // `/auth` reads the ban from the cached user state
+        self.invalidate_user(&user.id).await;
+        let event = if banned { WebhookEvent::UserBanned } else { WebhookEvent::UserUnbanned };
+        self.publish(event, json!({ "user_id": user.id, "email": user.email })).await;
+
+        let action = if banned { "Banned" } else { "Unbanned" };
+        Ok(Output::message(
+            format!("{} {} ({})", action, user.email, user.id),
+            json!({ "id": user.id, "email": user.email, "banned": banned }),
+        ))
+    }
+
+    async fn set_frozen(&self, product: &str, frozen: bool) -> Result<Output, String> {
+        if !self.repos.products.set_frozen(product, frozen).await.map_err(db_error)? {
+            return Err(format!("Product '{}' not found", product));
+        }
+        let event = if frozen { WebhookEvent::ProductFrozen } else { WebhookEvent::ProductUnfrozen };
+        self.publish(event, json!({ "product_id": product })).await;
+
+        let action = if frozen { "Froze" } else { "Unfroze" };
+        Ok(Output::message(format!("{} product {}", action, product), json!({ "id": product, "frozen": frozen })))
+    }
+
+    /// Queued in the database, the server's dispatcher sends it
+    async fn publish(&self, event: WebhookEvent, data: serde_json::Value) {
+        Webhooks::new(self.repos.webhooks.clone()).publish(event, data).await;
+    }
+
+    async fn invalidate_user(&self, user_id: &str) {
+        if let Err(err) = self.cache.invalidate_user(user_id).await {
+            warn(format!("Failed to invalidate cached state for user {}: {}", user_id, err));
+        }
+    }
+
+    /// Drop the cached state of everyone licensed for the product (or just the tier), so `/auth` sees the change
+    async fn invalidate_holders(&self, product: &str, tier: Option<&str>) {
+        let user_ids = match self.repos.licenses.holders(product, tier).await {
+            Ok(user_ids) => user_ids,
+            Err(err) => {
+                warn(format!("Failed to look up the license holders of {}, their cached /auth state expires in up to 5 minutes: {}", product, err));
+                return;
+            }
+        };
+        if let Err(err) = self.cache.invalidate_users(&user_ids).await {
+            warn(format!("Failed to invalidate cached state for the license holders of {}: {}", product, err));
+        }
+    }
+
+    async fn invalidate_banned_hwids(&self) {
+        if let Err(err) = self.cache.invalidate_banned_hwids().await {
+            warn(format!("Failed to invalidate the cached banned HWIDs: {}", err));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use authit::auth::MemoryBlacklist;
+    use authit::repository::HwidPolicy;
+    use authit::repository::{ExternalAccount, MemoryRepository};
+
+    fn admin() -> (Admin, Arc<MemoryRepository>) {
+        let repository = Arc::new(MemoryRepository::new());
+        repository.add_user("user-1", "player@example.com", "hash", Role::User, 2);
+        repository.add_product("game", "Game", HwidPolicy::PerAccount, None);
+
+        let admin = Admin {
+            pool: None,
+            repos: Repositories::memory(repository.clone()),
+            cache: AuthCache::new(None),
+            blacklist: Some(Arc::new(MemoryBlacklist::default())),
+            key_prefix: "TEST-".to_string(),
+        };
+        (admin, repository)
+    }
+
+    #[actix_web::test]
+    async fn users_are_found_by_id_or_email() {
+        let (admin, _) = admin();
+
+        admin.run(Command::UserSetRole { user: "player@example.com".to_string(), role: Role::Support }).await.unwrap();
+        assert_eq!(admin.repos.users.get("user-1").await.unwrap().unwrap().role, Role::Support);
+
+        admin.run(Command::UserBan { user: "user-1".to_string() }).await.unwrap();
+        assert!(admin.repos.users.status("user-1").await.unwrap().unwrap().banned);
+
+        let err = admin.run(Command::UserBan { user: "nobody@example.com".to_string() }).await.err().unwrap();
+        assert!(err.contains("not found"), "{}", err);
+    }
+
+    #[actix_web::test]
+    async fn users_are_found_and_shown_by_linked_account() {
+        let (admin, _) = admin();
+        let account = ExternalAccount {
+            provider: "discord".to_string(),
+            external_id: "80351110224678912".to_string(),
+            username: Some("player".to_string()),
+        };
+        admin.repos.identities.link("user-1", &account).await.unwrap();
+
+        let output = admin.run(Command::UserLicenses { user: "discord:80351110224678912".to_string() }).await.unwrap();
+        assert_eq!(output.json["user"]["id"], "user-1");
+        assert_eq!(output.json["user"]["linked_accounts"][0]["username"], "player");
+        assert!(output.message.unwrap().contains("discord player [80351110224678912]"));
+    }
+
+    #[actix_web::test]
+    async fn set_role_revokes_existing_tokens() {
+        let (admin, _) = admin();
+        let before = Utc::now().timestamp();
+
+        admin.run(Command::UserSetRole { user: "user-1".to_string(), role: Role::Admin }).await.unwrap();
+
+        let (_, revoked_before) = admin.blacklist.as_ref().unwrap().check("token", "user-1").await.unwrap();
+        assert!(revoked_before.is_some_and(|timestamp| timestamp >= before));
+    }
+
+    #[actix_web::test]
+    async fn generated_keys_can_be_exported_and_redeemed() {
+        let (admin, _) = admin();
+
+        let output = admin.run(Command::KeyGenerate { product: "game".to_string(), days: Some(30), count: 3, tier: None }).await.unwrap();
+        let keys = output.json["keys"].as_array().unwrap().clone();
+        assert_eq!(keys.len(), 3);
+        assert!(keys.iter().all(|key| key.as_str().unwrap().starts_with("TEST-")));
+
+        let export = admin.run(Command::KeyExport { product: "game".to_string() }).await.unwrap();
+        assert_eq!(export.table.unwrap().rows.len(), 3);
+        let key = admin.repos.keys.find(keys[0].as_str().unwrap()).await.unwrap().unwrap();
+        assert_eq!(key.time_hours, Some(30 * 24));
+
+        let err = admin.run(Command::KeyExport { product: "other".to_string() }).await.err().unwrap();
+        assert!(err.contains("not found"), "{}", err);
+    }
+
+    #[actix_web::test]
+    async fn licenses_show_expired_ones_too() {
+        let (admin, repository) = admin();
+        let now = Utc::now().timestamp();
+        repository.add_product("tool", "Tool", HwidPolicy::None, None);
+        repository.add_license("user-1", "game", now + 3 * 24 * 3600 + 60);
+        repository.add_license("user-1", "tool", now - 60);
+        repository.add_product("editor", "Editor", HwidPolicy::None, None);
+        admin.repos.licenses.assign("user-1", "editor", None, None).await.unwrap();
+
+        let output = admin.run(Command::UserLicenses { user: "user-1".to_string() }).await.unwrap();
+        let table = output.table.unwrap();
+        assert_eq!(table.rows.len(), 3);
+        assert!(table.rows.iter().any(|row| row[0] == "game" && row[3] == "3d 0h"));
+        assert!(table.rows.iter().any(|row| row[0] == "tool" && row[3] == "expired"));
+        assert!(table.rows.iter().any(|row| row[0] == "editor" && row[2] == "never" && row[3] == "lifetime"));
+        assert_eq!(output.json["user"]["email"], "player@example.com");
+    }
+
+    #[actix_web::test]
+    async fn products_and_hwid_bans() {
+        let (admin, _) = admin();
+
+        let create = Command::ProductCreate {
+            id: "new-game".to_string(),
+            name: "New Game".to_string(),
+            hwid_policy: HwidPolicy::Devices,
+            device_limit: Some(3),
+            max_sessions: None,
+        };
+        admin.run(create).await.unwrap();
+        let products = admin.run(Command::ProductList).await.unwrap();
+        assert_eq!(products.json[1]["id"], "new-game");
+        assert_eq!(products.json[1]["hwid_device_limit"], 3);
+
+        let update = Command::ProductUpdate { id: "new-game".to_string(), max_sessions: Some(Some(2)), hwid_policy: None, device_limit: None };
+        assert_eq!(admin.run(update).await.unwrap().json["max_sessions"], 2);
+        let update = Command::ProductUpdate {
+            id: "new-game".to_string(),
+            max_sessions: Some(None),
+            hwid_policy: Some(HwidPolicy::PerProduct),
+            device_limit: None,
+        };
+        admin.run(update).await.unwrap();
+        let products = admin.run(Command::ProductList).await.unwrap();
+        assert_eq!(products.json[1]["max_sessions"], serde_json::Value::Null);
+        assert_eq!(products.json[1]["hwid_policy"], "PerProduct");
+        assert_eq!(products.json[1]["hwid_device_limit"], serde_json::Value::Null);
+
+        admin.run(Command::HwidBan { hwid: "hwid-a".to_string(), reason: Some("Cheating".to_string()) }).await.unwrap();
+        assert!(admin.repos.bans.is_hwid_banned("hwid-a").await.unwrap());
+        admin.run(Command::HwidUnban { hwid: "hwid-a".to_string() }).await.unwrap();
+        assert!(!admin.repos.bans.is_hwid_banned("hwid-a").await.unwrap());
+    }
+}
@@ -0,0 +1,70 @@
+//! Administration without crafting API requests by hand: products, users, roles, keys, bans and
+//! compensation, run against the database directly or through the API with an admin token.
+
+use std::io::Write;
+
+mod api;
+mod args;
+mod database;
+mod output;
+
+use args::{Command, Target, USAGE};
+
+/// Prompt for the password on stderr so stdout stays clean for `--json`
+fn read_password() -> Result<String, String> {
+    eprint!("Password: ");
+    std::io::stderr().flush().ok();
+
+    let mut password = String::new();
+    std::io::stdin()
+        .read_line(&mut password)
+        .map_err(|err| format!("Failed to read the password: {}", err))?;
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
+
+#[actix_web::main]
+async fn main() {
+    let args = match args::parse(std::env::args().skip(1).collect(), |name| std::env::var(name).ok()) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let mut command = args.command;
+    match &mut command {
+        Command::Help => {
+            println!("{}", USAGE);
+            return;
+        }
+        Command::UserCreate { password: password @ None, .. } => match read_password() {
+            Ok(read) => *password = Some(read),
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        },
+        _ => {}
+    }
+
+    let result = match args.target {
+        Target::Api { url, token } => {
+            let client = authit_client::Client::new(url);
+            client.set_token(token);
+            api::run(&client, command).await
+        }
+        Target::Database(url) => match database::connect(url, command == Command::Migrate).await {
+            Ok(admin) => admin.run(command).await,
+            Err(err) => Err(err),
+        },
+    };
+
+    match result {
+        Ok(output) => output.print(args.json),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
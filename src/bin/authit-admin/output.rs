@@ -0,0 +1,122 @@
+use serde_json::{json, Value};
+
+/// What a command prints, as a table and message or as JSON with `--json`
+pub struct Output {
+    pub message: Option<String>,
+    pub table: Option<Table>,
+    pub json: Value,
+}
+
+pub struct Table {
+    pub columns: Vec<&'static str>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Output {
+    pub fn message(message: impl Into<String>, json: Value) -> Self {
+        Self { message: Some(message.into()), table: None, json }
+    }
+
+    pub fn table(table: Table, json: Value) -> Self {
+        Self { message: None, table: Some(table), json }
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    pub fn print(&self, json: bool) {
+        if json {
+            println!("{}", serde_json::to_string_pretty(&self.json).unwrap_or_default());
+            return;
+        }
+
+        if let Some(table) = &self.table {
+            print!("{}", table.render());
+        }
+        if let Some(message) = &self.message {
+            println!("{}", message);
+        }
+    }
+}
+
+impl Table {
+    pub fn new(columns: Vec<&'static str>) -> Self {
+        Self { columns, rows: Vec::new() }
+    }
+
+    pub fn row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    /// Columns padded to their widest cell, one line per row
+    pub fn render(&self) -> String {
+        let mut widths: Vec<usize> = self.columns.iter().map(|column| column.chars().count()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let line = |cells: Vec<&str>| {
+            let padded: Vec<String> = cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect();
+            format!("{}\n", padded.join("  ").trim_end())
+        };
+
+        let mut rendered = line(self.columns.clone());
+        for row in &self.rows {
+            rendered += &line(row.iter().map(String::as_str).collect());
+        }
+        rendered
+    }
+}
+
+/// Shared by the database and API backends so both print generated keys the same way
+/// `None` days for lifetime keys
+pub fn generated_keys(product: &str, days: Option<i64>, tier: Option<&str>, keys: Vec<String>) -> Output {
+    let mut table = Table::new(vec!["KEY"]);
+    for key in &keys {
+        table.row(vec![key.clone()]);
+    }
+    let mut message = match days {
+        Some(days) => format!("Generated {} key(s) for {} worth {} day(s)", keys.len(), product, days),
+        None => format!("Generated {} lifetime key(s) for {}", keys.len(), product),
+    };
+    if let Some(tier) = tier {
+        message += &format!(" of {}", tier);
+    }
+
+    Output::table(table, json!({ "product_id": product, "time_days": days, "lifetime": days.is_none(), "tier": tier, "keys": keys })).with_message(message)
+}
+
+pub fn compensated(product: &str, hours: i64, users: usize) -> Output {
+    Output::message(
+        format!("Extended {} license(s) for {} by {} hour(s)", users, product, hours),
+        json!({ "product_id": product, "time_hours": hours, "users_compensated": users }),
+    )
+}
+
+/// Whole days and hours, e.g. `3d 4h`
+pub fn duration(seconds: i64) -> String {
+    let hours = seconds.max(0) / 3600;
+    format!("{}d {}h", hours / 24, hours % 24)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_columns_line_up() {
+        let mut table = Table::new(vec!["ID", "NAME"]);
+        table.row(vec!["marvel-rivals".to_string(), "Marvel Rivals".to_string()]);
+        table.row(vec!["game".to_string(), String::new()]);
+
+        assert_eq!(table.render(), "ID             NAME\nmarvel-rivals  Marvel Rivals\ngame\n");
+    }
+}
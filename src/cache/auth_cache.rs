@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::auth::HwidComponents;
+use crate::repository::HwidPolicy;
+
+/// Safety net in case an invalidation is missed (e.g. a manual DB edit)
+const USER_STATE_TTL_SECONDS: u64 = 300;
+const BANNED_HWIDS_TTL_SECONDS: i64 = 300;
+
+/// Stores a user's state only if no invalidation bumped their generation since it was read
+/// KEYS: state key, generation key
+/// ARGV: generation read before loading the state, state json, ttl
+const SET_USER_STATE_SCRIPT: &str = r"
+if (redis.call('GET', KEYS[2]) or '0') ~= ARGV[1] then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[2], 'EX', ARGV[3])
+return 1
+";
+
+const BANNED_HWIDS_KEY: &str = "cache:banned_hwids";
+const BANNED_HWIDS_LOADED_KEY: &str = "cache:banned_hwids:loaded";
+
+/// Everything `/auth` needs to know about a user, cached as a single JSON value
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedUserState {
+    pub banned: bool,
+    /// Devices bound to the account
+    pub devices: Vec<CachedDevice>,
+    pub device_slots: i32,
+    /// product_id -> license
+    pub licenses: HashMap<String, CachedLicense>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedDevice {
+    pub device_id: String,
+    /// `None` for account wide devices
+    pub product_id: Option<String>,
+    pub hwid: String,
+    pub components: Option<HwidComponents>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedLicense {
+    /// Unix timestamp, `None` for lifetime licenses
+    pub expires_at: Option<i64>,
+    pub max_sessions: Option<i32>,
+    pub hwid_policy: HwidPolicy,
+    pub hwid_device_limit: Option<i32>,
+    pub tier: Option<String>,
+    pub entitlements: Vec<String>,
+}
+
+/// Without Redis (the in-memory server) every lookup misses and every write is dropped
+#[derive(Clone)]
+pub struct AuthCache {
+    redis: Option<ConnectionManager>,
+}
+
+impl AuthCache {
+    pub fn new(redis: Option<ConnectionManager>) -> Self {
+        Self { redis }
+    }
+
+    fn user_key(user_id: &str) -> String {
+        format!("cache:user:{}", user_id)
+    }
+
+    /// Bumped by every invalidation, outlives any load that read it
+    fn generation_key(user_id: &str) -> String {
+        format!("cache:user:{}:generation", user_id)
+    }
+
+    /// Get the cached state for a user, `None` on cache miss
+    pub async fn get_user_state(&self, user_id: &str) -> Result<Option<CachedUserState>, redis::RedisError> {
+        let Some(mut conn) = self.redis.clone() else {
+            return Ok(None);
+        };
+
+        let raw: Option<String> = conn.get(Self::user_key(user_id)).await?;
+
+        // A value we can't parse is treated as a miss and overwritten on the next store
+        Ok(raw.and_then(|raw| serde_json::from_str(&raw).ok()))
+    }
+
+    /// Read before loading a user's state from the database, and passed to `set_user_state` after
+    pub async fn user_generation(&self, user_id: &str) -> Result<i64, redis::RedisError> {
+        let Some(mut conn) = self.redis.clone() else {
+            return Ok(0);
+        };
+
+        let generation: Option<i64> = conn.get(Self::generation_key(user_id)).await?;
+
+        Ok(generation.unwrap_or(0))
+    }
+
+    /// Store the state for a user, loaded after reading `generation`
+    /// Returns false if the user was invalidated in the meantime, the state may be stale then and isn't stored
+    pub async fn set_user_state(&self, user_id: &str, state: &CachedUserState, generation: i64) -> Result<bool, redis::RedisError> {
+        let Some(mut conn) = self.redis.clone() else {
+            return Ok(true);
+        };
+
+        let raw = serde_json::to_string(state).unwrap_or_default();
+        let stored: i32 = redis::Script::new(SET_USER_STATE_SCRIPT)
+            .key(Self::user_key(user_id))
+            .key(Self::generation_key(user_id))
+            .arg(generation)
+            .arg(raw)
+            .arg(USER_STATE_TTL_SECONDS)
+            .invoke_async(&mut conn)
+            .await?;
+
+        Ok(stored == 1)
+    }
+
+    /// Drop the cached state for a user, forcing the next lookup to hit Postgres
+    /// Must be called whenever ban status, HWID, licenses or role change for the user
+    pub async fn invalidate_user(&self, user_id: &str) -> Result<(), redis::RedisError> {
+        let Some(mut conn) = self.redis.clone() else {
+            return Ok(());
+        };
+
+        let _: () = Self::invalidation(&[user_id.to_string()]).query_async(&mut conn).await?;
+        info!("Invalidated cached auth state for user {}", user_id);
+
+        Ok(())
+    }
+
+    /// Drop the cached state for several users at once (e.g. after a compensation)
+    pub async fn invalidate_users(&self, user_ids: &[String]) -> Result<(), redis::RedisError> {
+        if user_ids.is_empty() {
+            return Ok(());
+        }
+
+        let Some(mut conn) = self.redis.clone() else {
+            return Ok(());
+        };
+
+        let _: () = Self::invalidation(user_ids).query_async(&mut conn).await?;
+        info!("Invalidated cached auth state for {} user(s)", user_ids.len());
+
+        Ok(())
+    }
+
+    /// Deletes the states and bumps the generations, so loads that started before can't store what they read
+    fn invalidation(user_ids: &[String]) -> redis::Pipeline {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for user_id in user_ids {
+            pipe.del(Self::user_key(user_id))
+                .incr(Self::generation_key(user_id), 1)
+                .expire(Self::generation_key(user_id), USER_STATE_TTL_SECONDS as i64);
+        }
+        pipe
+    }
+
+    /// Check the cached banned HWID set
+    /// Returns `None` if the set hasn't been loaded yet (or has expired)
+    pub async fn is_hwid_banned(&self, hwid: &str) -> Result<Option<bool>, redis::RedisError> {
+        let Some(mut conn) = self.redis.clone() else {
+            return Ok(None);
+        };
+
+        let (loaded, banned): (bool, bool) = redis::pipe()
+            .exists(BANNED_HWIDS_LOADED_KEY)
+            .sismember(BANNED_HWIDS_KEY, hwid)
+            .query_async(&mut conn)
+            .await?;
+
+        if loaded {
+            Ok(Some(banned))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Replace the cached banned HWID set with the full list from the database
+    pub async fn set_banned_hwids(&self, hwids: &[String]) -> Result<(), redis::RedisError> {
+        let Some(mut conn) = self.redis.clone() else {
+            return Ok(());
+        };
+
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(BANNED_HWIDS_KEY);
+        if !hwids.is_empty() {
+            pipe.sadd(BANNED_HWIDS_KEY, hwids)
+                .expire(BANNED_HWIDS_KEY, BANNED_HWIDS_TTL_SECONDS);
+        }
+        pipe.set_ex(BANNED_HWIDS_LOADED_KEY, "1", BANNED_HWIDS_TTL_SECONDS as u64);
+
+        let _: () = pipe.query_async(&mut conn).await?;
+
+        Ok(())
+    }
+
+    /// Drop the cached banned HWID set, forcing a reload from Postgres
+    /// Must be called whenever a HWID is banned or unbanned
+    pub async fn invalidate_banned_hwids(&self) -> Result<(), redis::RedisError> {
+        let Some(mut conn) = self.redis.clone() else {
+            return Ok(());
+        };
+
+        let _: () = conn.del(&[BANNED_HWIDS_LOADED_KEY, BANNED_HWIDS_KEY]).await?;
+        info!("Invalidated cached banned HWID set");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,4 @@
+pub mod auth_cache;
+
+// Re-export commonly used items
+pub use auth_cache::{AuthCache, CachedDevice, CachedLicense, CachedUserState};
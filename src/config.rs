@@ -0,0 +1,723 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The secret docker-compose ships with, never acceptable in production
+const DEFAULT_JWT_SECRET: &str = "default-insecure-secret";
+const KNOWN_INSECURE_SECRETS: &[&str] = &[DEFAULT_JWT_SECRET, "your-secret-key-change-in-production"];
+const MIN_PRODUCTION_SECRET_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Environment {
+    #[default]
+    Development,
+    Production,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RedisConfig {
+    pub url: String,
+    /// Connect and per-command timeout
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum JwtAlgorithm {
+    HS256,
+    RS256,
+    EdDSA,
+}
+
+/// A JWT key, `key` holds the HS256 secret, the base64 Ed25519 seed or the path to an RSA private key PEM
+#[derive(Debug, Clone, Deserialize)]
+pub struct JwtKeyConfig {
+    pub kid: String,
+    pub algorithm: JwtAlgorithm,
+    pub key: String,
+}
+
+/// Where revoked JWTs are kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlacklistBackend {
+    /// Shared by every instance
+    #[default]
+    Redis,
+    /// In process, only for a single instance, revocations are lost on restart
+    Memory,
+}
+
+/// What the JWT blacklist check does when Redis can't be reached
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlacklistFailurePolicy {
+    /// Accept the token, revoked tokens work again for the duration of the outage
+    Open,
+    /// Reject every authenticated request with 503
+    #[default]
+    Closed,
+    /// Check the revocations this instance has made or seen recently
+    Local,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Used for HS256 when `jwt_keys` is empty
+    pub jwt_secret: String,
+    /// The first key signs new tokens, all of them verify
+    pub jwt_keys: Vec<JwtKeyConfig>,
+    /// Once `jwt_keys` is set, tokens without a `kid` are still checked against `jwt_secret` until then
+    /// Only meant for the switch to `jwt_keys`, tokens live for 24h so a day after it is enough
+    pub legacy_tokens_until: Option<DateTime<Utc>>,
+    /// `<kid>:<base64 seed>` entries, the first one signs `/auth` responses
+    pub signing_keys: Vec<String>,
+    pub blacklist_backend: BlacklistBackend,
+    /// Only applies to the Redis backend
+    pub blacklist_failure_policy: BlacklistFailurePolicy,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct KeysConfig {
+    /// Prepended to every generated CD key
+    pub prefix: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HwidConfig {
+    pub weights: HashMap<String, f64>,
+    pub match_threshold: f64,
+    /// Components a single match by similarity may change before the device has to be released instead
+    pub max_drifted_components: usize,
+    /// Users can release one device slot per this many hours
+    pub release_cooldown_hours: i64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Verbosity is controlled by `RUST_LOG`, this only picks the output format
+    pub format: LogFormat,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Bearer token `/metrics` requires, if set
+    pub token: Option<String>,
+    /// Serve `/metrics` on this address only (e.g. `127.0.0.1:9100`) instead of the public listener
+    pub bind: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// How often the dispatcher looks for due deliveries
+    pub poll_interval_ms: u64,
+    /// Per request, a receiver that takes longer counts as failed
+    pub timeout_ms: u64,
+    /// Attempts before a delivery is marked failed
+    pub max_attempts: i32,
+    /// Delay before the first retry, doubled for every further one
+    pub retry_base_seconds: i64,
+    pub retry_max_seconds: i64,
+}
+
+/// An OAuth2 provider accounts can be linked to, using the authorization code flow
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OAuthProviderConfig {
+    pub client_id: String,
+    /// Also read from `OAUTH_<NAME>_CLIENT_SECRET`
+    pub client_secret: String,
+    pub authorize_url: String,
+    pub token_url: String,
+    /// Fetched with the access token to learn who the user is
+    pub userinfo_url: String,
+    /// The callback, `<public URL>/api/v1/oauth/<name>/callback`
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    /// Field of the userinfo response holding the account id
+    pub id_field: String,
+    /// Field holding the display name, if there is one
+    pub username_field: Option<String>,
+    /// Whether a linked account can be used to log in
+    pub allow_login: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OAuthConfig {
+    /// By name, e.g. `[oauth.providers.discord]`
+    pub providers: HashMap<String, OAuthProviderConfig>,
+    /// Per request to the provider
+    pub timeout_ms: u64,
+}
+
+/// All runtime settings, loaded once at startup from an optional TOML file and the environment
+///
+/// The file is read from `AUTHIT_CONFIG` (or `./authit.toml` if it exists), environment
+/// variables override it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub environment: Environment,
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub redis: RedisConfig,
+    pub auth: AuthConfig,
+    pub keys: KeysConfig,
+    pub hwid: HwidConfig,
+    pub metrics: MetricsConfig,
+    pub logging: LoggingConfig,
+    pub webhooks: WebhookConfig,
+    pub oauth: OAuthConfig,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    File(String, String),
+    InvalidValue(&'static str, String),
+    Missing(&'static str),
+    Insecure(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::File(path, err) => write!(f, "Failed to read config file {}: {}", path, err),
+            ConfigError::InvalidValue(name, value) => write!(f, "Invalid value for {}: '{}'", name, value),
+            ConfigError::Missing(name) => write!(f, "{} must be set", name),
+            ConfigError::Insecure(reason) => write!(f, "Refusing to start in production: {}", reason),
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".to_string(),
+            port: 5593,
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            max_connections: 5,
+        }
+    }
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        Self {
+            url: "redis://127.0.0.1:6379".to_string(),
+            timeout_ms: 1000,
+        }
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            jwt_secret: DEFAULT_JWT_SECRET.to_string(),
+            jwt_keys: vec![],
+            legacy_tokens_until: None,
+            signing_keys: vec![],
+            blacklist_backend: BlacklistBackend::default(),
+            blacklist_failure_policy: BlacklistFailurePolicy::default(),
+        }
+    }
+}
+
+impl Default for HwidConfig {
+    fn default() -> Self {
+        Self {
+            weights: [("disk", 3.0), ("board", 3.0), ("cpu", 2.0), ("mac", 1.0), ("gpu", 1.0)]
+                .into_iter()
+                .map(|(name, weight)| (name.to_string(), weight))
+                .collect(),
+            match_threshold: 0.7,
+            max_drifted_components: 1,
+            release_cooldown_hours: 168,
+        }
+    }
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_ms: 1000,
+            timeout_ms: 5000,
+            max_attempts: 8,
+            retry_base_seconds: 30,
+            retry_max_seconds: 6 * 3600,
+        }
+    }
+}
+
+impl Default for OAuthConfig {
+    fn default() -> Self {
+        Self {
+            providers: HashMap::new(),
+            timeout_ms: 5000,
+        }
+    }
+}
+
+impl Default for OAuthProviderConfig {
+    fn default() -> Self {
+        Self {
+            client_id: String::new(),
+            client_secret: String::new(),
+            authorize_url: String::new(),
+            token_url: String::new(),
+            userinfo_url: String::new(),
+            redirect_uri: String::new(),
+            scopes: vec![],
+            id_field: "id".to_string(),
+            username_field: Some("username".to_string()),
+            allow_login: false,
+        }
+    }
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+fn parse<T: std::str::FromStr>(name: &'static str, value: String) -> Result<T, ConfigError> {
+    value.trim().parse().map_err(|_| ConfigError::InvalidValue(name, value))
+}
+
+/// Parse `<kid>:<HS256|RS256|EdDSA>:<key>,...`
+fn parse_jwt_keys(raw: &str) -> Result<Vec<JwtKeyConfig>, ConfigError> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let invalid = || ConfigError::InvalidValue("JWT_KEYS", entry.to_string());
+            let (kid, rest) = entry.split_once(':').ok_or_else(invalid)?;
+            let (algorithm, key) = rest.split_once(':').ok_or_else(invalid)?;
+            let algorithm = match algorithm {
+                "HS256" => JwtAlgorithm::HS256,
+                "RS256" => JwtAlgorithm::RS256,
+                "EdDSA" => JwtAlgorithm::EdDSA,
+                _ => return Err(invalid()),
+            };
+
+            Ok(JwtKeyConfig {
+                kid: kid.to_string(),
+                algorithm,
+                key: key.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parse `disk=3,board=3,...`
+fn parse_weights(raw: &str) -> Result<HashMap<String, f64>, ConfigError> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .split_once('=')
+                .and_then(|(name, weight)| Some((name.trim().to_string(), weight.trim().parse::<f64>().ok()?)))
+                .ok_or_else(|| ConfigError::InvalidValue("HWID_COMPONENT_WEIGHTS", entry.to_string()))
+        })
+        .collect()
+}
+
+impl Config {
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = env("AUTHIT_CONFIG").or_else(|| {
+            std::path::Path::new("authit.toml")
+                .exists()
+                .then(|| "authit.toml".to_string())
+        });
+
+        let mut config = match path {
+            Some(path) => {
+                let raw = std::fs::read_to_string(&path)
+                    .map_err(|err| ConfigError::File(path.clone(), err.to_string()))?;
+                toml::from_str(&raw).map_err(|err| ConfigError::File(path.clone(), err.to_string()))?
+            }
+            None => Config::default(),
+        };
+
+        config.apply_env()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        self.apply_vars(env)
+    }
+
+    /// Apply overrides looked up through `env`, the process environment outside of tests
+    fn apply_vars(&mut self, env: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        if let Some(value) = env("AUTHIT_ENV") {
+            self.environment = match value.trim().to_lowercase().as_str() {
+                "development" | "dev" => Environment::Development,
+                "production" | "prod" => Environment::Production,
+                _ => return Err(ConfigError::InvalidValue("AUTHIT_ENV", value)),
+            };
+        }
+        if let Some(value) = env("AUTHIT_HOST") {
+            self.server.host = value;
+        }
+        if let Some(value) = env("AUTHIT_PORT") {
+            self.server.port = parse("AUTHIT_PORT", value)?;
+        }
+        if let Some(value) = env("DATABASE_URL") {
+            self.database.url = value;
+        }
+        if let Some(value) = env("DATABASE_MAX_CONNECTIONS") {
+            self.database.max_connections = parse("DATABASE_MAX_CONNECTIONS", value)?;
+        }
+        if let Some(value) = env("REDIS_URL") {
+            self.redis.url = value;
+        }
+        if let Some(value) = env("REDIS_TIMEOUT_MS") {
+            self.redis.timeout_ms = parse("REDIS_TIMEOUT_MS", value)?;
+        }
+        if let Some(value) = env("JWT_SECRET") {
+            self.auth.jwt_secret = value;
+        }
+        if let Some(value) = env("JWT_KEYS") {
+            self.auth.jwt_keys = parse_jwt_keys(&value)?;
+        }
+        if let Some(value) = env("JWT_LEGACY_UNTIL") {
+            self.auth.legacy_tokens_until = Some(parse("JWT_LEGACY_UNTIL", value)?);
+        }
+        if let Some(value) = env("AUTH_SIGNING_KEYS") {
+            self.auth.signing_keys = value
+                .split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Some(value) = env("BLACKLIST_BACKEND") {
+            self.auth.blacklist_backend = match value.trim().to_lowercase().as_str() {
+                "redis" => BlacklistBackend::Redis,
+                "memory" => BlacklistBackend::Memory,
+                _ => return Err(ConfigError::InvalidValue("BLACKLIST_BACKEND", value)),
+            };
+        }
+        if let Some(value) = env("BLACKLIST_FAILURE_POLICY") {
+            self.auth.blacklist_failure_policy = match value.trim().to_lowercase().as_str() {
+                "open" => BlacklistFailurePolicy::Open,
+                "closed" => BlacklistFailurePolicy::Closed,
+                "local" => BlacklistFailurePolicy::Local,
+                _ => return Err(ConfigError::InvalidValue("BLACKLIST_FAILURE_POLICY", value)),
+            };
+        }
+        if let Some(value) = env("KEY_PREFIX") {
+            self.keys.prefix = value;
+        }
+        if let Some(value) = env("HWID_COMPONENT_WEIGHTS") {
+            self.hwid.weights = parse_weights(&value)?;
+        }
+        if let Some(value) = env("HWID_MATCH_THRESHOLD") {
+            self.hwid.match_threshold = parse("HWID_MATCH_THRESHOLD", value)?;
+        }
+        if let Some(value) = env("HWID_MAX_DRIFTED_COMPONENTS") {
+            self.hwid.max_drifted_components = parse("HWID_MAX_DRIFTED_COMPONENTS", value)?;
+        }
+        if let Some(value) = env("DEVICE_RELEASE_COOLDOWN_HOURS") {
+            self.hwid.release_cooldown_hours = parse("DEVICE_RELEASE_COOLDOWN_HOURS", value)?;
+        }
+        if let Some(value) = env("LOG_FORMAT") {
+            self.logging.format = match value.trim().to_lowercase().as_str() {
+                "text" => LogFormat::Text,
+                "json" => LogFormat::Json,
+                _ => return Err(ConfigError::InvalidValue("LOG_FORMAT", value)),
+            };
+        }
+        if let Some(value) = env("METRICS_TOKEN") {
+            self.metrics.token = Some(value);
+        }
+        if let Some(value) = env("METRICS_BIND") {
+            self.metrics.bind = Some(value);
+        }
+        if let Some(value) = env("WEBHOOK_POLL_INTERVAL_MS") {
+            self.webhooks.poll_interval_ms = parse("WEBHOOK_POLL_INTERVAL_MS", value)?;
+        }
+        if let Some(value) = env("WEBHOOK_TIMEOUT_MS") {
+            self.webhooks.timeout_ms = parse("WEBHOOK_TIMEOUT_MS", value)?;
+        }
+        if let Some(value) = env("WEBHOOK_MAX_ATTEMPTS") {
+            self.webhooks.max_attempts = parse("WEBHOOK_MAX_ATTEMPTS", value)?;
+        }
+        if let Some(value) = env("WEBHOOK_RETRY_BASE_SECONDS") {
+            self.webhooks.retry_base_seconds = parse("WEBHOOK_RETRY_BASE_SECONDS", value)?;
+        }
+        if let Some(value) = env("WEBHOOK_RETRY_MAX_SECONDS") {
+            self.webhooks.retry_max_seconds = parse("WEBHOOK_RETRY_MAX_SECONDS", value)?;
+        }
+        if let Some(value) = env("OAUTH_TIMEOUT_MS") {
+            self.oauth.timeout_ms = parse("OAUTH_TIMEOUT_MS", value)?;
+        }
+        // Secrets are kept out of the file, providers themselves are only configured there
+        for (name, provider) in self.oauth.providers.iter_mut() {
+            if let Some(value) = env(&format!("OAUTH_{}_CLIENT_SECRET", name.to_uppercase().replace('-', "_"))) {
+                provider.client_secret = value;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.database.url.is_empty() {
+            return Err(ConfigError::Missing("DATABASE_URL"));
+        }
+        if self.database.max_connections == 0 {
+            return Err(ConfigError::InvalidValue("DATABASE_MAX_CONNECTIONS", "0".to_string()));
+        }
+        if self.auth.jwt_secret.is_empty() {
+            return Err(ConfigError::Missing("JWT_SECRET"));
+        }
+        if self.redis.timeout_ms == 0 {
+            return Err(ConfigError::InvalidValue("REDIS_TIMEOUT_MS", "0".to_string()));
+        }
+        if !(0.0..=1.0).contains(&self.hwid.match_threshold) {
+            return Err(ConfigError::InvalidValue("HWID_MATCH_THRESHOLD", self.hwid.match_threshold.to_string()));
+        }
+        if self.hwid.max_drifted_components == 0 {
+            return Err(ConfigError::InvalidValue("HWID_MAX_DRIFTED_COMPONENTS", "0".to_string()));
+        }
+        if self.hwid.release_cooldown_hours < 0 {
+            return Err(ConfigError::InvalidValue("DEVICE_RELEASE_COOLDOWN_HOURS", self.hwid.release_cooldown_hours.to_string()));
+        }
+        if self.webhooks.poll_interval_ms == 0 {
+            return Err(ConfigError::InvalidValue("WEBHOOK_POLL_INTERVAL_MS", "0".to_string()));
+        }
+        if self.webhooks.timeout_ms == 0 {
+            return Err(ConfigError::InvalidValue("WEBHOOK_TIMEOUT_MS", "0".to_string()));
+        }
+        if self.webhooks.max_attempts <= 0 {
+            return Err(ConfigError::InvalidValue("WEBHOOK_MAX_ATTEMPTS", self.webhooks.max_attempts.to_string()));
+        }
+        if self.webhooks.retry_base_seconds <= 0 || self.webhooks.retry_max_seconds < self.webhooks.retry_base_seconds {
+            return Err(ConfigError::InvalidValue("WEBHOOK_RETRY_BASE_SECONDS", self.webhooks.retry_base_seconds.to_string()));
+        }
+        if !self.oauth.providers.is_empty() && self.oauth.timeout_ms == 0 {
+            return Err(ConfigError::InvalidValue("OAUTH_TIMEOUT_MS", "0".to_string()));
+        }
+        for (name, provider) in &self.oauth.providers {
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+                return Err(ConfigError::InvalidValue("oauth.providers", name.clone()));
+            }
+            if provider.client_id.is_empty() || provider.client_secret.is_empty() || provider.id_field.is_empty() {
+                return Err(ConfigError::InvalidValue("oauth.providers", format!("{} needs client_id, client_secret and id_field", name)));
+            }
+            for url in [&provider.authorize_url, &provider.token_url, &provider.userinfo_url, &provider.redirect_uri] {
+                if !(url.starts_with("https://") || url.starts_with("http://")) {
+                    return Err(ConfigError::InvalidValue("oauth.providers", format!("{}: '{}' is not an http(s) URL", name, url)));
+                }
+            }
+        }
+        if let Some((name, weight)) = self.hwid.weights.iter().find(|(_, weight)| **weight <= 0.0) {
+            return Err(ConfigError::InvalidValue("HWID_COMPONENT_WEIGHTS", format!("{}={}", name, weight)));
+        }
+
+        if self.environment == Environment::Production {
+            // JWT_SECRET signs without jwt_keys and verifies legacy tokens, so it's checked along with any HS256 keys
+            let mut secrets = vec![("JWT_SECRET", &self.auth.jwt_secret)];
+            secrets.extend(self.auth.jwt_keys
+                .iter()
+                .filter(|key| key.algorithm == JwtAlgorithm::HS256)
+                .map(|key| (key.kid.as_str(), &key.key)));
+
+            for (name, secret) in secrets {
+                if KNOWN_INSECURE_SECRETS.contains(&secret.as_str()) {
+                    return Err(ConfigError::Insecure(format!("{} is set to a default value", name)));
+                }
+                if secret.len() < MIN_PRODUCTION_SECRET_LENGTH {
+                    return Err(ConfigError::Insecure(format!("{} must be at least {} characters", name, MIN_PRODUCTION_SECRET_LENGTH)));
+                }
+            }
+            if self.auth.signing_keys.is_empty() {
+                return Err(ConfigError::Insecure("AUTH_SIGNING_KEYS must be set so signed responses survive restarts".to_string()));
+            }
+            if let Some(name) = self.oauth.providers.iter()
+                .find(|(_, provider)| !provider.token_url.starts_with("https://") || !provider.userinfo_url.starts_with("https://"))
+                .map(|(name, _)| name) {
+                return Err(ConfigError::Insecure(format!("OAuth provider {} must use https", name)));
+            }
+            if self.metrics.token.is_none() && self.metrics.bind.is_none() {
+                return Err(ConfigError::Insecure("METRICS_TOKEN or METRICS_BIND must be set to protect /metrics".to_string()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    /// A production config that passes validation
+    fn production() -> Config {
+        let mut config = Config {
+            environment: Environment::Production,
+            ..Config::default()
+        };
+        config.database.url = "postgres://localhost/authit".to_string();
+        config.auth.jwt_secret = SECRET.to_string();
+        config.auth.signing_keys = vec!["k1:c2VlZA==".to_string()];
+        config.metrics.token = Some("metrics-token".to_string());
+        config
+    }
+
+    fn provider(url: &str) -> OAuthProviderConfig {
+        OAuthProviderConfig {
+            client_id: "client".to_string(),
+            client_secret: "secret".to_string(),
+            authorize_url: format!("{}/authorize", url),
+            token_url: format!("{}/token", url),
+            userinfo_url: format!("{}/userinfo", url),
+            redirect_uri: "https://authit.example/api/v1/oauth/discord/callback".to_string(),
+            ..OAuthProviderConfig::default()
+        }
+    }
+
+    fn with_vars(vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let vars: HashMap<String, String> = vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        let mut config = Config::default();
+        config.apply_vars(|name| vars.get(name).cloned())?;
+        Ok(config)
+    }
+
+    #[test]
+    fn production_refuses_default_or_short_jwt_secrets() {
+        assert!(production().validate().is_ok());
+
+        for secret in [DEFAULT_JWT_SECRET, "your-secret-key-change-in-production", "too-short"] {
+            let mut config = production();
+            config.auth.jwt_secret = secret.to_string();
+            assert!(matches!(config.validate(), Err(ConfigError::Insecure(_))), "{}", secret);
+
+            // Development only needs one to be set
+            config.environment = Environment::Development;
+            assert!(config.validate().is_ok());
+        }
+
+        let mut config = production();
+        config.auth.jwt_keys = vec![JwtKeyConfig {
+            kid: "k1".to_string(),
+            algorithm: JwtAlgorithm::HS256,
+            key: "short".to_string(),
+        }];
+        assert!(matches!(config.validate(), Err(ConfigError::Insecure(reason)) if reason.contains("k1")));
+    }
+
+    #[test]
+    fn production_requires_signing_keys() {
+        let mut config = production();
+        config.auth.signing_keys.clear();
+        assert!(matches!(config.validate(), Err(ConfigError::Insecure(reason)) if reason.contains("AUTH_SIGNING_KEYS")));
+    }
+
+    #[test]
+    fn production_requires_protected_metrics() {
+        let mut config = production();
+        config.metrics.token = None;
+        assert!(matches!(config.validate(), Err(ConfigError::Insecure(reason)) if reason.contains("METRICS_TOKEN")));
+
+        config.metrics.bind = Some("127.0.0.1:9100".to_string());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn oauth_urls_must_be_http_and_https_in_production() {
+        let mut config = production();
+        config.oauth.providers.insert("discord".to_string(), provider("ftp://discord.example"));
+        assert!(matches!(config.validate(), Err(ConfigError::InvalidValue("oauth.providers", _))));
+
+        config.oauth.providers.insert("discord".to_string(), provider("http://discord.example"));
+        assert!(matches!(config.validate(), Err(ConfigError::Insecure(reason)) if reason.contains("discord")));
+
+        config.environment = Environment::Development;
+        assert!(config.validate().is_ok());
+
+        config.environment = Environment::Production;
+        config.oauth.providers.insert("discord".to_string(), provider("https://discord.example"));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn bad_enum_values_are_rejected() {
+        for (name, value) in [
+            ("AUTHIT_ENV", "staging"),
+            ("BLACKLIST_BACKEND", "disk"),
+            ("BLACKLIST_FAILURE_POLICY", "sometimes"),
+            ("LOG_FORMAT", "xml"),
+        ] {
+            assert!(matches!(with_vars(&[(name, value)]), Err(ConfigError::InvalidValue(field, _)) if field == name));
+        }
+        assert!(matches!(with_vars(&[("JWT_KEYS", "k1:HS512:secret")]), Err(ConfigError::InvalidValue("JWT_KEYS", _))));
+
+        let config = with_vars(&[("AUTHIT_ENV", "prod"), ("BLACKLIST_FAILURE_POLICY", "Local")]).unwrap();
+        assert_eq!(config.environment, Environment::Production);
+        assert_eq!(config.auth.blacklist_failure_policy, BlacklistFailurePolicy::Local);
+
+        assert!(toml::from_str::<Config>("environment = \"staging\"").is_err());
+        assert!(toml::from_str::<Config>("[auth]\nblacklist_failure_policy = \"sometimes\"").is_err());
+    }
+
+    #[test]
+    fn fingerprint_weights_must_be_positive_numbers() {
+        assert!(matches!(
+            with_vars(&[("HWID_COMPONENT_WEIGHTS", "disk=3,board=heavy")]),
+            Err(ConfigError::InvalidValue("HWID_COMPONENT_WEIGHTS", _))
+        ));
+
+        for weights in ["disk=3,board=0", "disk=-1"] {
+            let mut config = production();
+            config.hwid.weights = parse_weights(weights).unwrap();
+            assert!(matches!(config.validate(), Err(ConfigError::InvalidValue("HWID_COMPONENT_WEIGHTS", _))), "{}", weights);
+        }
+
+        let mut config = production();
+        config.hwid.match_threshold = 1.5;
+        assert!(matches!(config.validate(), Err(ConfigError::InvalidValue("HWID_MATCH_THRESHOLD", _))));
+    }
+}
@@ -0,0 +1,220 @@
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+use std::fmt;
+
+/// Every error the API can return, each with a stable machine-readable code
+/// Clients should branch on `code`, `message` is for humans and may change
+#[derive(Debug)]
+pub enum ApiError {
+    // Malformed requests
+    InvalidRequest(String),
+    RouteNotFound,
+
+    // JWT
+    TokenMissing,
+    TokenInvalid,
+    TokenExpired,
+    PermissionDenied(String),
+
+    // Accounts
+    InvalidCredentials,
+    UserNotFound,
+    SelfDemotion,
+    AccountBanned,
+
+    // OAuth account linking
+    OAuthProviderNotFound,
+    OAuthStateInvalid,
+    OAuthProviderError,
+    AccountNotLinked,
+    AccountAlreadyLinked,
+    LinkNotFound,
+
+    // Hardware
+    HwidBanned,
+    HwidMismatch,
+    HwidBindFailed,
+    DeviceNotFound,
+    DeviceReleaseCooldown { retry_after: i64 },
+
+    // /auth protocol
+    ChallengeInvalid,
+    SessionLimitReached,
+    SessionNotFound,
+
+    // Products, licenses and keys
+    ProductNotFound,
+    LicenseNotFound,
+    LicenseExpired,
+    KeyInvalid,
+    KeyGenerationFailed { keys: Vec<String>, message: String },
+    TierNotFound,
+    TierDowngrade,
+    LicenseLifetime,
+
+    // Webhooks
+    WebhookNotFound,
+    DeliveryNotFound,
+
+    // Readiness, carries the per-dependency report
+    ServiceUnavailable(serde_json::Value),
+
+    Internal,
+}
+
+pub use authit_types::{ErrorBody, ErrorCode};
+
+impl ApiError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ApiError::InvalidRequest(_) => ErrorCode::InvalidRequest,
+            ApiError::RouteNotFound => ErrorCode::RouteNotFound,
+            ApiError::TokenMissing => ErrorCode::TokenMissing,
+            ApiError::TokenInvalid => ErrorCode::TokenInvalid,
+            ApiError::TokenExpired => ErrorCode::TokenExpired,
+            ApiError::PermissionDenied(_) => ErrorCode::PermissionDenied,
+            ApiError::InvalidCredentials => ErrorCode::InvalidCredentials,
+            ApiError::UserNotFound => ErrorCode::UserNotFound,
+            ApiError::SelfDemotion => ErrorCode::SelfDemotion,
+            ApiError::AccountBanned => ErrorCode::AccountBanned,
+            ApiError::OAuthProviderNotFound => ErrorCode::OAuthProviderNotFound,
+            ApiError::OAuthStateInvalid => ErrorCode::OAuthStateInvalid,
+            ApiError::OAuthProviderError => ErrorCode::OAuthProviderError,
+            ApiError::AccountNotLinked => ErrorCode::AccountNotLinked,
+            ApiError::AccountAlreadyLinked => ErrorCode::AccountAlreadyLinked,
+            ApiError::LinkNotFound => ErrorCode::LinkNotFound,
+            ApiError::HwidBanned => ErrorCode::HwidBanned,
+            ApiError::HwidMismatch => ErrorCode::HwidMismatch,
+            ApiError::HwidBindFailed => ErrorCode::HwidBindFailed,
+            ApiError::DeviceNotFound => ErrorCode::DeviceNotFound,
+            ApiError::DeviceReleaseCooldown { .. } => ErrorCode::DeviceReleaseCooldown,
+            ApiError::ChallengeInvalid => ErrorCode::ChallengeInvalid,
+            ApiError::SessionLimitReached => ErrorCode::SessionLimitReached,
+            ApiError::SessionNotFound => ErrorCode::SessionNotFound,
+            ApiError::ProductNotFound => ErrorCode::ProductNotFound,
+            ApiError::LicenseNotFound => ErrorCode::LicenseNotFound,
+            ApiError::LicenseExpired => ErrorCode::LicenseExpired,
+            ApiError::KeyInvalid => ErrorCode::KeyInvalid,
+            ApiError::KeyGenerationFailed { .. } => ErrorCode::KeyGenerationFailed,
+            ApiError::TierNotFound => ErrorCode::TierNotFound,
+            ApiError::TierDowngrade => ErrorCode::TierDowngrade,
+            ApiError::LicenseLifetime => ErrorCode::LicenseLifetime,
+            ApiError::WebhookNotFound => ErrorCode::WebhookNotFound,
+            ApiError::DeliveryNotFound => ErrorCode::DeliveryNotFound,
+            ApiError::ServiceUnavailable(_) => ErrorCode::ServiceUnavailable,
+            ApiError::Internal => ErrorCode::InternalError,
+        }
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            ApiError::DeviceReleaseCooldown { retry_after } => Some(serde_json::json!({ "retry_after": retry_after })),
+            ApiError::KeyGenerationFailed { keys, .. } => Some(serde_json::json!({ "keys": keys })),
+            ApiError::ServiceUnavailable(report) => Some(report.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::InvalidRequest(message) => write!(f, "{}", message),
+            ApiError::RouteNotFound => write!(f, "Route not found."),
+            ApiError::TokenMissing => write!(f, "Missing authorization token"),
+            ApiError::TokenInvalid => write!(f, "Invalid token"),
+            ApiError::TokenExpired => write!(f, "Token expired"),
+            ApiError::PermissionDenied(message) => write!(f, "{}", message),
+            ApiError::InvalidCredentials => write!(f, "Invalid credentials"),
+            ApiError::UserNotFound => write!(f, "User not found."),
+            ApiError::SelfDemotion => write!(f, "Admins cannot demote themselves."),
+            ApiError::AccountBanned => write!(f, "Your account has been banned. Contact support for more information."),
+            ApiError::OAuthProviderNotFound => write!(f, "Unknown OAuth provider."),
+            ApiError::OAuthStateInvalid => write!(f, "OAuth state is invalid, expired or already used. Start again."),
+            ApiError::OAuthProviderError => write!(f, "The OAuth provider did not complete the sign-in. Try again."),
+            ApiError::AccountNotLinked => write!(f, "No account is linked to this external account. Log in and link it first."),
+            ApiError::AccountAlreadyLinked => write!(f, "This external account is already linked to another user."),
+            ApiError::LinkNotFound => write!(f, "No account is linked for this provider."),
+            ApiError::HwidBanned => write!(f, "Your hardware has been banned. Contact support for more information."),
+            ApiError::HwidMismatch => write!(f, "HWID mismatch and all device slots are in use. Release a device from your account or contact support."),
+            ApiError::HwidBindFailed => write!(f, "Failed to bind HWID - contact support."),
+            ApiError::DeviceNotFound => write!(f, "Device not found."),
+            ApiError::DeviceReleaseCooldown { retry_after } => {
+                write!(f, "You can release another device in {} hours.", (retry_after + 3599) / 3600)
+            }
+            ApiError::ChallengeInvalid => write!(f, "Challenge nonce is invalid, expired or already used. Request a new challenge."),
+            ApiError::SessionLimitReached => write!(f, "Maximum number of concurrent sessions reached for this product. Close another session and try again."),
+            ApiError::SessionNotFound => write!(f, "Session expired or was closed. Authenticate again."),
+            ApiError::ProductNotFound => write!(f, "Product not found."),
+            ApiError::LicenseNotFound => write!(f, "You don't own this product."),
+            ApiError::LicenseExpired => write!(f, "Your license for this product has expired."),
+            ApiError::KeyInvalid => write!(f, "Invalid or already used key."),
+            ApiError::KeyGenerationFailed { message, .. } => write!(f, "{}", message),
+            ApiError::TierNotFound => write!(f, "The product has no tier with this name."),
+            ApiError::TierDowngrade => write!(f, "This key is for a lower tier than your active license. Redeem it once the license has expired."),
+            ApiError::LicenseLifetime => write!(f, "Your license for this product never expires, this key can't add anything to it."),
+            ApiError::WebhookNotFound => write!(f, "Webhook not found."),
+            ApiError::DeliveryNotFound => write!(f, "Webhook delivery not found."),
+            ApiError::ServiceUnavailable(_) => write!(f, "A dependency is unavailable."),
+            ApiError::Internal => write!(f, "Internal server error - contact support."),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidRequest(_) | ApiError::SelfDemotion | ApiError::OAuthStateInvalid => StatusCode::BAD_REQUEST,
+            ApiError::TokenMissing
+            | ApiError::TokenInvalid
+            | ApiError::TokenExpired
+            | ApiError::InvalidCredentials
+            | ApiError::AccountNotLinked
+            | ApiError::HwidMismatch
+            | ApiError::ChallengeInvalid => StatusCode::UNAUTHORIZED,
+            ApiError::PermissionDenied(_)
+            | ApiError::AccountBanned
+            | ApiError::HwidBanned
+            | ApiError::LicenseNotFound
+            | ApiError::LicenseExpired => StatusCode::FORBIDDEN,
+            ApiError::RouteNotFound
+            | ApiError::UserNotFound
+            | ApiError::OAuthProviderNotFound
+            | ApiError::LinkNotFound
+            | ApiError::DeviceNotFound
+            | ApiError::SessionNotFound
+            | ApiError::ProductNotFound
+            | ApiError::KeyInvalid
+            | ApiError::TierNotFound
+            | ApiError::WebhookNotFound
+            | ApiError::DeliveryNotFound => StatusCode::NOT_FOUND,
+            ApiError::SessionLimitReached
+            | ApiError::AccountAlreadyLinked
+            | ApiError::TierDowngrade
+            | ApiError::LicenseLifetime => StatusCode::CONFLICT,
+            ApiError::DeviceReleaseCooldown { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::HwidBindFailed | ApiError::KeyGenerationFailed { .. } | ApiError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::OAuthProviderError => StatusCode::BAD_GATEWAY,
+            ApiError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+
+        if let ApiError::DeviceReleaseCooldown { retry_after } = self {
+            response.insert_header(("Retry-After", retry_after.to_string()));
+        }
+
+        response.json(ErrorBody {
+            success: false,
+            code: self.code(),
+            message: self.to_string(),
+            details: self.details(),
+        })
+    }
+}
+
+/// Turn actix extractor failures (bad JSON, path or query) into the same envelope
+pub fn extractor_error_handler<E: fmt::Display>(err: E, _req: &actix_web::HttpRequest) -> actix_web::Error {
+    ApiError::InvalidRequest(err.to_string()).into()
+}
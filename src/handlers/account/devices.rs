@@ -0,0 +1,168 @@
+use actix_web::web;
+use tracing::{error, info};
+use authit_types::{Device, DevicesResponse, ReleaseDeviceRequest, RenameDeviceRequest};
+
+use crate::AppState;
+use crate::auth::JwtClaims;
+use crate::cache::AuthCache;
+use crate::error::{ApiError, ErrorBody};
+use crate::repository::{DeviceRelease, RepositoryError};
+use crate::response::ApiResponse;
+use crate::telemetry;
+use crate::webhook::{WebhookEvent, Webhooks};
+
+async fn get_user_devices(data: &AppState, user_id: &str) -> Result<Option<DevicesResponse>, RepositoryError> {
+    let Some(status) = data.repos.users.status(user_id).await? else {
+        return Ok(None);
+    };
+
+    let devices = data
+        .repos
+        .devices
+        .list(user_id)
+        .await?
+        .into_iter()
+        .map(|device| Device {
+            device_id: device.id,
+            product_id: device.product_id,
+            hwid: device.hwid,
+            name: device.name,
+            bound_at: device.bound_at,
+        })
+        .collect();
+
+    Ok(Some(DevicesResponse {
+        devices,
+        device_slots: status.device_slots,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/account/devices",
+    tag = "account",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Devices bound to the account and its slot count", body = ApiResponse<DevicesResponse>),
+        (status = 404, description = "USER_NOT_FOUND", body = ErrorBody),
+    ),
+)]
+pub async fn devices(
+    claims: JwtClaims,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse<DevicesResponse>, ApiError> {
+    match get_user_devices(&data, &claims.sub).await {
+        Ok(Some(response)) => Ok(ApiResponse::new(response)),
+        Ok(None) => Err(ApiError::UserNotFound),
+        Err(err) => {
+            error!("Database error while fetching devices for user {}: {}", claims.sub, err);
+            telemetry::record_db_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/account/devices/rename",
+    tag = "account",
+    request_body = RenameDeviceRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Device renamed", body = ApiResponse),
+        (status = 404, description = "DEVICE_NOT_FOUND", body = ErrorBody),
+    ),
+)]
+pub async fn rename_device(
+    claims: JwtClaims,
+    body: web::Json<RenameDeviceRequest>,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse, ApiError> {
+    let name = body.name.trim();
+    if name.is_empty() || name.len() > 64 {
+        return Err(ApiError::InvalidRequest("name must be between 1 and 64 characters.".to_string()));
+    }
+
+    match data.repos.devices.rename(&claims.sub, &body.device_id, name).await {
+        Ok(false) => Err(ApiError::DeviceNotFound),
+        Ok(true) => {
+            info!("User {} renamed device {} to {}", claims.sub, body.device_id, name);
+            Ok(ApiResponse::message("Device renamed."))
+        }
+        Err(err) => {
+            error!("Database error while renaming device {}: {}", body.device_id, err);
+            telemetry::record_db_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/account/devices/release",
+    tag = "account",
+    request_body = ReleaseDeviceRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Device released", body = ApiResponse),
+        (status = 404, description = "DEVICE_NOT_FOUND", body = ErrorBody),
+        (status = 429, description = "DEVICE_RELEASE_COOLDOWN, `details.retry_after` in seconds", body = ErrorBody),
+    ),
+)]
+pub async fn release_device(
+    claims: JwtClaims,
+    body: web::Json<ReleaseDeviceRequest>,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse, ApiError> {
+    info!("Device release attempt by {} for device {}", claims.sub, body.device_id);
+    let cooldown_hours = data.config.hwid.release_cooldown_hours;
+
+    match data.repos.devices.exists(&claims.sub, &body.device_id).await {
+        Ok(true) => {}
+        Ok(false) => return Err(ApiError::DeviceNotFound),
+        Err(err) => {
+            error!("Database error while looking up device {}: {}", body.device_id, err);
+            telemetry::record_db_error();
+            return Err(ApiError::Internal);
+        }
+    }
+
+    match data.repos.devices.release_cooldown_remaining(&claims.sub, cooldown_hours).await {
+        Ok(None) => {}
+        Ok(Some(seconds)) => {
+            info!("Device release denied for user {}: cooldown has {}s left", claims.sub, seconds);
+            return Err(ApiError::DeviceReleaseCooldown { retry_after: seconds });
+        }
+        Err(err) => {
+            error!("Database error while checking release cooldown for user {}: {}", claims.sub, err);
+            telemetry::record_db_error();
+            return Err(ApiError::Internal);
+        }
+    }
+
+    match data.repos.devices.release(&claims.sub, &body.device_id, cooldown_hours).await {
+        Ok(DeviceRelease::Released) => {
+            let cache = AuthCache::new(data.redis.clone());
+            if let Err(err) = cache.invalidate_user(&claims.sub).await {
+                error!("Failed to invalidate cached state for user {}: {}", claims.sub, err);
+                telemetry::record_redis_error();
+            }
+
+            info!("User {} released device {}", claims.sub, body.device_id);
+            Webhooks::new(data.repos.webhooks.clone())
+                .publish(
+                    WebhookEvent::HwidReset,
+                    serde_json::json!({ "user_id": claims.sub, "device_id": body.device_id }),
+                )
+                .await;
+            Ok(ApiResponse::message("Device released. The slot can be used by a new machine."))
+        }
+        Ok(DeviceRelease::Cooldown) => Err(ApiError::DeviceReleaseCooldown { retry_after: cooldown_hours * 3600 }),
+        Ok(DeviceRelease::NotFound) => Err(ApiError::DeviceNotFound),
+        Err(err) => {
+            error!("Database error while releasing device {}: {}", body.device_id, err);
+            telemetry::record_db_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use actix_web::web;
+use tracing::{error, info};
+use authit_types::LinkedAccountsResponse;
+
+use crate::AppState;
+use crate::auth::JwtClaims;
+use crate::error::{ApiError, ErrorBody};
+use crate::response::ApiResponse;
+use crate::telemetry;
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/account/links",
+    tag = "account",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "External accounts linked to the caller", body = ApiResponse<LinkedAccountsResponse>),
+    ),
+)]
+pub async fn linked_accounts(
+    claims: JwtClaims,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse<LinkedAccountsResponse>, ApiError> {
+    match data.repos.identities.for_user(&claims.sub).await {
+        Ok(accounts) => Ok(ApiResponse::new(LinkedAccountsResponse { accounts })),
+        Err(err) => {
+            error!("Database error while listing linked accounts for user {}: {}", claims.sub, err);
+            telemetry::record_db_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/account/links/{provider}",
+    tag = "account",
+    params(("provider" = String, Path, description = "Provider to unlink")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Account unlinked", body = ApiResponse),
+        (status = 404, description = "LINK_NOT_FOUND", body = ErrorBody),
+    ),
+)]
+pub async fn unlink_account(
+    claims: JwtClaims,
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse, ApiError> {
+    let provider = path.into_inner();
+
+    match data.repos.identities.unlink(&claims.sub, &provider).await {
+        Ok(true) => {
+            info!("User {} unlinked their {} account", claims.sub, provider);
+            Ok(ApiResponse::message(format!("Unlinked your {} account.", provider)))
+        }
+        Ok(false) => Err(ApiError::LinkNotFound),
+        Err(err) => {
+            error!("Database error while unlinking {} for user {}: {}", provider, claims.sub, err);
+            telemetry::record_db_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
@@ -6,12 +6,8 @@ pub mod setrole;
 pub use setrole::*;
 pub mod products;
 pub use products::*;
-use serde::{Deserialize,  Serialize};
-
-#[derive(sqlx::Type, Debug, Clone, Copy, Serialize, Deserialize)]
-pub enum Role {
-    User,
-    Support,
-    Dev,
-    Admin,
-}
\ No newline at end of file
+pub mod devices;
+pub use devices::*;
+pub mod links;
+pub use links::*;
+pub use authit_types::Role;
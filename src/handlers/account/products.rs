@@ -1,130 +1,107 @@
-use actix_web::{HttpResponse, web};
+use actix_web::web;
 use tracing::{error, info};
-use serde::{Deserialize, Serialize};
+use authit_types::{ProductLicense, ProductsResponse};
 
 use crate::AppState;
 use crate::auth::JwtClaims;
-
-#[derive(Serialize)]
-pub struct ProductLicense {
-    product_id: String,
-    product_name: String,
-    expires_at: String,
-    time_remaining_seconds: i64,
-    frozen: bool,
-}
-
-#[derive(Serialize)]
-pub struct ProductsResponse {
-    success: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    products: Option<Vec<ProductLicense>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    message: Option<String>,
-}
+use crate::error::ApiError;
+use crate::repository::RepositoryError;
+use crate::response::ApiResponse;
+use crate::telemetry;
 
 async fn get_user_products(
-    pool: &sqlx::PgPool,
+    data: &AppState,
     user_id: &str,
-) -> Result<Vec<ProductLicense>, sqlx::Error> {
-    let rows = sqlx::query_as::<_, (String, String, String, i64, bool)>(
-        "SELECT ul.product_id, p.name, ul.expires_at::TEXT, EXTRACT(EPOCH FROM (ul.expires_at - NOW()))::BIGINT, p.frozen
-         FROM user_licenses ul
-         JOIN products p ON ul.product_id = p.id
-         WHERE ul.user_id = $1 AND ul.expires_at > NOW()
-         ORDER BY ul.expires_at DESC"
-    )
-    .bind(user_id)
-    .fetch_all(pool)
-    .await?;
-
-    Ok(rows
+) -> Result<Vec<ProductLicense>, RepositoryError> {
+    Ok(data
+        .repos
+        .licenses
+        .active_for_user(user_id)
+        .await?
         .into_iter()
-        .map(|(product_id, product_name, expires_at, time_remaining_seconds, frozen)| ProductLicense {
-            product_id,
-            product_name,
-            expires_at,
-            time_remaining_seconds,
-            frozen,
+        .map(|license| {
+            let (tier, entitlements) = match license.tier {
+                Some(tier) => (Some(tier.name), tier.entitlements),
+                None => (None, Vec::new()),
+            };
+            ProductLicense {
+                product_id: license.product_id,
+                product_name: license.product_name,
+                expires_at: license.expires_at,
+                time_remaining_seconds: license.time_remaining_seconds,
+                frozen: license.frozen,
+                tier,
+                entitlements,
+            }
         })
         .collect())
 }
 
-async fn get_all_products_lifetime(
-    pool: &sqlx::PgPool,
-) -> Result<Vec<ProductLicense>, sqlx::Error> {
-    let rows = sqlx::query_as::<_, (String, String, bool)>(
-        "SELECT id, name, frozen FROM products ORDER BY name"
-    )
-    .fetch_all(pool)
-    .await?;
-
-    Ok(rows
+/// Admins and devs aren't licensed at all, this is separate from lifetime licenses
+async fn get_all_products_unlimited(
+    data: &AppState,
+) -> Result<Vec<ProductLicense>, RepositoryError> {
+    Ok(data
+        .repos
+        .products
+        .list()
+        .await?
         .into_iter()
-        .map(|(product_id, product_name, frozen)| ProductLicense {
-            product_id,
-            product_name,
-            expires_at: "infinity".to_string(),
-            time_remaining_seconds: i64::MAX,
-            frozen,
+        .map(|product| ProductLicense {
+            product_id: product.id,
+            product_name: product.name,
+            expires_at: Some("infinity".to_string()),
+            time_remaining_seconds: Some(i64::MAX),
+            frozen: product.frozen,
+            tier: None,
+            entitlements: Vec::new(),
         })
         .collect())
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/account/products",
+    tag = "account",
+    security(("bearer" = [])),
+    responses((status = 200, description = "Products the account owns with the time remaining, which is null for lifetime licenses", body = ApiResponse<ProductsResponse>)),
+)]
 pub async fn products(
     claims: JwtClaims,
     data: web::Data<AppState>,
-) -> HttpResponse {
+) -> Result<ApiResponse<ProductsResponse>, ApiError> {
     info!("Products request for user {}", claims.sub);
 
     // Admins and Devs get lifetime access to all products
     if matches!(claims.role, crate::handlers::account::Role::Admin | crate::handlers::account::Role::Dev) {
         info!("Admin/Dev user {} requesting products - returning all products with lifetime access", claims.sub);
 
-        match get_all_products_lifetime(&data.db_pool).await {
-            Ok(products) => {
-                return HttpResponse::Ok().json(ProductsResponse {
-                    success: true,
-                    products: Some(products),
-                    message: Some("Lifetime access to all products.".to_string()),
-                });
-            }
+        return match get_all_products_unlimited(&data).await {
+            Ok(products) => Ok(ApiResponse::new(ProductsResponse { products })
+                .with_message("Unlimited access to all products.")),
             Err(err) => {
                 error!("Database error while fetching all products: {}", err);
-                return HttpResponse::InternalServerError().json(ProductsResponse {
-                    success: false,
-                    products: None,
-                    message: Some("Internal server error.".to_string()),
-                });
+                telemetry::record_db_error();
+                Err(ApiError::Internal)
             }
-        }
+        };
     }
 
-    match get_user_products(&data.db_pool, &claims.sub).await {
+    match get_user_products(&data, &claims.sub).await {
         Ok(products) => {
             if products.is_empty() {
                 info!("User {} has no active products", claims.sub);
-                HttpResponse::Ok().json(ProductsResponse {
-                    success: true,
-                    products: Some(vec![]),
-                    message: Some("No active products found.".to_string()),
-                })
+                Ok(ApiResponse::new(ProductsResponse { products })
+                    .with_message("No active products found."))
             } else {
                 info!("User {} has {} active product(s)", claims.sub, products.len());
-                HttpResponse::Ok().json(ProductsResponse {
-                    success: true,
-                    products: Some(products),
-                    message: None,
-                })
+                Ok(ApiResponse::new(ProductsResponse { products }))
             }
         }
         Err(err) => {
             error!("Database error while fetching products for user {}: {}", claims.sub, err);
-            HttpResponse::InternalServerError().json(ProductsResponse {
-                success: false,
-                products: None,
-                message: Some("Internal server error.".to_string()),
-            })
+            telemetry::record_db_error();
+            Err(ApiError::Internal)
         }
     }
 }
@@ -1,165 +1,204 @@
-use actix_web::{HttpResponse, web};
+use actix_web::web;
 use tracing::{error, info};
-use serde::{Deserialize, Serialize};
+use authit_types::RedeemRequest;
+use chrono::Utc;
 
 use crate::AppState;
 use crate::auth::JwtClaims;
-
-#[derive(Deserialize)]
-pub struct RedeemRequest {
-    key: String,
-}
-
-#[derive(Serialize)]
-pub struct RedeemResponse {
-    success: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    message: Option<String>,
-}
-
-
-async fn key_db_query(pool: &sqlx::PgPool, key: &str) -> Result<Option<(i64, String)>, sqlx::Error> {
-    sqlx::query_as::<_, (i64, String)>("SELECT time_hours, product_id FROM cd_keys WHERE key = $1")
-        .bind(key)
-        .fetch_optional(pool)
-        .await
-}
-
-async fn user_products_query(pool: &sqlx::PgPool, user_id: &str) -> Result<Vec<String>, sqlx::Error> {
-    let rows = sqlx::query_as::<_, (String,)>("SELECT product_id FROM user_licenses WHERE user_id = $1")
-        .bind(user_id)
-        .fetch_all(pool)
-        .await?;
-
-    Ok(rows.into_iter().map(|row| row.0).collect())
-}
-
-async fn user_product_extend_query(pool: &sqlx::PgPool, user_id: &str, product_id: &str, extend_by_hours: i64) -> Result<(), sqlx::Error> {
-    sqlx::query("UPDATE user_licenses SET expires_at = expires_at + ($1 || ' hours')::INTERVAL, updated_at = NOW() WHERE user_id = $2 AND product_id = $3")
-        .bind(extend_by_hours)
-        .bind(user_id)
-        .bind(product_id)
-        .execute(pool)
-        .await?;
-
-    Ok(())
-}
-
-async fn user_product_assign_query(pool: &sqlx::PgPool, user_id: &str, product_id: &str, hours: i64) -> Result<(), sqlx::Error> {
-    sqlx::query("INSERT INTO user_licenses (user_id, product_id, expires_at) VALUES ($1, $2, NOW() + ($3 || ' hours')::INTERVAL)")
-        .bind(user_id)
-        .bind(product_id)
-        .bind(hours)
-        .execute(pool)
-        .await?;
-
-    Ok(())
-}
-
-async fn consume_key_query(pool: &sqlx::PgPool, key: &str) -> Result<(), sqlx::Error> {
-    sqlx::query("DELETE FROM cd_keys WHERE key = $1")
-        .bind(key)
-        .execute(pool)
-        .await?;
-
-    Ok(())
-}
-
+use crate::error::{ApiError, ErrorBody};
+use crate::response::ApiResponse;
+use crate::cache::AuthCache;
+use crate::telemetry;
+use crate::webhook::{WebhookEvent, Webhooks};
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/account/redeem",
+    tag = "account",
+    request_body = RedeemRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Key redeemed onto the account", body = ApiResponse),
+        (status = 404, description = "KEY_INVALID", body = ErrorBody),
+        (status = 409, description = "TIER_DOWNGRADE, the key is for a lower tier than the active license. \
+            LICENSE_LIFETIME, the license never expires and the key doesn't upgrade it to a higher lifetime tier", body = ErrorBody),
+    ),
+)]
 pub async fn redeem(
     claims: JwtClaims,
     body: web::Json<RedeemRequest>,
     data: web::Data<AppState>,
-) -> HttpResponse {
+) -> Result<ApiResponse, ApiError> {
+    let result = redeem_key(&claims, &body, &data).await;
+    metrics::counter!("redeem_requests_total", "outcome" => telemetry::outcome(&result)).increment(1);
+    result
+}
+
+async fn redeem_key(claims: &JwtClaims, body: &RedeemRequest, data: &AppState) -> Result<ApiResponse, ApiError> {
     //redeem flow:
     /*
         - verify jwt token
         - check if the key exists and is valid/unused
         - check if the user has that product already
-        - if user doesn't have product, assign product to user
+        - if user doesn't have product, assign product to user with the key's tier
         - if they have the product, add however much time the key gives to their existing product license
+          and move it to the key's tier, refusing keys for a lower tier than an active license.
+          Keys without a tier only add time, the license keeps its tier
+        - lifetime keys turn the license into a lifetime license, lifetime licenses only take
+          lifetime keys for a higher tier
         - consume key
         - respond with success or failure message
      */
     info!("Redeem attempt for key: {} on userid {}", body.key, claims.sub);
 
     //validate key
-    let (time_hours, product_id) = match key_db_query(&data.db_pool, &body.key).await {
-        Ok(Some((time_hours, product_id))) => (time_hours, product_id),
+    let (time_hours, product_id, key_tier) = match data.repos.keys.find(&body.key).await {
+        Ok(Some(key)) => (key.time_hours, key.product_id, key.tier),
         Ok(None) => {
             info!("Redeem failed: invalid key {}", body.key);
-            return HttpResponse::Ok().json(RedeemResponse {
-                success: false,
-                message: Some("Invalid or already used key.".to_string()),
-            });
+            return Err(ApiError::KeyInvalid);
         }
         Err(err) => {
             error!("Database error during key lookup: {}", err);
-            return HttpResponse::InternalServerError().json(RedeemResponse {
-                success: false,
-                message: Some("Internal server error.".to_string()),
-            });
+            telemetry::record_db_error();
+            return Err(ApiError::Internal);
         }
     };
-    info!("Key valid for product {} with {} hours", product_id, time_hours);
+    info!("Key valid for product {} with {:?} hours, none for lifetime (tier: {:?})", product_id, time_hours, key_tier);
 
     //get user's current products/licenses & check if they have the product
-    let products = match user_products_query(&data.db_pool, &claims.sub).await {
-        Ok(products) => products,
+    let licenses = match data.repos.licenses.for_user(&claims.sub).await {
+        Ok(licenses) => licenses,
         Err(err) => {
             error!("Database error during user products lookup: {}", err);
-            return HttpResponse::InternalServerError().json(RedeemResponse {
-                success: false,
-                message: Some("Internal server error.".to_string()),
-            });
+            telemetry::record_db_error();
+            return Err(ApiError::Internal);
         }
     };
-    info!("User {} currently has products: {:?}", claims.sub, products);
+    info!("User {} currently has products: {:?}", claims.sub, licenses.iter().map(|license| &license.product_id).collect::<Vec<_>>());
+
+    // `None` for keys that only add time, licenses without a tier rank below every tier
+    let key_level = match &key_tier {
+        Some(name) => match data.repos.products.tiers(&product_id).await {
+            Ok(tiers) => match tiers.into_iter().find(|tier| &tier.name == name) {
+                Some(tier) => Some(tier.level),
+                None => {
+                    error!("Key {} is for tier {} which product {} doesn't have", body.key, name, product_id);
+                    return Err(ApiError::Internal);
+                }
+            },
+            Err(err) => {
+                error!("Database error loading tiers of product {}: {}", product_id, err);
+                telemetry::record_db_error();
+                return Err(ApiError::Internal);
+            }
+        },
+        None => None,
+    };
 
     // Assign or extend license
-    if products.contains(&product_id) {
-        info!("User {} already owns product {}, extending license by {} hours", claims.sub, product_id, time_hours);
-
-        if let Err(err) = user_product_extend_query(&data.db_pool, &claims.sub, &product_id, time_hours).await {
-            error!("Database error during license extension: {}", err);
-            return HttpResponse::InternalServerError().json(RedeemResponse {
-                success: false,
-                message: Some("Failed to extend license.".to_string()),
-            });
+    let license = licenses.into_iter().find(|license| license.product_id == product_id);
+    let extended = license.is_some();
+    if let Some(license) = license {
+        let license_level = license.tier.as_ref().map(|tier| tier.level);
+        let active = license.expires_at.is_none_or(|expires_at| expires_at > Utc::now().timestamp());
+
+        // The time already paid for keeps its tier, lower tier keys wait for the license to run out
+        if active && key_level.is_some_and(|level| Some(level) < license_level) {
+            info!("Redeem refused: key tier {:?} is below the active license tier of user {} for product {}", key_tier, claims.sub, product_id);
+            return Err(ApiError::TierDowngrade);
+        }
+
+        // Time can't be added to a license that never expires, only a lifetime upgrade changes it
+        let upgrade = key_level.is_some_and(|level| Some(level) > license_level);
+        if license.expires_at.is_none() && !(time_hours.is_none() && upgrade) {
+            info!("Redeem refused: user {} already has a lifetime license for product {}", claims.sub, product_id);
+            return Err(ApiError::LicenseLifetime);
+        }
+
+        match time_hours {
+            Some(hours) => {
+                info!("User {} already owns product {}, extending license by {} hours", claims.sub, product_id, hours);
+
+                if let Err(err) = data.repos.licenses.extend(&claims.sub, &product_id, hours).await {
+                    error!("Database error during license extension: {}", err);
+                    telemetry::record_db_error();
+                    return Err(ApiError::Internal);
+                }
+            }
+            None => {
+                info!("User {} already owns product {}, converting the license to a lifetime license", claims.sub, product_id);
+
+                if let Err(err) = data.repos.licenses.make_lifetime(&claims.sub, &product_id).await {
+                    error!("Database error during license conversion: {}", err);
+                    telemetry::record_db_error();
+                    return Err(ApiError::Internal);
+                }
+            }
+        }
+
+        if key_level.is_some() && key_level != license_level {
+            info!("Moving license of user {} for product {} to tier {:?}", claims.sub, product_id, key_tier);
+
+            if let Err(err) = data.repos.licenses.set_tier(&claims.sub, &product_id, key_tier.as_deref()).await {
+                error!("Database error during license tier change: {}", err);
+                telemetry::record_db_error();
+                return Err(ApiError::Internal);
+            }
         }
     } else {
-        info!("Assigning product {} to user {} with {} hours", product_id, claims.sub, time_hours);
+        info!("Assigning product {} to user {} with {:?} hours, none for lifetime", product_id, claims.sub, time_hours);
 
-        if let Err(err) = user_product_assign_query(&data.db_pool, &claims.sub, &product_id, time_hours).await {
+        if let Err(err) = data.repos.licenses.assign(&claims.sub, &product_id, time_hours, key_tier.as_deref()).await {
             error!("Database error during product assignment: {}", err);
-            return HttpResponse::InternalServerError().json(RedeemResponse {
-                success: false,
-                message: Some("Failed to assign product.".to_string()),
-            });
+            telemetry::record_db_error();
+            return Err(ApiError::Internal);
         }
     }
 
+    // License changed, drop the cached /auth state so the new expiry is picked up
+    let cache = AuthCache::new(data.redis.clone());
+    if let Err(err) = cache.invalidate_user(&claims.sub).await {
+        error!("Failed to invalidate cached state for user {}: {}", claims.sub, err);
+        telemetry::record_redis_error();
+    }
+
     // Consume the key (delete it from database)
-    if let Err(err) = consume_key_query(&data.db_pool, &body.key).await {
+    if let Err(err) = data.repos.keys.consume(&body.key).await {
         error!("Database error during key consumption: {}", err);
+        telemetry::record_db_error();
         // Note: License was already assigned/extended, so we still return success
         // but log the error for investigation
         error!("CRITICAL: Key {} was used but not consumed from database!", body.key);
     }
 
     info!("Successfully redeemed key {} for user {}", body.key, claims.sub);
+    Webhooks::new(data.repos.webhooks.clone())
+        .publish(
+            WebhookEvent::KeyRedeemed,
+            serde_json::json!({
+                "user_id": claims.sub,
+                "product_id": product_id,
+                "key": body.key,
+                "time_hours": time_hours,
+                "lifetime": time_hours.is_none(),
+                "tier": key_tier,
+                "extended": extended,
+            }),
+        )
+        .await;
 
     // Convert hours to days for user-friendly message
-    let time_days = time_hours / 24;
-    let remaining_hours = time_hours % 24;
+    let time_message = match time_hours {
+        Some(hours) if hours % 24 == 0 => format!("{} days", hours / 24),
+        Some(hours) => format!("{} days and {} hours", hours / 24, hours % 24),
+        None => "lifetime access".to_string(),
+    };
 
-    let time_message = if remaining_hours == 0 {
-        format!("{} days", time_days)
-    } else {
-        format!("{} days and {} hours", time_days, remaining_hours)
+    let time_message = match &key_tier {
+        Some(tier) => format!("{} of {}", time_message, tier),
+        None => time_message,
     };
 
-    HttpResponse::Ok().json(RedeemResponse {
-        success: true,
-        message: Some(format!("Successfully redeemed {} for product {}.", time_message, product_id)),
-    })
+    Ok(ApiResponse::message(format!("Successfully redeemed {} for product {}.", time_message, product_id)))
 }
\ No newline at end of file
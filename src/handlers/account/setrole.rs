@@ -1,91 +1,142 @@
-use actix_web::{HttpResponse, web};
+use actix_web::web;
 use tracing::{error, info};
-use serde::{Deserialize, Serialize};
+use authit_types::SetRoleRequest;
 use chrono::Utc;
 
 use crate::AppState;
-use crate::auth::{JwtClaims, TokenBlacklist};
+use crate::auth::JwtClaims;
+use crate::auth::jwt::TOKEN_LIFETIME_SECONDS;
+use crate::config::BlacklistFailurePolicy;
+use crate::cache::AuthCache;
+use crate::error::{ApiError, ErrorBody};
+use crate::response::ApiResponse;
+use crate::telemetry;
+use crate::webhook::{WebhookEvent, Webhooks};
 use super::Role;
 
-#[derive(Deserialize)]
-pub struct SetRoleRequest {
-    user_id: String,
-    role: Role,
-}
-
-#[derive(Serialize)]
-pub struct SetRoleResponse {
-    success: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    message: Option<String>,
-}
-
-async fn update_user_role_query(pool: &sqlx::PgPool, user_id: &str, role: Role) -> Result<u64, sqlx::Error> {
-    let result = sqlx::query("UPDATE users SET role = $1, updated_at = NOW() WHERE id = $2")
-        .bind(role)
-        .bind(user_id)
-        .execute(pool)
-        .await?;
-
-    Ok(result.rows_affected())
-}
-
+#[utoipa::path(
+    post,
+    path = "/api/v1/account/set-role",
+    tag = "account",
+    request_body = SetRoleRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Role changed, the user's existing tokens are revoked", body = ApiResponse),
+        (status = 400, description = "SELF_DEMOTION", body = ErrorBody),
+        (status = 403, description = "PERMISSION_DENIED, admin only", body = ErrorBody),
+        (status = 404, description = "USER_NOT_FOUND", body = ErrorBody),
+    ),
+)]
 pub async fn set_role(
     claims: JwtClaims,
     body: web::Json<SetRoleRequest>,
     data: web::Data<AppState>,
-) -> HttpResponse {
+) -> Result<ApiResponse, ApiError> {
     info!("SetRole attempt by {} for user {} to role {:?}", claims.sub, body.user_id, body.role);
 
     // Check if requester is Admin
     if !matches!(claims.role, Role::Admin) {
         info!("SetRole denied: user {} is not an admin (role: {:?})", claims.sub, claims.role);
-        return HttpResponse::Forbidden().json(SetRoleResponse {
-            success: false,
-            message: Some("Only admins can change user roles.".to_string()),
-        });
+        return Err(ApiError::PermissionDenied("Only admins can change user roles.".to_string()));
     }
 
     // Prevent self-demotion
     if claims.sub == body.user_id && !matches!(body.role, Role::Admin) {
         info!("SetRole denied: admin {} attempted to demote themselves", claims.sub);
-        return HttpResponse::BadRequest().json(SetRoleResponse {
-            success: false,
-            message: Some("Admins cannot demote themselves.".to_string()),
-        });
+        return Err(ApiError::SelfDemotion);
+    }
+
+    // Revoke tokens issued before now first, so a failed revocation can still abort the change
+    // The local copy backs the `local` failure policy, the memory blacklist shares the same store
+    let now = Utc::now().timestamp();
+    data.revocations.revoke_user_before(&body.user_id, now, TOKEN_LIFETIME_SECONDS);
+    if let Err(e) = data.blacklist.blacklist_user_before_timestamp(&body.user_id, now, TOKEN_LIFETIME_SECONDS).await {
+        error!("Failed to blacklist user tokens: {}", e);
+        telemetry::record_redis_error();
+
+        // Old tokens would keep the old role once Redis is back, only proceed if the policy accepts that
+        if data.config.auth.blacklist_failure_policy == BlacklistFailurePolicy::Closed {
+            return Err(ApiError::ServiceUnavailable(serde_json::json!({ "dependency": "redis" })));
+        }
     }
 
     // Update user role
-    match update_user_role_query(&data.db_pool, &body.user_id, body.role).await {
-        Ok(rows_affected) => {
-            if rows_affected == 0 {
+    match data.repos.users.set_role(&body.user_id, body.role).await {
+        Ok(updated) => {
+            if !updated {
                 info!("SetRole failed: user {} not found", body.user_id);
-                return HttpResponse::NotFound().json(SetRoleResponse {
-                    success: false,
-                    message: Some("User not found.".to_string()),
-                });
+                return Err(ApiError::UserNotFound);
             }
 
-            // Blacklist all tokens issued before now (24 hours = max token lifetime)
-            let now = Utc::now().timestamp();
-            let blacklist = TokenBlacklist::new(data.redis_client.clone());
-            if let Err(e) = blacklist.blacklist_user_before_timestamp(&body.user_id, now, 86400).await {
-                error!("Failed to blacklist user tokens: {}", e);
-                // Continue anyway - role was updated in database
+            let cache = AuthCache::new(data.redis.clone());
+            if let Err(e) = cache.invalidate_user(&body.user_id).await {
+                error!("Failed to invalidate cached state for user {}: {}", body.user_id, e);
+                telemetry::record_redis_error();
             }
 
             info!("Successfully updated user {} to role {:?} and invalidated all tokens", body.user_id, body.role);
-            HttpResponse::Ok().json(SetRoleResponse {
-                success: true,
-                message: Some(format!("Successfully updated user role to {:?}. User must re-login.", body.role)),
-            })
+            Webhooks::new(data.repos.webhooks.clone())
+                .publish(
+                    WebhookEvent::RoleChanged,
+                    serde_json::json!({ "user_id": body.user_id, "role": body.role, "changed_by": claims.sub }),
+                )
+                .await;
+            Ok(ApiResponse::message(format!("Successfully updated user role to {:?}. User must re-login.", body.role)))
         }
         Err(err) => {
             error!("Database error during role update: {}", err);
-            HttpResponse::InternalServerError().json(SetRoleResponse {
-                success: false,
-                message: Some("Internal server error.".to_string()),
-            })
+            telemetry::record_db_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::web;
+    use authit_types::SetRoleRequest;
+    use std::sync::Arc;
+
+    use crate::AppState;
+    use crate::auth::JwtClaims;
+    use crate::auth::blacklist::UnreachableBlacklist;
+    use crate::auth::jwt::Claims;
+    use crate::config::{BlacklistFailurePolicy, Config};
+    use crate::error::ApiError;
+    use crate::repository::MemoryRepository;
+    use super::{Role, set_role};
+
+    /// Promote user-1 to dev while the blacklist can't be reached
+    async fn promote(policy: BlacklistFailurePolicy) -> (Result<(), ApiError>, Role) {
+        let repository = Arc::new(MemoryRepository::new());
+        repository.add_user("user-1", "player@example.com", "hash", Role::User, 1);
+
+        let mut config = Config::default();
+        config.auth.blacklist_failure_policy = policy;
+        let mut state = AppState::in_memory(config, repository).unwrap();
+        state.blacklist = Arc::new(UnreachableBlacklist);
+        let state = web::Data::new(state);
+
+        let admin = JwtClaims(Claims::new("admin".to_string(), "admin@example.com".to_string(), Role::Admin));
+        let body = web::Json(SetRoleRequest { user_id: "user-1".to_string(), role: Role::Dev });
+        let result = set_role(admin, body, state.clone()).await.map(|_| ());
+
+        (result, state.repos.users.get("user-1").await.unwrap().unwrap().role)
+    }
+
+    #[actix_web::test]
+    async fn closed_refuses_to_change_the_role() {
+        let (result, role) = promote(BlacklistFailurePolicy::Closed).await;
+        assert!(matches!(result, Err(ApiError::ServiceUnavailable(_))));
+        assert_eq!(role, Role::User);
+    }
+
+    #[actix_web::test]
+    async fn open_and_local_change_it_anyway() {
+        for policy in [BlacklistFailurePolicy::Open, BlacklistFailurePolicy::Local] {
+            let (result, role) = promote(policy).await;
+            assert!(result.is_ok(), "{:?}", policy);
+            assert_eq!(role, Role::Dev);
         }
     }
 }
@@ -1,3 +1,6 @@
 pub mod account;
+pub mod oauth;
 pub mod product;
-pub mod public;
\ No newline at end of file
+pub mod public;
+pub mod session;
+pub mod webhooks;
\ No newline at end of file
@@ -0,0 +1,106 @@
+use actix_web::web;
+use tracing::{error, info};
+use authit_types::{OAuthAuthorizeResponse, OAuthStartRequest};
+
+use crate::AppState;
+use crate::auth::oauth::is_loopback;
+use crate::auth::{JwtClaims, OAuthIntent};
+use crate::error::{ApiError, ErrorBody};
+use crate::response::ApiResponse;
+use crate::telemetry;
+
+/// Issue a state for the flow and build the provider's authorize URL around it
+async fn authorize(
+    data: &AppState,
+    provider_name: String,
+    user_id: Option<String>,
+    start: OAuthStartRequest,
+) -> Result<ApiResponse<OAuthAuthorizeResponse>, ApiError> {
+    let Some(provider) = data.oauth.get(&provider_name) else {
+        return Err(ApiError::OAuthProviderNotFound);
+    };
+    if !is_loopback(&start.return_to) {
+        return Err(ApiError::InvalidRequest("return_to must be an http://127.0.0.1, [::1] or localhost URL.".to_string()));
+    }
+    // A base64url SHA-256 digest
+    if start.code_challenge.len() != 43 {
+        return Err(ApiError::InvalidRequest("code_challenge must be the base64url SHA-256 of the verifier.".to_string()));
+    }
+
+    let intent = OAuthIntent {
+        provider: provider_name,
+        user_id,
+        return_to: start.return_to,
+        code_challenge: start.code_challenge,
+        account: None,
+    };
+
+    let state = match data.oauth_states.issue(&intent).await {
+        Ok(state) => state,
+        Err(err) => {
+            error!("Redis error while issuing OAuth state: {}", err);
+            telemetry::record_redis_error();
+            return Err(ApiError::Internal);
+        }
+    };
+
+    match data.oauth.authorize_url(provider, &state) {
+        Ok(authorize_url) => Ok(ApiResponse::new(OAuthAuthorizeResponse { authorize_url, state })),
+        Err(err) => {
+            error!("OAuth provider {} is misconfigured: {}", intent.provider, err);
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/oauth/{provider}/link",
+    tag = "oauth",
+    params(("provider" = String, Path, description = "Provider name from the server's configuration")),
+    request_body = OAuthStartRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Send the browser to `authorize_url`, `/exchange` links the account afterwards", body = ApiResponse<OAuthAuthorizeResponse>),
+        (status = 400, description = "INVALID_REQUEST, `return_to` isn't a loopback URL or `code_challenge` isn't a digest", body = ErrorBody),
+        (status = 404, description = "OAUTH_PROVIDER_NOT_FOUND", body = ErrorBody),
+    ),
+)]
+pub async fn oauth_link(
+    claims: JwtClaims,
+    path: web::Path<String>,
+    body: web::Json<OAuthStartRequest>,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse<OAuthAuthorizeResponse>, ApiError> {
+    let provider = path.into_inner();
+    info!("OAuth link started by {} with {}", claims.sub, provider);
+
+    authorize(&data, provider, Some(claims.sub.clone()), body.into_inner()).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/oauth/{provider}/login",
+    tag = "oauth",
+    params(("provider" = String, Path, description = "Provider name from the server's configuration")),
+    request_body = OAuthStartRequest,
+    responses(
+        (status = 200, description = "Send the browser to `authorize_url`, `/exchange` returns a JWT afterwards", body = ApiResponse<OAuthAuthorizeResponse>),
+        (status = 400, description = "INVALID_REQUEST, `return_to` isn't a loopback URL or `code_challenge` isn't a digest", body = ErrorBody),
+        (status = 403, description = "PERMISSION_DENIED, the provider can only be linked", body = ErrorBody),
+        (status = 404, description = "OAUTH_PROVIDER_NOT_FOUND", body = ErrorBody),
+    ),
+)]
+pub async fn oauth_login(
+    path: web::Path<String>,
+    body: web::Json<OAuthStartRequest>,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse<OAuthAuthorizeResponse>, ApiError> {
+    let provider = path.into_inner();
+
+    if data.oauth.get(&provider).is_some_and(|provider| !provider.allow_login) {
+        return Err(ApiError::PermissionDenied(format!("Logging in with {} is disabled.", provider)));
+    }
+
+    authorize(&data, provider, None, body.into_inner()).await
+}
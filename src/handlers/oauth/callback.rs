@@ -0,0 +1,90 @@
+use actix_web::{HttpResponse, web};
+use tracing::{error, info, warn};
+use authit_types::OAuthCallbackQuery;
+
+use crate::AppState;
+use crate::auth::OAuthIntent;
+use crate::auth::oauth::return_url;
+use crate::error::{ApiError, ErrorBody};
+use crate::telemetry;
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/oauth/{provider}/callback",
+    tag = "oauth",
+    params(
+        ("provider" = String, Path, description = "Provider name from the server's configuration"),
+        OAuthCallbackQuery,
+    ),
+    responses(
+        (status = 302, description = "Back to the flow's `return_to` with a one-time `code` for `/exchange`, or `error` (e.g. OAUTH_PROVIDER_ERROR)"),
+        (status = 400, description = "OAUTH_STATE_INVALID", body = ErrorBody),
+    ),
+)]
+pub async fn oauth_callback(
+    path: web::Path<String>,
+    query: web::Query<OAuthCallbackQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    // The state is spent either way, a failed callback has to start over
+    let intent = match data.oauth_states.take(&query.state).await {
+        Ok(Some(intent)) if intent.provider == *path && intent.account.is_none() => intent,
+        Ok(_) => {
+            metrics::counter!("oauth_callbacks_total", "outcome" => "OAUTH_STATE_INVALID").increment(1);
+            return Err(ApiError::OAuthStateInvalid);
+        }
+        Err(err) => {
+            error!("Redis error while checking OAuth state: {}", err);
+            telemetry::record_redis_error();
+            return Err(ApiError::Internal);
+        }
+    };
+
+    // From here on the browser goes back to the loader, which reports the outcome to the user
+    let result = identify(&path, &query, &data, intent.clone()).await;
+    metrics::counter!("oauth_callbacks_total", "outcome" => telemetry::outcome(&result)).increment(1);
+    let params = match &result {
+        Ok(code) => [("code", code.as_str())],
+        Err(err) => [("error", err.code().as_str())],
+    };
+
+    match return_url(&intent.return_to, &params) {
+        Ok(location) => Ok(HttpResponse::Found().insert_header(("Location", location)).finish()),
+        Err(err) => {
+            error!("OAuth flow has an unusable return_to: {}", err);
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+/// Swap the provider's code for the account and issue the exchange code the loader redeems
+async fn identify(provider_name: &str, query: &OAuthCallbackQuery, data: &AppState, intent: OAuthIntent) -> Result<String, ApiError> {
+    let Some(provider) = data.oauth.get(provider_name) else {
+        return Err(ApiError::OAuthProviderNotFound);
+    };
+
+    if let Some(reason) = &query.error {
+        info!("OAuth with {} was not completed: {}", provider_name, reason);
+        return Err(ApiError::OAuthProviderError);
+    }
+    let Some(code) = &query.code else {
+        return Err(ApiError::InvalidRequest("code is missing.".to_string()));
+    };
+
+    let account = match data.oauth.identify(provider_name, provider, code).await {
+        Ok(account) => account,
+        Err(err) => {
+            warn!("OAuth code exchange with {} failed: {}", provider_name, err);
+            return Err(ApiError::OAuthProviderError);
+        }
+    };
+
+    match data.oauth_states.issue(&OAuthIntent { account: Some(account), ..intent }).await {
+        Ok(code) => Ok(code),
+        Err(err) => {
+            error!("Redis error while issuing OAuth exchange code: {}", err);
+            telemetry::record_redis_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use actix_web::web;
+use tracing::{error, info};
+use authit_types::{OAuthExchangeRequest, OAuthExchangeResponse};
+
+use crate::AppState;
+use crate::auth::{OAuthIntent, jwt};
+use crate::auth::oauth::{LinkedAccount, verifies};
+use crate::error::{ApiError, ErrorBody};
+use crate::response::ApiResponse;
+use crate::telemetry;
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/oauth/{provider}/exchange",
+    tag = "oauth",
+    params(("provider" = String, Path, description = "Provider name from the server's configuration")),
+    request_body = OAuthExchangeRequest,
+    responses(
+        (status = 200, description = "Account linked, or logged in with `token` set", body = ApiResponse<OAuthExchangeResponse>),
+        (status = 400, description = "OAUTH_STATE_INVALID, the code is unknown, spent or the verifier doesn't match", body = ErrorBody),
+        (status = 401, description = "ACCOUNT_NOT_LINKED, logging in with an account nobody linked", body = ErrorBody),
+        (status = 409, description = "ACCOUNT_ALREADY_LINKED to another user", body = ErrorBody),
+    ),
+)]
+pub async fn oauth_exchange(
+    path: web::Path<String>,
+    body: web::Json<OAuthExchangeRequest>,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse<OAuthExchangeResponse>, ApiError> {
+    let result = complete(&path, &body, &data).await;
+    metrics::counter!("oauth_exchanges_total", "outcome" => telemetry::outcome(&result)).increment(1);
+    result
+}
+
+async fn complete(provider_name: &str, body: &OAuthExchangeRequest, data: &AppState) -> Result<ApiResponse<OAuthExchangeResponse>, ApiError> {
+    // Spent either way, only the loader holding the verifier can finish the flow it started
+    // A `state` has no account yet, so it can't be redeemed before the callback ran
+    let (user_id, account) = match data.oauth_states.take(&body.code).await {
+        Ok(Some(OAuthIntent { provider, user_id, code_challenge, account: Some(account), .. }))
+            if provider == provider_name && verifies(&code_challenge, &body.code_verifier) =>
+        {
+            (user_id, account)
+        }
+        Ok(_) => return Err(ApiError::OAuthStateInvalid),
+        Err(err) => {
+            error!("Redis error while checking OAuth exchange code: {}", err);
+            telemetry::record_redis_error();
+            return Err(ApiError::Internal);
+        }
+    };
+
+    // Logging in looks the user up by the external account, linking takes the user from the state
+    let (user_id, token) = match user_id {
+        Some(user_id) => (user_id, false),
+        None => match data.repos.identities.find_user(provider_name, &account.external_id).await {
+            Ok(Some(user_id)) => (user_id, true),
+            Ok(None) => {
+                info!("OAuth login with unlinked {} account {}", provider_name, account.external_id);
+                return Err(ApiError::AccountNotLinked);
+            }
+            Err(err) => {
+                error!("Database error during OAuth login: {}", err);
+                telemetry::record_db_error();
+                return Err(ApiError::Internal);
+            }
+        },
+    };
+
+    // Linking again on login keeps the stored username current
+    match data.repos.identities.link(&user_id, &account).await {
+        Ok(true) => {}
+        Ok(false) => {
+            info!("{} account {} is already linked to another user than {}", provider_name, account.external_id, user_id);
+            return Err(ApiError::AccountAlreadyLinked);
+        }
+        Err(err) => {
+            error!("Database error while linking {} account for user {}: {}", provider_name, user_id, err);
+            telemetry::record_db_error();
+            return Err(ApiError::Internal);
+        }
+    }
+
+    let linked = match data.repos.identities.for_user(&user_id).await {
+        Ok(accounts) => accounts.into_iter().find(|linked| linked.provider == provider_name),
+        Err(err) => {
+            error!("Database error while looking up linked accounts for user {}: {}", user_id, err);
+            telemetry::record_db_error();
+            return Err(ApiError::Internal);
+        }
+    };
+    let account = linked.unwrap_or(LinkedAccount {
+        provider: account.provider,
+        external_id: account.external_id,
+        username: account.username,
+        linked_at: String::new(),
+    });
+
+    if !token {
+        info!("User {} linked {} account {}", user_id, provider_name, account.external_id);
+        return Ok(ApiResponse::new(OAuthExchangeResponse { account, token: None })
+            .with_message(format!("Linked your {} account.", provider_name)));
+    }
+
+    let user = match data.repos.users.get(&user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(ApiError::AccountNotLinked),
+        Err(err) => {
+            error!("Database error during OAuth login: {}", err);
+            telemetry::record_db_error();
+            return Err(ApiError::Internal);
+        }
+    };
+
+    match jwt::generate_token(&data.jwt_keys, user.id.clone(), user.email, user.role) {
+        Ok(token) => {
+            info!("User {} logged in with {}", user.id, provider_name);
+            Ok(ApiResponse::new(OAuthExchangeResponse { account, token: Some(token) }))
+        }
+        Err(err) => {
+            error!("Failed to generate JWT token: {}", err);
+            Err(ApiError::Internal)
+        }
+    }
+}
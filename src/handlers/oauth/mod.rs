@@ -0,0 +1,6 @@
+pub mod authorize;
+pub use authorize::*;
+pub mod callback;
+pub use callback::*;
+pub mod exchange;
+pub use exchange::*;
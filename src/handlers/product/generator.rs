@@ -1,39 +1,21 @@
-use actix_web::{HttpResponse, web};
+use actix_web::web;
 use tracing::{error, info};
-use serde::{Deserialize, Serialize};
+use authit_types::{GenerateKeyRequest, GenerateKeyResponse};
 use rand::Rng;
 
 use crate::AppState;
 use crate::auth::JwtClaims;
+use crate::error::{ApiError, ErrorBody};
+use crate::response::ApiResponse;
 use crate::handlers::account::Role;
-
-#[derive(Deserialize)]
-pub struct GenerateKeyRequest {
-    product_id: String,
-    time_days: i64,
-    #[serde(default = "default_count")]
-    count: i32,
-}
-
-fn default_count() -> i32 {
-    1
-}
-
-#[derive(Serialize)]
-pub struct GenerateKeyResponse {
-    success: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    keys: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    message: Option<String>,
-}
+use crate::telemetry;
 
 /// Generate a random CD key in format: XXXX-XXXX-XXXX-XXXX
-fn generate_random_key() -> String {
+pub fn generate_random_key(prefix: &str) -> String {
     const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
     let mut rng = rand::thread_rng();
 
-    let mut key = String::with_capacity(50) + std::env::var("KEY_PREFIX").unwrap_or_else(|_| "".to_string()).as_str(); // 16 chars + 3 dashes
+    let mut key = String::with_capacity(50) + prefix; // 16 chars + 3 dashes
 
     for segment in 0..7 {
         if segment > 0 {
@@ -48,90 +30,82 @@ fn generate_random_key() -> String {
     key
 }
 
-/// Check if a product exists in the database
-async fn product_exists(pool: &sqlx::PgPool, product_id: &str) -> Result<bool, sqlx::Error> {
-    let result = sqlx::query("SELECT id FROM products WHERE id = $1")
-        .bind(product_id)
-        .fetch_optional(pool)
-        .await?;
-
-    Ok(result.is_some())
-}
-
-/// Insert a CD key into the database
-async fn insert_key(pool: &sqlx::PgPool, key: &str, product_id: &str, time_hours: i64) -> Result<bool, sqlx::Error> {
-    let result = sqlx::query(
-        "INSERT INTO cd_keys (key, product_id, time_hours) VALUES ($1, $2, $3) ON CONFLICT (key) DO NOTHING"
-    )
-    .bind(key)
-    .bind(product_id)
-    .bind(time_hours)
-    .execute(pool)
-    .await?;
-
-    Ok(result.rows_affected() > 0)
-}
-
+#[utoipa::path(
+    post,
+    path = "/api/v1/product/generate-key",
+    tag = "product",
+    request_body = GenerateKeyRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Generated keys", body = ApiResponse<GenerateKeyResponse>),
+        (status = 403, description = "PERMISSION_DENIED, admin only", body = ErrorBody),
+        (status = 404, description = "PRODUCT_NOT_FOUND or TIER_NOT_FOUND", body = ErrorBody),
+    ),
+)]
 pub async fn generate_key(
     claims: JwtClaims,
     body: web::Json<GenerateKeyRequest>,
     data: web::Data<AppState>,
-) -> HttpResponse {
-    info!("Generate key attempt by {} for product {} ({} days, count: {})",
-          claims.sub, body.product_id, body.time_days, body.count);
+) -> Result<ApiResponse<GenerateKeyResponse>, ApiError> {
+    info!("Generate key attempt by {} for product {} ({:?} days, lifetime: {}, count: {}, tier: {:?})",
+          claims.sub, body.product_id, body.time_days, body.lifetime, body.count, body.tier);
 
     // Check if requester is Admin
     if !matches!(claims.role, Role::Admin) {
         info!("Generate key denied: user {} is not an admin (role: {:?})", claims.sub, claims.role);
-        return HttpResponse::Forbidden().json(GenerateKeyResponse {
-            success: false,
-            keys: None,
-            message: Some("Only admins can generate CD keys.".to_string()),
-        });
-    }
-
-    // Validate time_days is positive
-    if body.time_days <= 0 {
-        info!("Generate key denied: invalid time_days {}", body.time_days);
-        return HttpResponse::BadRequest().json(GenerateKeyResponse {
-            success: false,
-            keys: None,
-            message: Some("time_days must be positive.".to_string()),
-        });
+        return Err(ApiError::PermissionDenied("Only admins can generate CD keys.".to_string()));
     }
 
-    // Convert days to hours for storage
-    let time_hours = body.time_days * 24;
+    // Lifetime keys carry no time, every other key needs a positive time_days
+    let time_hours = match (body.lifetime, body.time_days) {
+        (true, None) => None,
+        (true, Some(_)) => {
+            info!("Generate key denied: time_days given for lifetime keys");
+            return Err(ApiError::InvalidRequest("Lifetime keys don't take time_days.".to_string()));
+        }
+        // Convert days to hours for storage
+        (false, Some(days)) if days > 0 => Some(days * 24),
+        (false, days) => {
+            info!("Generate key denied: invalid time_days {:?}", days);
+            return Err(ApiError::InvalidRequest("time_days must be positive.".to_string()));
+        }
+    };
 
     // Validate count is positive and reasonable
     if body.count <= 0 || body.count > 1000 {
         info!("Generate key denied: invalid count {}", body.count);
-        return HttpResponse::BadRequest().json(GenerateKeyResponse {
-            success: false,
-            keys: None,
-            message: Some("count must be between 1 and 1000.".to_string()),
-        });
+        return Err(ApiError::InvalidRequest("count must be between 1 and 1000.".to_string()));
     }
 
     // Check if product exists
-    match product_exists(&data.db_pool, &body.product_id).await {
+    match data.repos.products.exists(&body.product_id).await {
         Ok(exists) => {
             if !exists {
                 info!("Generate key failed: product {} does not exist", body.product_id);
-                return HttpResponse::NotFound().json(GenerateKeyResponse {
-                    success: false,
-                    keys: None,
-                    message: Some("Product not found.".to_string()),
-                });
+                return Err(ApiError::ProductNotFound);
             }
         }
         Err(err) => {
             error!("Database error checking product existence: {}", err);
-            return HttpResponse::InternalServerError().json(GenerateKeyResponse {
-                success: false,
-                keys: None,
-                message: Some("Internal server error.".to_string()),
-            });
+            telemetry::record_db_error();
+            return Err(ApiError::Internal);
+        }
+    }
+
+    // The tier must be one of the product's
+    if let Some(tier) = &body.tier {
+        match data.repos.products.tiers(&body.product_id).await {
+            Ok(tiers) => {
+                if !tiers.iter().any(|known| &known.name == tier) {
+                    info!("Generate key failed: product {} has no tier {}", body.product_id, tier);
+                    return Err(ApiError::TierNotFound);
+                }
+            }
+            Err(err) => {
+                error!("Database error loading tiers of product {}: {}", body.product_id, err);
+                telemetry::record_db_error();
+                return Err(ApiError::Internal);
+            }
         }
     }
 
@@ -144,21 +118,21 @@ pub async fn generate_key(
         if attempts >= body.count * MAX_ATTEMPTS_PER_KEY {
             error!("Failed to generate {} keys after {} attempts", body.count, attempts);
             let count = generated_keys.len();
-            return HttpResponse::InternalServerError().json(GenerateKeyResponse {
-                success: false,
-                keys: Some(generated_keys),
-                message: Some(format!("Only generated {} out of {} keys due to collisions.",
-                                    count, body.count)),
+            return Err(ApiError::KeyGenerationFailed {
+                keys: generated_keys,
+                message: format!("Only generated {} out of {} keys due to collisions.",
+                                 count, body.count),
             });
         }
 
-        let key = generate_random_key();
+        let key = generate_random_key(&data.config.keys.prefix);
         attempts += 1;
 
-        match insert_key(&data.db_pool, &key, &body.product_id, time_hours).await {
+        match data.repos.keys.insert(&key, &body.product_id, time_hours, body.tier.as_deref()).await {
             Ok(inserted) => {
                 if inserted {
                     generated_keys.push(key.clone());
+                    metrics::counter!("keys_generated_total", "product" => body.product_id.clone()).increment(1);
                     info!("Generated key: {}", key);
                 } else {
                     // Key collision, try again
@@ -167,21 +141,18 @@ pub async fn generate_key(
             }
             Err(err) => {
                 error!("Database error inserting key: {}", err);
+                telemetry::record_db_error();
                 let count = generated_keys.len();
-                return HttpResponse::InternalServerError().json(GenerateKeyResponse {
-                    success: false,
-                    keys: Some(generated_keys),
-                    message: Some(format!("Partial success: generated {} keys before error.",
-                                        count)),
+                return Err(ApiError::KeyGenerationFailed {
+                    keys: generated_keys,
+                    message: format!("Partial success: generated {} keys before error.",
+                                     count),
                 });
             }
         }
     }
 
     info!("Successfully generated {} keys for product {}", generated_keys.len(), body.product_id);
-    HttpResponse::Ok().json(GenerateKeyResponse {
-        success: true,
-        keys: Some(generated_keys),
-        message: Some(format!("Successfully generated {} key(s).", body.count)),
-    })
-}
\ No newline at end of file
+    Ok(ApiResponse::new(GenerateKeyResponse { keys: generated_keys })
+        .with_message(format!("Successfully generated {} key(s).", body.count)))
+}
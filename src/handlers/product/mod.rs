@@ -1,4 +1,4 @@
 pub mod generator;
 pub use generator::*;
 pub mod compensate;
-pub use compensate::*;
\ No newline at end of file
+pub use compensate::*;
@@ -1,238 +1,558 @@
-use actix_web::{HttpResponse, web};
+use actix_web::web;
 use tracing::{error, info};
-use serde::{Deserialize, Serialize};
+use authit_types::{AuthRequest, AuthResponse};
+use chrono::Utc;
 
 use crate::AppState;
-use crate::auth::JwtClaims;
+use crate::error::{ApiError, ErrorBody};
+use crate::response::ApiResponse;
+use crate::auth::{FingerprintMatcher, HwidComponents, JwtClaims, SignedClaims};
+use crate::auth::session::Session;
+use crate::cache::{AuthCache, CachedDevice, CachedLicense, CachedUserState};
+use crate::repository::{HwidPolicy, RepositoryError};
+use crate::telemetry;
 
-#[derive(Deserialize)]
-pub struct AuthRequest {
-    product_id: String,
-    hwid: String,
-}
+/// Load everything `/auth` needs about a user straight from the repositories
+/// Returns `None` if the user doesn't exist
+async fn load_user_state(
+    data: &AppState,
+    user_id: &str,
+) -> Result<Option<CachedUserState>, RepositoryError> {
+    let Some(status) = data.repos.users.status(user_id).await? else {
+        return Ok(None); // User not found
+    };
 
-#[derive(Serialize)]
-pub struct AuthResponse {
-    success: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    time_remaining: Option<i64>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    message: Option<String>,
+    let devices = data.repos.devices.list(user_id).await?;
+    let licenses = data.repos.licenses.for_user(user_id).await?;
+
+    Ok(Some(CachedUserState {
+        banned: status.banned,
+        devices: devices
+            .into_iter()
+            .map(|device| CachedDevice {
+                device_id: device.id,
+                product_id: device.product_id,
+                hwid: device.hwid,
+                components: device.components,
+            })
+            .collect(),
+        device_slots: status.device_slots,
+        licenses: licenses
+            .into_iter()
+            .map(|license| {
+                let (tier, entitlements) = match license.tier {
+                    Some(tier) => (Some(tier.name), tier.entitlements),
+                    None => (None, Vec::new()),
+                };
+                (license.product_id, CachedLicense {
+                    expires_at: license.expires_at,
+                    max_sessions: license.max_sessions,
+                    hwid_policy: license.hwid_policy,
+                    hwid_device_limit: license.hwid_device_limit,
+                    tier,
+                    entitlements,
+                })
+            })
+            .collect(),
+    }))
 }
 
-type DbResponse = Result<Option<i64>, sqlx::Error>;
-async fn get_license_time_remaining(
-    pool: &sqlx::PgPool,
+/// Get a user's auth state from Redis, falling back to Postgres on a miss or Redis error
+async fn get_user_state(
+    data: &AppState,
     user_id: &str,
-    product_id: &str,
-) -> DbResponse {
-    sqlx::query_as::<_, (i64,)>(
-        "SELECT EXTRACT(EPOCH FROM (expires_at - NOW()))::BIGINT FROM user_licenses WHERE user_id = $1 AND product_id = $2 AND expires_at > NOW()",
-    )
-    .bind(user_id)
-    .bind(product_id)
-    .fetch_optional(pool)
-    .await
-    .map(|opt| opt.map(|row| row.0))
+) -> Result<Option<CachedUserState>, RepositoryError> {
+    let cache = AuthCache::new(data.redis.clone());
+
+    match cache.get_user_state(user_id).await {
+        Ok(Some(state)) => return Ok(Some(state)),
+        Ok(None) => {
+            // cache miss, load from database
+        }
+        Err(err) => {
+            error!("Redis error while reading cached state for user {}: {}", user_id, err);
+            telemetry::record_redis_error();
+        }
+    }
+
+    // Read first, an invalidation racing the load below bumps it and the stale state isn't cached
+    let generation = match cache.user_generation(user_id).await {
+        Ok(generation) => Some(generation),
+        Err(err) => {
+            error!("Redis error while reading cache generation for user {}: {}", user_id, err);
+            telemetry::record_redis_error();
+            None
+        }
+    };
+
+    let state = load_user_state(data, user_id).await?;
+
+    if let (Some(state), Some(generation)) = (&state, generation) {
+        match cache.set_user_state(user_id, state, generation).await {
+            Ok(true) => {}
+            Ok(false) => info!("State of user {} changed while it was loaded, not caching it", user_id),
+            Err(err) => {
+                error!("Redis error while caching state for user {}: {}", user_id, err);
+                telemetry::record_redis_error();
+            }
+        }
+    }
+
+    Ok(state)
 }
 
-async fn check_hwid(
-    pool: &sqlx::PgPool,
-    user_id: &str,
-    hwid: &str,
-) -> Result<Option<bool>, sqlx::Error> {
-    let row = sqlx::query_as::<_, (Option<String>,)>(
-        "SELECT hwid FROM users WHERE id = $1",
-    )
-    .bind(user_id)
-    .fetch_optional(pool)
-    .await?;
-
-    match row {
-        Some((Some(stored_hwid),)) => Ok(Some(stored_hwid == hwid)),
-        Some((None,)) => Ok(None), // No HWID set yet - needs auto-binding
-        None => Ok(Some(false)), // User not found
+/// The devices a license is checked against and how many of them may be bound
+#[derive(Clone, Copy)]
+struct DeviceScope<'a> {
+    /// `None` for the account wide devices
+    product_id: Option<&'a str>,
+    slots: i32,
+}
+
+impl<'a> DeviceScope<'a> {
+    /// `None` if the product doesn't check hardware at all
+    fn for_license(state: &CachedUserState, license: &CachedLicense, product_id: &'a str) -> Option<Self> {
+        match license.hwid_policy {
+            HwidPolicy::None => None,
+            HwidPolicy::PerAccount => Some(Self { product_id: None, slots: state.device_slots }),
+            HwidPolicy::PerProduct => Some(Self { product_id: Some(product_id), slots: 1 }),
+            HwidPolicy::Devices => Some(Self { product_id: Some(product_id), slots: license.hwid_device_limit.unwrap_or(1) }),
+        }
     }
 }
 
-async fn bind_hwid(
-    pool: &sqlx::PgPool,
-    user_id: &str,
-    hwid: &str,
-) -> Result<bool, sqlx::Error> {
-    let result = sqlx::query(
-        "UPDATE users SET hwid = $1, updated_at = NOW() WHERE id = $2 AND hwid IS NULL"
-    )
-    .bind(hwid)
-    .bind(user_id)
-    .execute(pool)
-    .await?;
-
-    Ok(result.rows_affected() > 0)
+/// Outcome of comparing the presented hardware with the devices bound to the account
+enum DeviceMatch<'a> {
+    /// The product's policy doesn't check hardware
+    Unchecked,
+    Exact,
+    /// Same machine by weighted component similarity, but some components changed
+    /// `hwid` is the one the device was bound with, bans on it still apply
+    Fuzzy { device_id: String, hwid: String, drifted: Vec<String> },
+    FreeSlot(DeviceScope<'a>),
+    Mismatch,
 }
 
-async fn check_if_banned(
-    pool: &sqlx::PgPool,
-    user_id: &str,
-) -> Result<bool, sqlx::Error> {
-    let row = sqlx::query_as::<_, (bool,)>(
-        "SELECT banned FROM users WHERE id = $1",
-    )
-    .bind(user_id)
-    .fetch_optional(pool)
-    .await?;
-
-    match row {
-        Some((banned,)) => Ok(banned),
-        None => Ok(false), // User not found
+fn match_device<'a>(
+    matcher: &FingerprintMatcher,
+    state: &CachedUserState,
+    scope: DeviceScope<'a>,
+    hwid: &str,
+    components: Option<&HwidComponents>,
+) -> DeviceMatch<'a> {
+    let devices: Vec<&CachedDevice> = state
+        .devices
+        .iter()
+        .filter(|device| device.product_id.as_deref() == scope.product_id)
+        .collect();
+
+    if devices.iter().any(|device| device.hwid == hwid) {
+        return DeviceMatch::Exact;
+    }
+
+    if let Some(presented) = components {
+        let best = devices
+            .iter()
+            .filter_map(|device| {
+                let stored = device.components.as_ref()?;
+                let drifted = matcher.fuzzy_match(stored, presented)?;
+                Some((matcher.similarity(stored, presented), device, drifted))
+            })
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+
+        if let Some((_, device, drifted)) = best {
+            return DeviceMatch::Fuzzy {
+                device_id: device.device_id.clone(),
+                hwid: device.hwid.clone(),
+                drifted,
+            };
+        }
+    }
+
+    if (devices.len() as i32) < scope.slots {
+        DeviceMatch::FreeSlot(scope)
+    } else {
+        DeviceMatch::Mismatch
     }
 }
 
+/// Check the banned HWID set in Redis, (re)loading it from Postgres when it isn't cached
 async fn check_hwid_banned(
-    pool: &sqlx::PgPool,
+    data: &AppState,
     hwid: &str,
-) -> Result<bool, sqlx::Error> {
-    let row = sqlx::query_as::<_, (String,)>(
-        "SELECT hwid FROM banned_hwids WHERE hwid = $1",
-    )
-    .bind(hwid)
-    .fetch_optional(pool)
-    .await?;
-
-    Ok(row.is_some())
+) -> Result<bool, RepositoryError> {
+    let cache = AuthCache::new(data.redis.clone());
+
+    match cache.is_hwid_banned(hwid).await {
+        Ok(Some(banned)) => Ok(banned),
+        Ok(None) => {
+            let banned_hwids = data.repos.bans.banned_hwids().await?;
+            let banned = banned_hwids.iter().any(|banned_hwid| banned_hwid == hwid);
+
+            if let Err(err) = cache.set_banned_hwids(&banned_hwids).await {
+                error!("Redis error while caching banned HWIDs: {}", err);
+                telemetry::record_redis_error();
+            }
+
+            Ok(banned)
+        }
+        Err(err) => {
+            error!("Redis error while checking banned HWID {}: {}", hwid, err);
+            telemetry::record_redis_error();
+            data.repos.bans.is_hwid_banned(hwid).await
+        }
+    }
+}
+
+/// What a granted license unlocks
+struct Entitlements {
+    tier: Option<String>,
+    features: Vec<String>,
+}
+
+/// Sign a successful authorization
+fn granted(
+    data: &AppState,
+    claims: &JwtClaims,
+    body: &AuthRequest,
+    session: Session,
+    expires_at: Option<i64>,
+    time_remaining: Option<i64>,
+    entitlements: Entitlements,
+) -> AuthResponse {
+    let signature = data.response_signer.sign(SignedClaims {
+        user_id: claims.sub.clone(),
+        product_id: body.product_id.clone(),
+        hwid: body.hwid.clone(),
+        nonce: body.nonce.clone(),
+        session_id: session.session_id.clone(),
+        expires_at,
+        tier: entitlements.tier.clone(),
+        entitlements: entitlements.features.clone(),
+    });
+
+    AuthResponse {
+        time_remaining,
+        session_id: session.session_id,
+        tier: entitlements.tier,
+        entitlements: entitlements.features,
+        signature,
+    }
+}
+
+/// Admins and devs get the product's highest tier and every entitlement any tier has
+async fn all_entitlements(data: &AppState, product_id: &str) -> Result<Entitlements, RepositoryError> {
+    let tiers = data.repos.products.tiers(product_id).await?;
+
+    let mut features: Vec<String> = Vec::new();
+    for feature in tiers.iter().flat_map(|tier| &tier.entitlements) {
+        if !features.contains(feature) {
+            features.push(feature.clone());
+        }
+    }
+
+    Ok(Entitlements { tier: tiers.last().map(|tier| tier.name.clone()), features })
 }
-     
 
+/// Open a session, failing if the limit is reached or Redis is unavailable
+async fn open_session(
+    data: &AppState,
+    claims: &JwtClaims,
+    body: &AuthRequest,
+    max_sessions: Option<i32>,
+) -> Result<Session, ApiError> {
+    match data.sessions.open(&claims.sub, &body.product_id, &body.hwid, max_sessions).await {
+        Ok(Some(session)) => Ok(session),
+        Ok(None) => {
+            info!("User {} hit the session limit ({:?}) for product {}", &claims.sub, max_sessions, &body.product_id);
+            Err(ApiError::SessionLimitReached)
+        }
+        Err(err) => {
+            error!("Redis error while opening session for user {}: {}", &claims.sub, err);
+            telemetry::record_redis_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth",
+    tag = "public",
+    request_body = AuthRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Authorized, a session was opened", body = ApiResponse<AuthResponse>),
+        (status = 401, description = "TOKEN_*, CHALLENGE_INVALID or HWID_MISMATCH", body = ErrorBody),
+        (status = 403, description = "ACCOUNT_BANNED, HWID_BANNED, LICENSE_NOT_FOUND or LICENSE_EXPIRED", body = ErrorBody),
+        (status = 409, description = "SESSION_LIMIT_REACHED", body = ErrorBody),
+    ),
+)]
 pub async fn auth(
     claims: JwtClaims,
     body: web::Json<AuthRequest>,
     data: web::Data<AppState>,
-) -> HttpResponse {
-    // admins & devs always have access to all products
-    if matches!(claims.role, crate::handlers::account::Role::Admin | crate::handlers::account::Role::Dev) {
-        return HttpResponse::Ok().json(AuthResponse {
-            success: true,
-            time_remaining: Some(i64::MAX),
-            message: None,
-        });
-    }
+) -> Result<ApiResponse<AuthResponse>, ApiError> {
+    let result = authorize(&claims, &body, &data).await;
+
+    // product_id is client supplied, only label it once a license proved the product exists
+    let product = match &result {
+        Ok(_)
+        | Err(ApiError::LicenseExpired | ApiError::HwidMismatch | ApiError::HwidBindFailed | ApiError::SessionLimitReached) => body.product_id.clone(),
+        Err(_) => "unknown".to_string(),
+    };
+    metrics::counter!("auth_requests_total", "outcome" => telemetry::outcome(&result), "product" => product).increment(1);
+
+    result
+}
 
-    match check_if_banned(&data.db_pool, &claims.sub).await {
+async fn authorize(claims: &JwtClaims, body: &AuthRequest, data: &AppState) -> Result<ApiResponse<AuthResponse>, ApiError> {
+    // Every request must spend a fresh challenge nonce, so recorded responses can't be replayed
+    match data.challenges.consume(&claims.sub, &body.nonce).await {
         Ok(true) => {
-            info!("Banned user {} attempted authentication", &claims.sub);
-            return HttpResponse::Forbidden().json(AuthResponse {
-                success: false,
-                time_remaining: None,
-                message: Some("Your account has been banned. Contact support for more information.".to_string()),
-            });
-        },
+            // valid nonce, continue
+        }
         Ok(false) => {
-            // not banned, continue
+            info!("User {} sent an invalid, expired or reused challenge nonce", &claims.sub);
+            return Err(ApiError::ChallengeInvalid);
+        }
+        Err(err) => {
+            error!("Redis error while consuming challenge for user {}: {}", &claims.sub, err);
+            telemetry::record_redis_error();
+            return Err(ApiError::Internal);
         }
+    }
+
+    // admins & devs always have access to all products
+    if matches!(claims.role, crate::handlers::account::Role::Admin | crate::handlers::account::Role::Dev) {
+        let entitlements = match all_entitlements(data, &body.product_id).await {
+            Ok(entitlements) => entitlements,
+            Err(err) => {
+                error!("Database error while loading tiers of product {}: {}", &body.product_id, err);
+                telemetry::record_db_error();
+                return Err(ApiError::Internal);
+            }
+        };
+
+        // sessions are still tracked, but never limited
+        let session = open_session(data, claims, body, None).await?;
+        return Ok(ApiResponse::new(granted(data, claims, body, session, Some(i64::MAX), Some(i64::MAX), entitlements)));
+    }
+
+    let state = match get_user_state(data, &claims.sub).await {
+        Ok(state) => state,
         Err(err) => {
-            error!("Database error while checking ban status for user {}: {}", &claims.sub, err);
-            return HttpResponse::InternalServerError().json(AuthResponse {
-                success: false,
-                time_remaining: None,
-                message: Some("Internal server error - contact support.".to_string()),
-            });
+            error!("Database error while loading state for user {}: {}", &claims.sub, err);
+            telemetry::record_db_error();
+            return Err(ApiError::Internal);
         }
+    };
+
+    // User not found is treated as not banned, the license check below rejects it
+    if state.as_ref().is_some_and(|state| state.banned) {
+        info!("Banned user {} attempted authentication", &claims.sub);
+        return Err(ApiError::AccountBanned);
     }
 
     // Check if HWID is banned
-    match check_hwid_banned(&data.db_pool, &body.hwid).await {
+    match check_hwid_banned(data, &body.hwid).await {
         Ok(true) => {
             info!("Banned HWID {} attempted authentication (user: {})", &body.hwid, &claims.sub);
-            return HttpResponse::Forbidden().json(AuthResponse {
-                success: false,
-                time_remaining: None,
-                message: Some("Your hardware has been banned. Contact support for more information.".to_string()),
-            });
+            return Err(ApiError::HwidBanned);
         },
         Ok(false) => {
             // HWID not banned, continue
         }
         Err(err) => {
             error!("Database error while checking HWID ban for {}: {}", &body.hwid, err);
-            return HttpResponse::InternalServerError().json(AuthResponse {
-                success: false,
-                time_remaining: None,
-                message: Some("Internal server error - contact support.".to_string()),
-            });
+            telemetry::record_db_error();
+            return Err(ApiError::Internal);
         }
     }
 
-    match check_hwid(&data.db_pool, &claims.sub, &body.hwid).await {
-        Ok(Some(true)) => {
+    // Also covers a missing user, which has no licenses
+    let Some((state, license)) = state
+        .as_ref()
+        .and_then(|state| state.licenses.get(&body.product_id).map(|license| (state, license.clone())))
+    else {
+        info!("User {} has no license for product {}", &claims.sub, &body.product_id);
+        return Err(ApiError::LicenseNotFound);
+    };
+
+    if license.expires_at.is_some_and(|expires_at| expires_at <= Utc::now().timestamp()) {
+        info!("License of user {} for product {} has expired", &claims.sub, &body.product_id);
+        return Err(ApiError::LicenseExpired);
+    }
+
+    let scope = DeviceScope::for_license(state, &license, &body.product_id);
+    let device_match = match scope {
+        Some(scope) => match_device(&data.fingerprint_matcher, state, scope, &body.hwid, body.components.as_ref()),
+        None => DeviceMatch::Unchecked,
+    };
+
+    match device_match {
+        DeviceMatch::Unchecked => {
+            info!("Product {} does not check hardware, skipping HWID check for user {}", &body.product_id, &claims.sub);
+        },
+        DeviceMatch::Exact => {
             info!("HWID check passed for user {}", &claims.sub);
         },
-        Ok(Some(false)) => {
+        DeviceMatch::Fuzzy { device_id, hwid, drifted } => {
+            // The presented HWID passed the ban check above, the machine it claims to be must too
+            match check_hwid_banned(data, &hwid).await {
+                Ok(true) => {
+                    info!("Banned HWID {} attempted authentication as {} (user: {})", &hwid, &body.hwid, &claims.sub);
+                    return Err(ApiError::HwidBanned);
+                }
+                Ok(false) => {}
+                Err(err) => {
+                    error!("Database error while checking HWID ban for {}: {}", &hwid, err);
+                    telemetry::record_db_error();
+                    return Err(ApiError::Internal);
+                }
+            }
+            info!("HWID check passed for user {} by component match on device {}, drifted components: {:?}", &claims.sub, device_id, drifted);
+
+            // body.components is always set for a fuzzy match
+            if let Some(components) = &body.components {
+                match data.repos.devices.update_fingerprint(&device_id, &body.hwid, components).await {
+                    Ok(()) => {
+                        let cache = AuthCache::new(data.redis.clone());
+                        if let Err(err) = cache.invalidate_user(&claims.sub).await {
+                            error!("Failed to invalidate cached state for user {}: {}", &claims.sub, err);
+                            telemetry::record_redis_error();
+                        }
+                    }
+                    Err(err) => {
+                        // The match already passed, the stale fingerprint is retried next time
+                        error!("Database error while updating fingerprint of device {}: {}", device_id, err);
+                        telemetry::record_db_error();
+                    }
+                }
+            }
+        },
+        DeviceMatch::Mismatch => {
             info!("HWID check failed for user {}", &claims.sub);
-            return HttpResponse::Unauthorized().json(AuthResponse {
-                success: false,
-                time_remaining: None,
-                message: Some("HWID mismatch. If you are on the same machine or recently changed your hardware, please contact support.".to_string()),
-            });
+            return Err(ApiError::HwidMismatch);
         },
-        Ok(None) => {
-            // No HWID set yet - auto-bind it
-            info!("No HWID set for user {}, attempting to bind HWID: {}", &claims.sub, &body.hwid);
-            match bind_hwid(&data.db_pool, &claims.sub, &body.hwid).await {
+        DeviceMatch::FreeSlot(scope) => {
+            // Unknown HWID with a free slot - auto-bind it
+            info!("Free device slot for user {}, attempting to bind HWID: {}", &claims.sub, &body.hwid);
+            match data.repos.devices.bind(&claims.sub, scope.product_id, scope.slots, &body.hwid, body.components.as_ref()).await {
                 Ok(true) => {
                     info!("Successfully bound HWID for user {}", &claims.sub);
+                    let cache = AuthCache::new(data.redis.clone());
+                    if let Err(err) = cache.invalidate_user(&claims.sub).await {
+                        error!("Failed to invalidate cached state for user {}: {}", &claims.sub, err);
+                        telemetry::record_redis_error();
+                    }
                 },
                 Ok(false) => {
                     error!("Failed to bind HWID for user {} - no rows affected", &claims.sub);
-                    return HttpResponse::InternalServerError().json(AuthResponse {
-                        success: false,
-                        time_remaining: None,
-                        message: Some("Failed to bind HWID - contact support.".to_string()),
-                    });
+                    return Err(ApiError::HwidBindFailed);
                 },
                 Err(err) => {
                     error!("Database error while binding HWID for user {}: {}", &claims.sub, err);
-                    return HttpResponse::InternalServerError().json(AuthResponse {
-                        success: false,
-                        time_remaining: None,
-                        message: Some("Internal server error - contact support.".to_string()),
-                    });
+                    telemetry::record_db_error();
+                    return Err(ApiError::Internal);
                 }
             }
         },
-        Err(err) => {
-            error!("Database error while checking HWID for user {}: {}", &claims.sub, err);
-            return HttpResponse::InternalServerError().json(AuthResponse {
-                success: false,
-                time_remaining: None,
-                message: Some("Internal server error - contact support.".to_string()),
-            });
-        }
     }
 
+    let session = open_session(data, claims, body, license.max_sessions).await?;
 
-    match get_license_time_remaining(&data.db_pool, &claims.sub, &body.product_id).await {
-        Ok(Some(time)) => {
-            info!("User {} authenticated for product {} with {} seconds remaining", &claims.sub, &body.product_id, time);
-            return HttpResponse::Ok().json(AuthResponse {
-                success: true,
-                time_remaining: Some(time),
-                message: Some(format!("Welcome back, {}.", &claims.sub.to_string())),
-            });
-        },
-        Ok(None) => {
-            return HttpResponse::Ok().json(AuthResponse {
-                success: false,
-                time_remaining: None,
-                message: Some("Product not found or expired.".to_string()),
-            });
-        }
-        Err(err) => {
-            error!("Database error while checking license for user {} and product {}: {}", &claims.sub, &body.product_id, err);
-            return HttpResponse::InternalServerError().json(AuthResponse {
-                success: false,
-                time_remaining: None,
-                message: Some("Internal server error - contact support.".to_string()),
-            });
+    let time = license.expires_at.map(|expires_at| expires_at - Utc::now().timestamp());
+    match time {
+        Some(time) => info!("User {} authenticated for product {} with {} seconds remaining (session {})", &claims.sub, &body.product_id, time, session.session_id),
+        None => info!("User {} authenticated for product {} with a lifetime license (session {})", &claims.sub, &body.product_id, session.session_id),
+    }
+    let entitlements = Entitlements { tier: license.tier, features: license.entitlements };
+    Ok(ApiResponse::new(granted(data, claims, body, session, license.expires_at, time, entitlements))
+        .with_message(format!("Welcome back, {}.", &claims.sub)))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web};
+    use serde_json::{Value, json};
+    use std::sync::Arc;
+
+    use crate::AppState;
+    use crate::auth::{MemoryChallenges, jwt};
+    use crate::config::Config;
+    use crate::handlers::account::Role;
+    use crate::repository::{HwidPolicy, MemoryRepository};
+
+    fn state(challenges: MemoryChallenges) -> web::Data<AppState> {
+        let repository = Arc::new(MemoryRepository::new());
+        for user_id in ["user-1", "user-2"] {
+            repository.add_user(user_id, &format!("{}@example.com", user_id), "hash", Role::User, 1);
         }
+        repository.add_product("game", "Game", HwidPolicy::None, None);
+        repository.add_license("user-1", "game", chrono::Utc::now().timestamp() + 3600);
+
+        let mut state = AppState::in_memory(Config::default(), repository).unwrap();
+        state.challenges = Arc::new(challenges);
+        web::Data::new(state)
+    }
+
+    fn bearer(state: &AppState, user_id: &str) -> (&'static str, String) {
+        let token = jwt::generate_token(&state.jwt_keys, user_id.to_string(), format!("{}@example.com", user_id), Role::User).unwrap();
+        ("Authorization", format!("Bearer {}", token))
+    }
+
+    macro_rules! call {
+        ($app:expr, $request:expr) => {{
+            let response = test::call_service(&$app, $request.to_request()).await;
+            let status = response.status().as_u16();
+            let body: Value = test::read_body_json(response).await;
+            (status, body)
+        }};
+    }
+
+    fn challenge(state: &AppState, user_id: &str) -> test::TestRequest {
+        test::TestRequest::post().uri("/api/v1/auth/challenge").insert_header(bearer(state, user_id))
+    }
+
+    fn auth(state: &AppState, user_id: &str, nonce: &Value) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/api/v1/auth")
+            .insert_header(bearer(state, user_id))
+            .set_json(json!({ "product_id": "game", "hwid": "hwid-a", "nonce": nonce }))
+    }
+
+    #[actix_web::test]
+    async fn nonces_are_spent_by_the_first_auth() {
+        let state = state(MemoryChallenges::default());
+        let app = test::init_service(crate::app(state.clone(), false)).await;
+
+        let (_, body) = call!(app, challenge(&state, "user-1"));
+        let (status, response) = call!(app, auth(&state, "user-1", &body["nonce"]));
+        assert_eq!(status, 200, "{}", response);
+
+        // Replaying the recorded request
+        let (status, response) = call!(app, auth(&state, "user-1", &body["nonce"]));
+        assert_eq!((status, response["code"].as_str()), (401, Some("CHALLENGE_INVALID")));
+    }
+
+    #[actix_web::test]
+    async fn expired_nonces_are_refused() {
+        let state = state(MemoryChallenges::with_ttl(0));
+        let app = test::init_service(crate::app(state.clone(), false)).await;
+
+        let (_, body) = call!(app, challenge(&state, "user-1"));
+        let (status, response) = call!(app, auth(&state, "user-1", &body["nonce"]));
+        assert_eq!((status, response["code"].as_str()), (401, Some("CHALLENGE_INVALID")));
     }
-}
\ No newline at end of file
+
+    #[actix_web::test]
+    async fn nonces_only_work_for_the_user_they_were_issued_to() {
+        let state = state(MemoryChallenges::default());
+        let app = test::init_service(crate::app(state.clone(), false)).await;
+
+        let (_, body) = call!(app, challenge(&state, "user-2"));
+        let (status, response) = call!(app, auth(&state, "user-1", &body["nonce"]));
+        assert_eq!((status, response["code"].as_str()), (401, Some("CHALLENGE_INVALID")));
+    }
+}
@@ -0,0 +1,41 @@
+use actix_web::web;
+use tracing::{error, info};
+use authit_types::ChallengeResponse;
+
+use crate::AppState;
+use crate::auth::JwtClaims;
+use crate::auth::challenge::CHALLENGE_TTL_SECONDS;
+use crate::error::{ApiError, ErrorBody};
+use crate::response::ApiResponse;
+use crate::telemetry;
+
+/// Issue a single-use nonce that must be sent with the next `/auth` request
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/challenge",
+    tag = "public",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Nonce for the next /auth request", body = ApiResponse<ChallengeResponse>),
+        (status = 401, description = "TOKEN_MISSING, TOKEN_INVALID or TOKEN_EXPIRED", body = ErrorBody),
+    ),
+)]
+pub async fn challenge(
+    claims: JwtClaims,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse<ChallengeResponse>, ApiError> {
+    match data.challenges.issue(&claims.sub).await {
+        Ok(nonce) => {
+            info!("Issued auth challenge for user {}", claims.sub);
+            Ok(ApiResponse::new(ChallengeResponse {
+                nonce,
+                expires_in: CHALLENGE_TTL_SECONDS,
+            }))
+        }
+        Err(err) => {
+            error!("Redis error while issuing challenge for user {}: {}", claims.sub, err);
+            telemetry::record_redis_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
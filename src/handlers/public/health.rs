@@ -1,6 +1,265 @@
-use actix_web::HttpResponse;
+use actix_web::web;
+use redis::aio::ConnectionManager;
+use serde::Serialize;
+use utoipa::ToSchema;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::error;
 
+use crate::AppState;
+use crate::error::{ApiError, ErrorBody};
+use crate::response::ApiResponse;
 
-pub async fn health_check() -> HttpResponse {
-    HttpResponse::Ok().body("OK")
-}
\ No newline at end of file
+/// How long a dependency gets to answer before it counts as down
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, ToSchema)]
+pub struct BuildInfo {
+    version: &'static str,
+    git_hash: &'static str,
+    uptime_seconds: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Up,
+    Down,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DependencyStatus {
+    status: Status,
+    latency_ms: f64,
+}
+
+/// A dependency the instance runs without (the in-memory server) is left out
+#[derive(Serialize, ToSchema)]
+pub struct Dependencies {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    postgres: Option<DependencyStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redis: Option<DependencyStatus>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReadinessResponse {
+    #[serde(flatten)]
+    build: BuildInfo,
+    dependencies: Dependencies,
+}
+
+fn build_info(data: &AppState) -> BuildInfo {
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: env!("AUTHIT_GIT_HASH"),
+        uptime_seconds: data.started_at.elapsed().as_secs(),
+    }
+}
+
+/// Time a dependency check, logging (but not returning) the failure reason
+async fn check<E: std::fmt::Display>(name: &str, probe: impl Future<Output = Result<(), E>>) -> DependencyStatus {
+    let started = Instant::now();
+    let result = actix_web::rt::time::timeout(CHECK_TIMEOUT, probe).await;
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    let status = match result {
+        Ok(Ok(())) => Status::Up,
+        Ok(Err(err)) => {
+            error!("Readiness check failed for {}: {}", name, err);
+            Status::Down
+        }
+        Err(_) => {
+            error!("Readiness check for {} timed out after {:?}", name, CHECK_TIMEOUT);
+            Status::Down
+        }
+    };
+
+    DependencyStatus { status, latency_ms }
+}
+
+async fn ping_postgres(pool: &sqlx::PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT 1").execute(pool).await?;
+    Ok(())
+}
+
+async fn ping_redis(redis: &ConnectionManager) -> Result<(), redis::RedisError> {
+    redis::cmd("PING").query_async::<String>(&mut redis.clone()).await?;
+    Ok(())
+}
+
+/// Liveness, answers as long as the process is serving requests
+#[utoipa::path(
+    get,
+    path = "/api/v1/health-check",
+    tag = "public",
+    responses((status = 200, description = "Process is serving requests", body = ApiResponse<BuildInfo>)),
+)]
+pub async fn health_check(
+    data: web::Data<AppState>,
+) -> ApiResponse<BuildInfo> {
+    ApiResponse::new(build_info(&data)).with_message("OK")
+}
+
+/// Same as `health_check`, under the name orchestrators expect
+#[utoipa::path(
+    get,
+    path = "/api/v1/health/live",
+    tag = "public",
+    responses((status = 200, description = "Process is serving requests", body = ApiResponse<BuildInfo>)),
+)]
+pub async fn liveness_check(
+    data: web::Data<AppState>,
+) -> ApiResponse<BuildInfo> {
+    health_check(data).await
+}
+
+/// Readiness, 503 if Postgres or Redis can't be reached so the instance is taken out of rotation
+#[utoipa::path(
+    get,
+    path = "/api/v1/health/ready",
+    tag = "public",
+    responses(
+        (status = 200, description = "Postgres and Redis are reachable", body = ApiResponse<ReadinessResponse>),
+        (status = 503, description = "A dependency is down, `details` carries the report", body = ErrorBody),
+    ),
+)]
+pub async fn readiness_check(
+    data: web::Data<AppState>,
+) -> Result<ApiResponse<ReadinessResponse>, ApiError> {
+    let dependencies = Dependencies {
+        postgres: match &data.db_pool {
+            Some(pool) => Some(check("postgres", ping_postgres(pool)).await),
+            None => None,
+        },
+        redis: match &data.redis {
+            Some(redis) => Some(check("redis", ping_redis(redis)).await),
+            None => None,
+        },
+    };
+    let ready = [&dependencies.postgres, &dependencies.redis]
+        .into_iter()
+        .flatten()
+        .all(|dependency| matches!(dependency.status, Status::Up));
+
+    let response = ReadinessResponse {
+        build: build_info(&data),
+        dependencies,
+    };
+
+    if !ready {
+        return Err(ApiError::ServiceUnavailable(serde_json::to_value(&response).unwrap_or_default()));
+    }
+
+    Ok(ApiResponse::new(response).with_message("Ready"))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web};
+    use serde_json::Value;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::AppState;
+    use crate::config::Config;
+    use crate::repository::MemoryRepository;
+
+    /// Answer every command of a RESP connection with an error
+    fn refuse_commands(stream: TcpStream) -> std::io::Result<()> {
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            // `*<n>` then n `$<len>` bulk strings
+            let arguments: usize = line.trim_start_matches('*').trim().parse().unwrap_or(0);
+            for _ in 0..arguments {
+                line.clear();
+                reader.read_line(&mut line)?;
+                let len: usize = line.trim_start_matches('$').trim().parse().unwrap_or(0);
+                reader.by_ref().take(len as u64 + 2).read_to_end(&mut Vec::new())?;
+            }
+            writer.write_all(b"-ERR unavailable\r\n")?;
+        }
+    }
+
+    /// A Redis that accepts connections but fails every command
+    async fn failing_redis() -> redis::aio::ConnectionManager {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                std::thread::spawn(move || refuse_commands(stream));
+            }
+        });
+
+        redis::aio::ConnectionManager::new(redis::Client::open(url).unwrap()).await.unwrap()
+    }
+
+    /// Postgres on a port nothing listens on
+    fn unreachable_postgres() -> sqlx::PgPool {
+        sqlx::postgres::PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(200))
+            .connect_lazy("postgres://authit@127.0.0.1:1/authit")
+            .unwrap()
+    }
+
+    async fn get(state: AppState, path: &str) -> (u16, Value) {
+        let app = test::init_service(crate::app(web::Data::new(state), false)).await;
+        let response = test::call_service(&app, test::TestRequest::get().uri(path).to_request()).await;
+        let status = response.status().as_u16();
+        (status, test::read_body_json(response).await)
+    }
+
+    fn state() -> AppState {
+        AppState::in_memory(Config::default(), Arc::new(MemoryRepository::new())).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn ready_without_dependencies() {
+        let (status, body) = get(state(), "/api/v1/health/ready").await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["dependencies"], serde_json::json!({}));
+    }
+
+    #[actix_web::test]
+    async fn not_ready_while_postgres_is_down() {
+        let mut state = state();
+        state.db_pool = Some(unreachable_postgres());
+
+        let (status, body) = get(state, "/api/v1/health/ready").await;
+        assert_eq!((status, body["code"].as_str()), (503, Some("SERVICE_UNAVAILABLE")));
+        assert_eq!(body["details"]["dependencies"]["postgres"]["status"], "down");
+        assert!(body["details"]["dependencies"]["redis"].is_null());
+        assert!(body["details"]["version"].is_string());
+    }
+
+    #[actix_web::test]
+    async fn not_ready_while_redis_is_down() {
+        let mut state = state();
+        state.redis = Some(failing_redis().await);
+
+        let (status, body) = get(state, "/api/v1/health/ready").await;
+        assert_eq!((status, body["code"].as_str()), (503, Some("SERVICE_UNAVAILABLE")));
+        assert_eq!(body["details"]["dependencies"]["redis"]["status"], "down");
+    }
+
+    #[actix_web::test]
+    async fn live_while_dependencies_are_down() {
+        for path in ["/api/v1/health/live", "/api/v1/health-check"] {
+            let mut state = state();
+            state.db_pool = Some(unreachable_postgres());
+            state.redis = Some(failing_redis().await);
+
+            let (status, body) = get(state, path).await;
+            assert_eq!(status, 200, "{}: {}", path, body);
+            assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        }
+    }
+}
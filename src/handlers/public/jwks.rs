@@ -0,0 +1,27 @@
+use actix_web::{HttpResponse, web};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::AppState;
+use crate::auth::keys::Jwk;
+
+/// RFC 7517 JWK Set, served bare since JWT libraries fetch it directly
+#[derive(Serialize, ToSchema)]
+pub struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+/// Publish the public keys login tokens are signed with, so other services can verify them
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    tag = "public",
+    responses((status = 200, description = "Public keys login tokens are signed with, without the response envelope", body = JwkSet)),
+)]
+pub async fn jwks(
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(JwkSet {
+        keys: data.jwt_keys.jwks(),
+    })
+}
@@ -0,0 +1,78 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use subtle::ConstantTimeEq;
+
+use crate::AppState;
+use crate::error::{ApiError, ErrorBody};
+
+/// Prometheus scrape endpoint, requires `metrics.token` as a bearer token when one is configured
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "public",
+    security((), ("metrics_token" = [])),
+    responses(
+        (status = 200, description = "Prometheus text format", body = String, content_type = "text/plain"),
+        (status = 401, description = "Missing or wrong metrics token", body = ErrorBody),
+    ),
+)]
+pub async fn metrics(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    if let Some(expected) = &data.config.metrics.token {
+        let token = req
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(ApiError::TokenMissing)?;
+
+        // Constant time, so response timing doesn't reveal how much of a guess was right
+        if !bool::from(token.as_bytes().ct_eq(expected.as_bytes())) {
+            return Err(ApiError::TokenInvalid);
+        }
+    }
+
+    // Pool usage is sampled at scrape time rather than tracked on every checkout
+    if let Some(pool) = &data.db_pool {
+        let idle = pool.num_idle() as f64;
+        metrics::gauge!("db_pool_connections", "state" => "idle").set(idle);
+        metrics::gauge!("db_pool_connections", "state" => "active").set(pool.size() as f64 - idle);
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(data.metrics.render()))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web};
+    use std::sync::Arc;
+
+    use crate::AppState;
+    use crate::config::Config;
+    use crate::repository::MemoryRepository;
+
+    #[actix_web::test]
+    async fn scrapes_need_the_exact_token() {
+        let mut config = Config::default();
+        config.metrics.token = Some("scrape-token".to_string());
+        let state = web::Data::new(AppState::in_memory(config, Arc::new(MemoryRepository::new())).unwrap());
+        let app = test::init_service(crate::app(state, true)).await;
+
+        for (authorization, expected) in [
+            (None, 401),
+            (Some("Bearer scrape-toke"), 401),
+            (Some("Bearer scrape-token2"), 401),
+            (Some("Bearer scrape-token"), 200),
+        ] {
+            let mut request = test::TestRequest::get().uri("/metrics");
+            if let Some(authorization) = authorization {
+                request = request.insert_header(("Authorization", authorization));
+            }
+            let response = test::call_service(&app, request.to_request()).await;
+            assert_eq!(response.status().as_u16(), expected, "{:?}", authorization);
+        }
+    }
+}
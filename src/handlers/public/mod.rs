@@ -1,4 +1,14 @@
 pub mod auth;
+pub mod challenge;
 pub mod health;
+pub mod jwks;
+pub mod metrics;
+pub mod openapi;
+pub mod signing_keys;
 pub use auth::*;
-pub use health::*;
\ No newline at end of file
+pub use challenge::*;
+pub use health::*;
+pub use jwks::*;
+pub use metrics::*;
+pub use openapi::*;
+pub use signing_keys::*;
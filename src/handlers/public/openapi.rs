@@ -0,0 +1,14 @@
+use actix_web::HttpResponse;
+
+use crate::openapi::SPEC;
+
+/// The OpenAPI 3 document for this API, also rendered at `/api-docs`
+#[utoipa::path(
+    get,
+    path = "/api-docs/openapi.json",
+    tag = "public",
+    responses((status = 200, description = "OpenAPI 3 document", content_type = "application/json")),
+)]
+pub async fn openapi() -> HttpResponse {
+    HttpResponse::Ok().json(&*SPEC)
+}
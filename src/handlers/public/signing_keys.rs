@@ -0,0 +1,20 @@
+use actix_web::web;
+use authit_types::SigningKeysResponse;
+
+use crate::AppState;
+use crate::response::ApiResponse;
+
+/// Publish the public keys `/auth` responses are signed with
+#[utoipa::path(
+    get,
+    path = "/.well-known/authit-signing-keys",
+    tag = "public",
+    responses((status = 200, description = "Public keys /auth responses are signed with", body = ApiResponse<SigningKeysResponse>)),
+)]
+pub async fn signing_keys(
+    data: web::Data<AppState>,
+) -> ApiResponse<SigningKeysResponse> {
+    ApiResponse::new(SigningKeysResponse {
+        keys: data.response_signer.public_keys(),
+    })
+}
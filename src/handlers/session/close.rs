@@ -0,0 +1,39 @@
+use actix_web::web;
+use tracing::{error, info};
+use authit_types::CloseSessionRequest;
+
+use crate::AppState;
+use crate::auth::JwtClaims;
+use crate::error::{ApiError, ErrorBody};
+use crate::response::ApiResponse;
+use crate::telemetry;
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/session/close",
+    tag = "session",
+    request_body = CloseSessionRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Session closed, its slot is free again", body = ApiResponse),
+        (status = 404, description = "SESSION_NOT_FOUND, also for sessions of other users", body = ErrorBody),
+    ),
+)]
+pub async fn close_session(
+    claims: JwtClaims,
+    body: web::Json<CloseSessionRequest>,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse, ApiError> {
+    match data.sessions.close(&claims.sub, &body.session_id).await {
+        Ok(true) => Ok(ApiResponse::message("Session closed.")),
+        Ok(false) => {
+            info!("Close for unknown or expired session {} (user: {})", body.session_id, claims.sub);
+            Err(ApiError::SessionNotFound)
+        }
+        Err(err) => {
+            error!("Redis error while closing session {}: {}", body.session_id, err);
+            telemetry::record_redis_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use actix_web::web;
+use tracing::{error, info};
+use authit_types::{HeartbeatRequest, HeartbeatResponse};
+
+use crate::AppState;
+use crate::auth::JwtClaims;
+use crate::auth::session::SESSION_TTL_SECONDS;
+use crate::error::{ApiError, ErrorBody};
+use crate::response::ApiResponse;
+use crate::telemetry;
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/session/heartbeat",
+    tag = "session",
+    request_body = HeartbeatRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Session kept alive", body = ApiResponse<HeartbeatResponse>),
+        (status = 404, description = "SESSION_NOT_FOUND", body = ErrorBody),
+    ),
+)]
+pub async fn heartbeat(
+    claims: JwtClaims,
+    body: web::Json<HeartbeatRequest>,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse<HeartbeatResponse>, ApiError> {
+    match data.sessions.heartbeat(&claims.sub, &body.session_id).await {
+        Ok(true) => Ok(ApiResponse::new(HeartbeatResponse {
+            expires_in: SESSION_TTL_SECONDS,
+        })),
+        Ok(false) => {
+            info!("Heartbeat for unknown or expired session {} (user: {})", body.session_id, claims.sub);
+            Err(ApiError::SessionNotFound)
+        }
+        Err(err) => {
+            error!("Redis error during heartbeat for session {}: {}", body.session_id, err);
+            telemetry::record_redis_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use actix_web::web;
+use tracing::{error, info};
+
+use crate::AppState;
+use crate::auth::JwtClaims;
+use crate::error::{ApiError, ErrorBody};
+use crate::handlers::account::Role;
+use crate::response::ApiResponse;
+use crate::telemetry;
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/session/{session_id}",
+    tag = "session",
+    params(("session_id" = String, Path, description = "Session to kill")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Session killed", body = ApiResponse),
+        (status = 403, description = "PERMISSION_DENIED, admin only", body = ErrorBody),
+        (status = 404, description = "SESSION_NOT_FOUND", body = ErrorBody),
+    ),
+)]
+pub async fn kill_session(
+    claims: JwtClaims,
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse, ApiError> {
+    let session_id = path.into_inner();
+    info!("Kill session attempt by {} for session {}", claims.sub, session_id);
+
+    // Check if requester is Admin
+    if !matches!(claims.role, Role::Admin) {
+        info!("Kill session denied: user {} is not an admin (role: {:?})", claims.sub, claims.role);
+        return Err(ApiError::PermissionDenied("Only admins can kill sessions.".to_string()));
+    }
+
+    match data.sessions.kill(&session_id).await {
+        Ok(true) => Ok(ApiResponse::message("Session killed.")),
+        Ok(false) => Err(ApiError::SessionNotFound),
+        Err(err) => {
+            error!("Redis error while killing session {}: {}", session_id, err);
+            telemetry::record_redis_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use actix_web::web;
+use tracing::{error, info};
+use authit_types::ListSessionsResponse;
+
+use crate::AppState;
+use crate::auth::JwtClaims;
+use crate::error::{ApiError, ErrorBody};
+use crate::handlers::account::Role;
+use crate::response::ApiResponse;
+use crate::telemetry;
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/session/list",
+    tag = "session",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Every live session, with the linked accounts of their users", body = ApiResponse<ListSessionsResponse>),
+        (status = 403, description = "PERMISSION_DENIED, admin only", body = ErrorBody),
+    ),
+)]
+pub async fn list_sessions(
+    claims: JwtClaims,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse<ListSessionsResponse>, ApiError> {
+    // Check if requester is Admin
+    if !matches!(claims.role, Role::Admin) {
+        info!("List sessions denied: user {} is not an admin (role: {:?})", claims.sub, claims.role);
+        return Err(ApiError::PermissionDenied("Only admins can list sessions.".to_string()));
+    }
+
+    let sessions = match data.sessions.list().await {
+        Ok(sessions) => sessions,
+        Err(err) => {
+            error!("Redis error while listing sessions: {}", err);
+            telemetry::record_redis_error();
+            return Err(ApiError::Internal);
+        }
+    };
+
+    let mut user_ids: Vec<String> = sessions.iter().map(|session| session.user_id.clone()).collect();
+    user_ids.sort();
+    user_ids.dedup();
+    match data.repos.identities.for_users(&user_ids).await {
+        Ok(linked_accounts) => Ok(ApiResponse::new(ListSessionsResponse { sessions, linked_accounts })),
+        Err(err) => {
+            error!("Database error while looking up linked accounts: {}", err);
+            telemetry::record_db_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
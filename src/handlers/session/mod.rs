@@ -0,0 +1,8 @@
+pub mod heartbeat;
+pub use heartbeat::*;
+pub mod close;
+pub use close::*;
+pub mod list;
+pub use list::*;
+pub mod kill;
+pub use kill::*;
@@ -0,0 +1,88 @@
+use actix_web::web;
+use tracing::{error, info};
+use authit_types::{DeliveriesQuery, WebhookDeliveriesResponse};
+
+use crate::AppState;
+use crate::auth::JwtClaims;
+use crate::error::{ApiError, ErrorBody};
+use crate::handlers::account::Role;
+use crate::response::ApiResponse;
+use crate::telemetry;
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 500;
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/deliveries",
+    tag = "webhook",
+    params(DeliveriesQuery),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "The delivery log, newest first", body = ApiResponse<WebhookDeliveriesResponse>),
+        (status = 400, description = "INVALID_REQUEST, limit out of range", body = ErrorBody),
+        (status = 403, description = "PERMISSION_DENIED, admin only", body = ErrorBody),
+    ),
+)]
+pub async fn list_deliveries(
+    claims: JwtClaims,
+    query: web::Query<DeliveriesQuery>,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse<WebhookDeliveriesResponse>, ApiError> {
+    // Check if requester is Admin
+    if !matches!(claims.role, Role::Admin) {
+        info!("List deliveries denied: user {} is not an admin (role: {:?})", claims.sub, claims.role);
+        return Err(ApiError::PermissionDenied("Only admins can manage webhooks.".to_string()));
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(ApiError::InvalidRequest(format!("limit must be between 1 and {}.", MAX_LIMIT)));
+    }
+
+    match data.repos.webhooks.deliveries(query.webhook_id.as_deref(), query.status, limit).await {
+        Ok(deliveries) => Ok(ApiResponse::new(WebhookDeliveriesResponse { deliveries })),
+        Err(err) => {
+            error!("Database error listing webhook deliveries: {}", err);
+            telemetry::record_db_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks/deliveries/{delivery_id}/redeliver",
+    tag = "webhook",
+    params(("delivery_id" = String, Path, description = "Delivery to send again")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Queued again with a fresh set of attempts", body = ApiResponse),
+        (status = 403, description = "PERMISSION_DENIED, admin only", body = ErrorBody),
+        (status = 404, description = "DELIVERY_NOT_FOUND", body = ErrorBody),
+    ),
+)]
+pub async fn redeliver(
+    claims: JwtClaims,
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse, ApiError> {
+    let delivery_id = path.into_inner();
+    info!("Redeliver attempt by {} for {}", claims.sub, delivery_id);
+
+    // Check if requester is Admin
+    if !matches!(claims.role, Role::Admin) {
+        info!("Redeliver denied: user {} is not an admin (role: {:?})", claims.sub, claims.role);
+        return Err(ApiError::PermissionDenied("Only admins can manage webhooks.".to_string()));
+    }
+
+    match data.repos.webhooks.redeliver(&delivery_id).await {
+        Ok(true) => Ok(ApiResponse::message("Delivery queued again.")),
+        Ok(false) => Err(ApiError::DeliveryNotFound),
+        Err(err) => {
+            error!("Database error queueing delivery {} again: {}", delivery_id, err);
+            telemetry::record_db_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
@@ -0,0 +1,4 @@
+pub mod subscriptions;
+pub use subscriptions::*;
+pub mod deliveries;
+pub use deliveries::*;
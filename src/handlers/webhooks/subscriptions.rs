@@ -0,0 +1,140 @@
+use actix_web::web;
+use tracing::{error, info};
+use authit_types::{CreateWebhookRequest, CreateWebhookResponse, WebhooksResponse};
+
+use crate::AppState;
+use crate::auth::JwtClaims;
+use crate::error::{ApiError, ErrorBody};
+use crate::handlers::account::Role;
+use crate::response::ApiResponse;
+use crate::telemetry;
+use crate::webhook::signature;
+
+/// Shortest secret accepted when the caller brings their own
+const MIN_SECRET_LENGTH: usize = 16;
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks",
+    tag = "webhook",
+    request_body = CreateWebhookRequest,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Webhook registered, the secret is only shown here", body = ApiResponse<CreateWebhookResponse>),
+        (status = 400, description = "INVALID_REQUEST, bad URL, no events or a short secret", body = ErrorBody),
+        (status = 403, description = "PERMISSION_DENIED, admin only", body = ErrorBody),
+    ),
+)]
+pub async fn create_webhook(
+    claims: JwtClaims,
+    body: web::Json<CreateWebhookRequest>,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse<CreateWebhookResponse>, ApiError> {
+    info!("Create webhook attempt by {} for {}", claims.sub, body.url);
+
+    // Check if requester is Admin
+    if !matches!(claims.role, Role::Admin) {
+        info!("Create webhook denied: user {} is not an admin (role: {:?})", claims.sub, claims.role);
+        return Err(ApiError::PermissionDenied("Only admins can manage webhooks.".to_string()));
+    }
+
+    if !(body.url.starts_with("https://") || body.url.starts_with("http://")) {
+        return Err(ApiError::InvalidRequest("url must be an http(s) URL.".to_string()));
+    }
+    if body.events.is_empty() {
+        return Err(ApiError::InvalidRequest("events must not be empty.".to_string()));
+    }
+
+    let secret = match &body.secret {
+        Some(secret) if secret.len() < MIN_SECRET_LENGTH => {
+            return Err(ApiError::InvalidRequest(format!("secret must be at least {} characters.", MIN_SECRET_LENGTH)));
+        }
+        Some(secret) => secret.clone(),
+        None => signature::generate_secret(),
+    };
+
+    let mut events = body.events.clone();
+    events.sort_by_key(|event| event.as_str());
+    events.dedup();
+
+    match data.repos.webhooks.create(&body.url, &secret, &events).await {
+        Ok(webhook) => {
+            info!("Registered webhook {} for {} event(s)", webhook.id, events.len());
+            Ok(ApiResponse::new(CreateWebhookResponse { webhook: webhook.into(), secret }))
+        }
+        Err(err) => {
+            error!("Database error creating webhook: {}", err);
+            telemetry::record_db_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks",
+    tag = "webhook",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Every registered webhook, oldest first", body = ApiResponse<WebhooksResponse>),
+        (status = 403, description = "PERMISSION_DENIED, admin only", body = ErrorBody),
+    ),
+)]
+pub async fn list_webhooks(
+    claims: JwtClaims,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse<WebhooksResponse>, ApiError> {
+    // Check if requester is Admin
+    if !matches!(claims.role, Role::Admin) {
+        info!("List webhooks denied: user {} is not an admin (role: {:?})", claims.sub, claims.role);
+        return Err(ApiError::PermissionDenied("Only admins can manage webhooks.".to_string()));
+    }
+
+    match data.repos.webhooks.list().await {
+        Ok(webhooks) => Ok(ApiResponse::new(WebhooksResponse {
+            webhooks: webhooks.into_iter().map(Into::into).collect(),
+        })),
+        Err(err) => {
+            error!("Database error listing webhooks: {}", err);
+            telemetry::record_db_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/webhooks/{webhook_id}",
+    tag = "webhook",
+    params(("webhook_id" = String, Path, description = "Webhook to remove, its delivery log goes with it")),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Webhook removed", body = ApiResponse),
+        (status = 403, description = "PERMISSION_DENIED, admin only", body = ErrorBody),
+        (status = 404, description = "WEBHOOK_NOT_FOUND", body = ErrorBody),
+    ),
+)]
+pub async fn delete_webhook(
+    claims: JwtClaims,
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<ApiResponse, ApiError> {
+    let webhook_id = path.into_inner();
+    info!("Delete webhook attempt by {} for {}", claims.sub, webhook_id);
+
+    // Check if requester is Admin
+    if !matches!(claims.role, Role::Admin) {
+        info!("Delete webhook denied: user {} is not an admin (role: {:?})", claims.sub, claims.role);
+        return Err(ApiError::PermissionDenied("Only admins can manage webhooks.".to_string()));
+    }
+
+    match data.repos.webhooks.delete(&webhook_id).await {
+        Ok(true) => Ok(ApiResponse::message("Webhook removed.")),
+        Ok(false) => Err(ApiError::WebhookNotFound),
+        Err(err) => {
+            error!("Database error deleting webhook {}: {}", webhook_id, err);
+            telemetry::record_db_error();
+            Err(ApiError::Internal)
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::{middleware, web, App, HttpResponse, Route};
+use std::sync::Arc;
+use utoipa_scalar::{Scalar, Servable};
+
+pub mod handlers;
+pub mod auth;
+pub mod cache;
+pub mod config;
+pub mod error;
+pub mod migrate;
+pub mod openapi;
+pub mod repository;
+pub mod response;
+pub mod telemetry;
+pub mod webhook;
+use crate::handlers::*;
+
+pub struct AppState {
+    /// `None` when running on the in-memory repositories
+    pub db_pool: Option<sqlx::PgPool>,
+    pub repos: repository::Repositories,
+    /// `None` when running without Redis, the `/auth` cache is then skipped
+    pub redis: Option<redis::aio::ConnectionManager>,
+    pub blacklist: Arc<dyn auth::TokenBlacklist>,
+    pub challenges: Arc<dyn auth::ChallengeStore>,
+    pub oauth: auth::OAuthProviders,
+    pub oauth_states: Arc<dyn auth::OAuthStateStore>,
+    pub sessions: Arc<dyn auth::SessionStore>,
+    pub jwt_keys: auth::JwtKeys,
+    pub revocations: auth::LocalRevocations,
+    pub response_signer: auth::ResponseSigner,
+    pub fingerprint_matcher: auth::FingerprintMatcher,
+    pub config: Arc<config::Config>,
+    pub metrics: metrics_exporter_prometheus::PrometheusHandle,
+    pub started_at: std::time::Instant,
+}
+
+impl AppState {
+    /// A complete server without Postgres or Redis, for tests and local client development
+    /// Everything lives in process and is lost when it stops, seed data through `repository`
+    pub fn in_memory(config: config::Config, repository: Arc<repository::MemoryRepository>) -> Result<Self, Box<dyn std::error::Error>> {
+        let revocations = auth::LocalRevocations::default();
+
+        Ok(Self {
+            db_pool: None,
+            repos: repository::Repositories::memory(repository),
+            redis: None,
+            blacklist: Arc::new(auth::MemoryBlacklist::new(revocations.clone())),
+            challenges: Arc::new(auth::MemoryChallenges::default()),
+            oauth: auth::OAuthProviders::new(&config.oauth),
+            oauth_states: Arc::new(auth::MemoryOAuthStates::default()),
+            sessions: Arc::new(auth::MemorySessions::default()),
+            jwt_keys: auth::JwtKeys::from_config(&config.auth)?,
+            revocations,
+            response_signer: auth::ResponseSigner::from_keys(&config.auth.signing_keys)?,
+            fingerprint_matcher: auth::FingerprintMatcher::new(config.hwid.weights.clone(), config.hwid.match_threshold, config.hwid.max_drifted_components),
+            config: Arc::new(config),
+            metrics: telemetry::detached(),
+            started_at: std::time::Instant::now(),
+        })
+    }
+}
+
+/// Every route as (method, path, handler), registered by `app()` and checked against the OpenAPI spec
+/// `/metrics` comes last, `app()` leaves it out when a dedicated metrics listener serves it
+pub fn routes() -> Vec<(Method, &'static str, Route)> {
+    vec![
+        (Method::GET, "/.well-known/authit-signing-keys", web::to(public::signing_keys)),
+        (Method::GET, "/.well-known/jwks.json", web::to(public::jwks)),
+        (Method::GET, "/api-docs/openapi.json", web::to(public::openapi)),
+        (Method::GET, "/api/v1/health-check", web::to(public::health_check)),
+        (Method::GET, "/api/v1/health/live", web::to(public::liveness_check)),
+        (Method::GET, "/api/v1/health/ready", web::to(public::readiness_check)),
+        (Method::POST, "/api/v1/auth", web::to(public::auth)),
+        (Method::POST, "/api/v1/auth/challenge", web::to(public::challenge)),
+        (Method::POST, "/api/v1/account/login", web::to(account::login)),
+        (Method::POST, "/api/v1/account/redeem", web::to(account::redeem)),
+        (Method::POST, "/api/v1/account/set-role", web::to(account::set_role)),
+        (Method::GET, "/api/v1/account/products", web::to(account::products)),
+        (Method::GET, "/api/v1/account/devices", web::to(account::devices)),
+        (Method::POST, "/api/v1/account/devices/rename", web::to(account::rename_device)),
+        (Method::POST, "/api/v1/account/devices/release", web::to(account::release_device)),
+        (Method::GET, "/api/v1/account/links", web::to(account::linked_accounts)),
+        (Method::DELETE, "/api/v1/account/links/{provider}", web::to(account::unlink_account)),
+        (Method::POST, "/api/v1/oauth/{provider}/link", web::to(oauth::oauth_link)),
+        (Method::POST, "/api/v1/oauth/{provider}/login", web::to(oauth::oauth_login)),
+        (Method::GET, "/api/v1/oauth/{provider}/callback", web::to(oauth::oauth_callback)),
+        (Method::POST, "/api/v1/oauth/{provider}/exchange", web::to(oauth::oauth_exchange)),
+        (Method::POST, "/api/v1/product/generate-key", web::to(product::generate_key)),
+        (Method::POST, "/api/v1/product/compensate", web::to(product::compensate)),
+        (Method::POST, "/api/v1/session/heartbeat", web::to(session::heartbeat)),
+        (Method::POST, "/api/v1/session/close", web::to(session::close_session)),
+        (Method::GET, "/api/v1/session/list", web::to(session::list_sessions)),
+        (Method::DELETE, "/api/v1/session/{session_id}", web::to(session::kill_session)),
+        (Method::GET, "/api/v1/webhooks", web::to(webhooks::list_webhooks)),
+        (Method::POST, "/api/v1/webhooks", web::to(webhooks::create_webhook)),
+        (Method::GET, "/api/v1/webhooks/deliveries", web::to(webhooks::list_deliveries)),
+        (Method::POST, "/api/v1/webhooks/deliveries/{delivery_id}/redeliver", web::to(webhooks::redeliver)),
+        (Method::DELETE, "/api/v1/webhooks/{webhook_id}", web::to(webhooks::delete_webhook)),
+        (Method::GET, METRICS_PATH, web::to(public::metrics)),
+    ]
+}
+
+/// Where Prometheus scrapes, on the public listener or the one `METRICS_BIND` names
+pub const METRICS_PATH: &str = "/metrics";
+
+/// The public API, `/metrics` is only included with `serve_metrics` (i.e. no dedicated metrics listener)
+pub fn app(
+    state: web::Data<AppState>,
+    serve_metrics: bool,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let mut app = App::new()
+        .app_data(state)
+        .wrap(middleware::from_fn(telemetry::track_requests))
+        .wrap(middleware::from_fn(telemetry::request_context))
+        // Malformed bodies, paths and queries get the same JSON error envelope as handlers
+        .app_data(web::JsonConfig::default().error_handler(error::extractor_error_handler))
+        .app_data(web::PathConfig::default().error_handler(error::extractor_error_handler))
+        .app_data(web::QueryConfig::default().error_handler(error::extractor_error_handler));
+
+    for (method, path, route) in routes() {
+        if path != METRICS_PATH || serve_metrics {
+            app = app.route(path, route.method(method));
+        }
+    }
+
+    app.service(Scalar::with_url("/api-docs", openapi::SPEC.clone()))
+        .default_service(web::to(|| async { Err::<HttpResponse, _>(error::ApiError::RouteNotFound) }))
+}
@@ -1,30 +1,43 @@
 use actix_web::{web, App, HttpServer};
 use sqlx::postgres::PgPoolOptions;
-use tracing::{Level, error, info};
-use tracing_subscriber;
+use std::sync::Arc;
+use tracing::{error, info};
 
-mod handlers;
-mod auth;
-use crate::handlers::*;
-
-
-pub struct AppState {
-    db_pool: sqlx::PgPool,
-    redis_client: redis::Client,
-}
+use authit::handlers::public;
+use authit::{auth, config, migrate, repository, telemetry, webhook, AppState};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Initialize tracing subscriber to print to stdout
-    tracing_subscriber::fmt()
-        .with_max_level(Level::INFO)
-        .init();
+    // `authit` serves, `authit migrate` only applies migrations and exits
+    let migrate_only = match std::env::args().nth(1).as_deref() {
+        None => false,
+        Some("migrate") => true,
+        Some(command) => {
+            eprintln!("Unknown command '{}'\nUsage: authit [migrate]", command);
+            std::process::exit(2);
+        }
+    };
+
+    // Load and validate settings once, everything below reads them from here
+    let config = config::Config::load();
+
+    // Logging comes up before the config is checked so a bad config is still reported
+    telemetry::init_logging(config.as_ref().map(|config| config.logging.format).unwrap_or_default());
+
+    let config = match config {
+        Ok(config) => Arc::new(config),
+        Err(err) => {
+            error!("Invalid configuration: {}", err);
+            std::process::exit(1);
+        }
+    };
+    info!("Loaded configuration ({:?})", config.environment);
 
     let pool = match PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&std::env::var("DATABASE_URL").expect("DATABASE_URL env var not set")).await {
+        .max_connections(config.database.max_connections)
+        .connect(&config.database.url).await {
             Ok(pool) => {
-            info!("Connected to database @ {}", std::env::var("DATABASE_URL").unwrap()); //unwrap is safe due to expect earlier
+            info!("Connected to database (max {} connections)", config.database.max_connections);
             pool
         }
         Err(err) => {
@@ -33,51 +46,156 @@ async fn main() -> std::io::Result<()> {
         }
     };
 
-    // Connect to Redis
-    let redis_url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
-    let redis_client = match redis::Client::open(redis_url.as_str()) {
-        Ok(client) => {
-            // Test connection
-            match client.get_multiplexed_async_connection().await {
-                Ok(_) => {
-                    info!("Connected to Redis @ {}", redis_url);
-                    client
-                }
-                Err(err) => {
-                    error!("Failed to connect to Redis: {}", err);
-                    std::process::exit(1);
-                }
-            }
+    if let Err(err) = migrate::run(&pool).await {
+        error!("Failed to apply database migrations: {}", err);
+        std::process::exit(1);
+    }
+    if migrate_only {
+        return Ok(());
+    }
+
+    // Revocations made in process, the memory blacklist keeps its entries in the same store
+    let revocations = auth::LocalRevocations::default();
+
+    // The memory backend is for a single instance, which then runs without Redis at all:
+    // challenges, sessions and OAuth states stay in process too and the /auth cache is skipped
+    let redis = match config.auth.blacklist_backend {
+        config::BlacklistBackend::Redis => Some(connect_redis(&config.redis).await),
+        config::BlacklistBackend::Memory => {
+            info!("Running without Redis, revocations, sessions and challenges are kept in memory and lost on restart");
+            None
+        }
+    };
+
+    let blacklist: Arc<dyn auth::TokenBlacklist> = match &redis {
+        Some(redis) => Arc::new(auth::RedisBlacklist::new(redis.clone())),
+        None => Arc::new(auth::MemoryBlacklist::new(revocations.clone())),
+    };
+    let challenges: Arc<dyn auth::ChallengeStore> = match &redis {
+        Some(redis) => Arc::new(auth::RedisChallenges::new(redis.clone())),
+        None => Arc::new(auth::MemoryChallenges::default()),
+    };
+    let oauth_states: Arc<dyn auth::OAuthStateStore> = match &redis {
+        Some(redis) => Arc::new(auth::RedisOAuthStates::new(redis.clone())),
+        None => Arc::new(auth::MemoryOAuthStates::default()),
+    };
+    let sessions: Arc<dyn auth::SessionStore> = match &redis {
+        Some(redis) => Arc::new(auth::RedisSessions::new(redis.clone())),
+        None => Arc::new(auth::MemorySessions::default()),
+    };
+
+    // Load the keys used to sign and verify login tokens
+    let jwt_keys = match auth::JwtKeys::from_config(&config.auth) {
+        Ok(keys) => keys,
+        Err(err) => {
+            error!("Failed to load JWT keys: {}", err);
+            std::process::exit(1);
         }
+    };
+
+    // Load the keys used to sign /auth responses
+    let response_signer = match auth::ResponseSigner::from_keys(&config.auth.signing_keys) {
+        Ok(signer) => signer,
         Err(err) => {
-            error!("Failed to create Redis client: {}", err);
+            error!("Failed to load auth signing keys: {}", err);
             std::process::exit(1);
         }
     };
 
-    HttpServer::new(move || {
-        App::new()
-            .app_data(web::Data::new(AppState {
-                db_pool: pool.clone(),
-                redis_client: redis_client.clone()
-            }))
-            .service(
-                web::scope("/api/v1")
-                    .route("/health-check", web::get().to(public::health_check))
-                    .route("/auth", web::post().to(public::auth))
-                    .service(web::scope("/account")
-                        .route("/login", web::post().to(account::login))
-                        .route("/redeem", web::post().to(account::redeem))
-                        .route("/set-role", web::post().to(account::set_role))
-                        .route("/products", web::get().to(account::products))
-                    )
-                    .service(web::scope("/product")
-                        .route("/generate-key", web::post().to(product::generate_key))
-                        .route("/compensate", web::post().to(product::compensate))
-                    )
-            )
-    })
-    .bind(("0.0.0.0", 5593))?
-    .run()
-    .await
-}
\ No newline at end of file
+    let fingerprint_matcher = auth::FingerprintMatcher::new(config.hwid.weights.clone(), config.hwid.match_threshold, config.hwid.max_drifted_components);
+
+    let metrics = match telemetry::install() {
+        Ok(handle) => handle,
+        Err(err) => {
+            error!("Failed to install metrics recorder: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // Histograms are only aggregated during upkeep, keep memory bounded even if nobody scrapes
+    let upkeep_handle = metrics.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(5));
+        loop {
+            interval.tick().await;
+            upkeep_handle.run_upkeep();
+        }
+    });
+
+    let bind_address = (config.server.host.clone(), config.server.port);
+    let metrics_bind = config.metrics.bind.clone();
+
+    let repos = repository::Repositories::postgres(pool.clone());
+
+    // Sends queued webhook deliveries and announces expired licenses
+    actix_web::rt::spawn(webhook::Dispatcher::new(repos.clone(), config.webhooks.clone()).run());
+
+    let state = web::Data::new(AppState {
+        repos,
+        db_pool: Some(pool),
+        redis,
+        blacklist,
+        challenges,
+        oauth: auth::OAuthProviders::new(&config.oauth),
+        oauth_states,
+        sessions,
+        jwt_keys,
+        revocations,
+        response_signer,
+        fingerprint_matcher,
+        config,
+        metrics,
+        started_at: std::time::Instant::now(),
+    });
+
+    // With a dedicated metrics address, /metrics is only served there and never on the public listener
+    if let Some(metrics_bind) = &metrics_bind {
+        let metrics_state = state.clone();
+        let metrics_server = HttpServer::new(move || {
+            App::new()
+                .app_data(metrics_state.clone())
+                .route(authit::METRICS_PATH, web::get().to(public::metrics))
+        })
+        .workers(1)
+        .bind(metrics_bind)?
+        .run();
+
+        info!("Serving metrics on {}", metrics_bind);
+        actix_web::rt::spawn(async move {
+            if let Err(err) = metrics_server.await {
+                error!("Metrics server stopped: {}", err);
+            }
+        });
+    }
+
+    HttpServer::new(move || authit::app(state.clone(), metrics_bind.is_none()))
+        .bind(bind_address)?
+        .run()
+        .await
+}
+
+/// One auto-reconnecting multiplexed connection shared by every request, exits if Redis can't be reached
+async fn connect_redis(config: &config::RedisConfig) -> redis::aio::ConnectionManager {
+    let redis_client = match redis::Client::open(config.url.as_str()) {
+        Ok(client) => client,
+        Err(err) => {
+            error!("Failed to create Redis client: {}", err);
+            std::process::exit(1);
+        }
+    };
+    // Timeouts keep a hung Redis from stalling requests, so the blacklist failure policy can kick in
+    let redis_timeout = std::time::Duration::from_millis(config.timeout_ms);
+    let redis_config = redis::aio::ConnectionManagerConfig::new()
+        .set_connection_timeout(redis_timeout)
+        .set_response_timeout(redis_timeout);
+    match redis::aio::ConnectionManager::new_with_config(redis_client, redis_config).await {
+        Ok(manager) => {
+            info!("Connected to Redis @ {}", config.url);
+            manager
+        }
+        Err(err) => {
+            error!("Failed to connect to Redis: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use sqlx::PgPool;
+use sqlx::migrate::{Migrate, MigrateError, Migrator};
+use std::collections::HashSet;
+use tracing::info;
+
+/// Every file in `migrations/`, embedded at compile time
+///
+/// Applied versions and their checksums are tracked in `_sqlx_migrations`. Editing a migration
+/// that already ran, or running a binary that doesn't know an applied version, fails instead of
+/// silently diverging. Add a new numbered file instead of changing an old one.
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Versions embedded in the binary that haven't been applied to the database yet
+pub async fn pending(pool: &PgPool) -> Result<Vec<i64>, MigrateError> {
+    let applied: HashSet<i64> = {
+        let mut conn = pool.acquire().await?;
+        conn.ensure_migrations_table().await?;
+        conn.list_applied_migrations().await?.into_iter().map(|migration| migration.version).collect()
+    };
+
+    Ok(MIGRATOR.iter().map(|migration| migration.version).filter(|version| !applied.contains(version)).collect())
+}
+
+/// Apply pending migrations, refusing if the applied ones drifted from the embedded files
+/// Returns the versions that were applied
+pub async fn run(pool: &PgPool) -> Result<Vec<i64>, MigrateError> {
+    let pending = pending(pool).await?;
+    for migration in MIGRATOR.iter().filter(|migration| pending.contains(&migration.version)) {
+        info!("Applying migration {:03} ({})", migration.version, migration.description);
+    }
+
+    MIGRATOR.run(pool).await?;
+    info!("Database schema up to date ({} migrations, {} new)", MIGRATOR.iter().count(), pending.len());
+
+    Ok(pending)
+}
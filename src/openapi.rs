@@ -0,0 +1,125 @@
+use std::sync::LazyLock;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::handlers::{account, oauth, product, public, session, webhooks};
+
+/// OpenAPI 3 description of every route, generated from the handlers and their request/response types
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "authit", description = "License, account and HWID authorization API"),
+    paths(
+        public::signing_keys,
+        public::jwks,
+        public::metrics,
+        public::openapi,
+        public::health_check,
+        public::liveness_check,
+        public::readiness_check,
+        public::auth,
+        public::challenge,
+        account::login,
+        account::redeem,
+        account::set_role,
+        account::products,
+        account::devices,
+        account::rename_device,
+        account::release_device,
+        account::linked_accounts,
+        account::unlink_account,
+        oauth::oauth_link,
+        oauth::oauth_login,
+        oauth::oauth_callback,
+        oauth::oauth_exchange,
+        product::generate_key,
+        product::compensate,
+        session::heartbeat,
+        session::close_session,
+        session::list_sessions,
+        session::kill_session,
+        webhooks::create_webhook,
+        webhooks::list_webhooks,
+        webhooks::delete_webhook,
+        webhooks::list_deliveries,
+        webhooks::redeliver,
+    ),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "public", description = "Loader facing and unauthenticated endpoints"),
+        (name = "account", description = "The caller's account"),
+        (name = "oauth", description = "Linking external accounts and logging in with them"),
+        (name = "product", description = "Keys and licenses for a product"),
+        (name = "session", description = "Live /auth sessions"),
+        (name = "webhook", description = "Outgoing event notifications"),
+    ),
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).bearer_format("JWT").build()),
+        );
+        components.add_security_scheme(
+            "metrics_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// Built once, the spec never changes while the process runs
+pub static SPEC: LazyLock<utoipa::openapi::OpenApi> = LazyLock::new(|| {
+    let mut spec = ApiDoc::openapi();
+    spec.info.version = env!("CARGO_PKG_VERSION").to_string();
+    spec.info.license = None; // picked up from Cargo.toml, which has none
+    spec
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every route `app()` registers, as (lowercase method, path)
+    fn routes_in_app() -> Vec<(String, String)> {
+        crate::routes()
+            .into_iter()
+            .map(|(method, path, _)| (method.as_str().to_lowercase(), path.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn every_route_is_documented() {
+        let routes = routes_in_app();
+        assert!(routes.contains(&("post".to_string(), "/api/v1/account/login".to_string())));
+
+        let spec = serde_json::to_value(&*SPEC).unwrap();
+        let missing: Vec<String> = routes
+            .iter()
+            .filter(|(method, path)| spec["paths"][path][method].is_null())
+            .map(|(method, path)| format!("{} {}", method.to_uppercase(), path))
+            .collect();
+
+        assert!(missing.is_empty(), "routes missing from the OpenAPI spec: {:?}", missing);
+    }
+
+    #[test]
+    fn every_documented_route_exists() {
+        let routes = routes_in_app();
+        let spec = serde_json::to_value(&*SPEC).unwrap();
+
+        for (path, operations) in spec["paths"].as_object().unwrap() {
+            for method in operations.as_object().unwrap().keys() {
+                assert!(
+                    routes.contains(&(method.clone(), path.clone())),
+                    "{} {} is documented but not routed",
+                    method.to_uppercase(),
+                    path,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,987 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::auth::HwidComponents;
+use crate::handlers::account::Role;
+use crate::auth::oauth::LinkedAccount;
+use crate::webhook::{DeliveryStatus, WebhookDelivery, WebhookEvent};
+use super::{
+    ActiveLicense, BanRepository, CdKey, DeliveryAttempt, Device, DeviceRelease, DeviceRepository, DueDelivery, ExpiredLicense,
+    ExternalAccount, HwidPolicy, IdentityRepository, KeyRepository, License, LicenseRepository, Product, ProductRepository, Repositories, RepositoryResult, Tier,
+    User, UserCredentials, UserRepository, UserStatus, WebhookRepository, WebhookSubscription,
+};
+
+struct MemoryUser {
+    email: String,
+    password_hash: String,
+    role: Role,
+    banned: bool,
+    device_slots: i32,
+    last_device_release_at: Option<i64>,
+}
+
+struct MemoryProduct {
+    name: String,
+    frozen: bool,
+    max_sessions: Option<i32>,
+    hwid_policy: HwidPolicy,
+    hwid_device_limit: Option<i32>,
+    /// Lowest level first
+    tiers: Vec<Tier>,
+}
+
+impl MemoryProduct {
+    fn tier(&self, name: Option<&str>) -> Option<Tier> {
+        self.tiers.iter().find(|tier| Some(tier.name.as_str()) == name).cloned()
+    }
+}
+
+struct MemoryLicense {
+    user_id: String,
+    product_id: String,
+    /// `None` for lifetime licenses
+    expires_at: Option<i64>,
+    expiry_notified: bool,
+    tier: Option<String>,
+}
+
+impl MemoryLicense {
+    fn extend(&mut self, hours: i64) {
+        if let Some(expires_at) = &mut self.expires_at {
+            *expires_at += hours * 3600;
+            self.expiry_notified &= *expires_at <= now();
+        }
+    }
+
+    fn expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+struct MemoryDelivery {
+    id: String,
+    webhook_id: String,
+    event: WebhookEvent,
+    payload: String,
+    status: DeliveryStatus,
+    attempts: i32,
+    next_attempt_at: i64,
+    last_status_code: Option<i32>,
+    last_error: Option<String>,
+    created_at: i64,
+    delivered_at: Option<i64>,
+}
+
+#[derive(Default)]
+struct Tables {
+    users: HashMap<String, MemoryUser>,
+    /// user_id and device, in bind order
+    devices: Vec<(String, Device)>,
+    licenses: Vec<MemoryLicense>,
+    products: HashMap<String, MemoryProduct>,
+    keys: HashMap<String, CdKey>,
+    /// Ban reasons aren't kept, nothing reads them back
+    banned_hwids: HashSet<String>,
+    next_device_id: u64,
+    next_user_id: u64,
+    webhooks: Vec<WebhookSubscription>,
+    /// In the order they were queued
+    deliveries: Vec<MemoryDelivery>,
+    next_webhook_id: u64,
+    next_delivery_id: u64,
+    /// user_id and linked account
+    identities: Vec<(String, LinkedAccount)>,
+}
+
+/// Keeps every table in a map so handler logic can be exercised without Postgres
+#[derive(Default)]
+pub struct MemoryRepository {
+    tables: Mutex<Tables>,
+}
+
+fn now() -> i64 {
+    Utc::now().timestamp()
+}
+
+fn timestamp_text(timestamp: i64) -> String {
+    DateTime::<Utc>::from_timestamp(timestamp, 0).unwrap_or_default().to_rfc3339()
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_user(&self, user_id: &str, email: &str, password_hash: &str, role: Role, device_slots: i32) {
+        self.tables.lock().users.insert(user_id.to_string(), MemoryUser {
+            email: email.to_string(),
+            password_hash: password_hash.to_string(),
+            role,
+            banned: false,
+            device_slots,
+            last_device_release_at: None,
+        });
+    }
+
+    pub fn add_product(&self, product_id: &str, name: &str, hwid_policy: HwidPolicy, hwid_device_limit: Option<i32>) {
+        self.tables.lock().products.insert(product_id.to_string(), MemoryProduct {
+            name: name.to_string(),
+            frozen: false,
+            max_sessions: None,
+            hwid_policy,
+            hwid_device_limit,
+            tiers: Vec::new(),
+        });
+    }
+
+    pub fn add_tier(&self, product_id: &str, name: &str, level: i32, entitlements: &[&str]) {
+        if let Some(product) = self.tables.lock().products.get_mut(product_id) {
+            product.tiers.push(Tier {
+                name: name.to_string(),
+                level,
+                entitlements: entitlements.iter().map(|entitlement| entitlement.to_string()).collect(),
+            });
+            product.tiers.sort_by_key(|tier| tier.level);
+        }
+    }
+
+    pub fn add_license(&self, user_id: &str, product_id: &str, expires_at: i64) {
+        self.tables.lock().licenses.push(MemoryLicense {
+            user_id: user_id.to_string(),
+            product_id: product_id.to_string(),
+            expires_at: Some(expires_at),
+            expiry_notified: false,
+            tier: None,
+        });
+    }
+
+    pub fn ban_hwid(&self, hwid: &str) {
+        self.tables.lock().banned_hwids.insert(hwid.to_string());
+    }
+}
+
+impl Repositories {
+    pub fn memory(backend: Arc<MemoryRepository>) -> Self {
+        Self::from_backend(backend)
+    }
+}
+
+#[async_trait]
+impl UserRepository for MemoryRepository {
+    async fn find_by_email(&self, email: &str) -> RepositoryResult<Option<UserCredentials>> {
+        Ok(self.tables.lock().users.iter().find(|(_, user)| user.email == email).map(|(id, user)| UserCredentials {
+            id: id.clone(),
+            password_hash: user.password_hash.clone(),
+            role: user.role,
+        }))
+    }
+
+    async fn get(&self, user_id: &str) -> RepositoryResult<Option<User>> {
+        Ok(self.tables.lock().users.get(user_id).map(|user| User {
+            id: user_id.to_string(),
+            email: user.email.clone(),
+            role: user.role,
+            banned: user.banned,
+            device_slots: user.device_slots,
+        }))
+    }
+
+    async fn create(&self, email: &str, password_hash: &str, role: Role) -> RepositoryResult<Option<String>> {
+        let mut tables = self.tables.lock();
+        if tables.users.values().any(|user| user.email == email) {
+            return Ok(None);
+        }
+
+        tables.next_user_id += 1;
+        let user_id = format!("user-{}", tables.next_user_id);
+        tables.users.insert(user_id.clone(), MemoryUser {
+            email: email.to_string(),
+            password_hash: password_hash.to_string(),
+            role,
+            banned: false,
+            device_slots: 2,
+            last_device_release_at: None,
+        });
+
+        Ok(Some(user_id))
+    }
+
+    async fn status(&self, user_id: &str) -> RepositoryResult<Option<UserStatus>> {
+        Ok(self.tables.lock().users.get(user_id).map(|user| UserStatus {
+            banned: user.banned,
+            device_slots: user.device_slots,
+        }))
+    }
+
+    async fn set_role(&self, user_id: &str, role: Role) -> RepositoryResult<bool> {
+        Ok(self.tables.lock().users.get_mut(user_id).map(|user| user.role = role).is_some())
+    }
+
+    async fn set_banned(&self, user_id: &str, banned: bool) -> RepositoryResult<bool> {
+        Ok(self.tables.lock().users.get_mut(user_id).map(|user| user.banned = banned).is_some())
+    }
+}
+
+#[async_trait]
+impl DeviceRepository for MemoryRepository {
+    async fn list(&self, user_id: &str) -> RepositoryResult<Vec<Device>> {
+        Ok(self
+            .tables
+            .lock()
+            .devices
+            .iter()
+            .filter(|(owner, _)| owner == user_id)
+            .map(|(_, device)| device.clone())
+            .collect())
+    }
+
+    async fn bind(
+        &self,
+        user_id: &str,
+        product_id: Option<&str>,
+        slots: i32,
+        hwid: &str,
+        components: Option<&HwidComponents>,
+    ) -> RepositoryResult<bool> {
+        let mut tables = self.tables.lock();
+
+        let scoped: Vec<&Device> = tables
+            .devices
+            .iter()
+            .filter(|(owner, device)| owner == user_id && device.product_id.as_deref() == product_id)
+            .map(|(_, device)| device)
+            .collect();
+        if scoped.len() as i32 >= slots || scoped.iter().any(|device| device.hwid == hwid) {
+            return Ok(false);
+        }
+
+        tables.next_device_id += 1;
+        let device = Device {
+            id: format!("device-{}", tables.next_device_id),
+            product_id: product_id.map(str::to_string),
+            hwid: hwid.to_string(),
+            components: components.cloned(),
+            name: None,
+            bound_at: timestamp_text(now()),
+        };
+        tables.devices.push((user_id.to_string(), device));
+
+        Ok(true)
+    }
+
+    async fn update_fingerprint(&self, device_id: &str, hwid: &str, components: &HwidComponents) -> RepositoryResult<()> {
+        if let Some((_, device)) = self.tables.lock().devices.iter_mut().find(|(_, device)| device.id == device_id) {
+            device.hwid = hwid.to_string();
+            device.components = Some(components.clone());
+        }
+
+        Ok(())
+    }
+
+    async fn rename(&self, user_id: &str, device_id: &str, name: &str) -> RepositoryResult<bool> {
+        let mut tables = self.tables.lock();
+        let device = tables.devices.iter_mut().find(|(owner, device)| owner == user_id && device.id == device_id);
+
+        Ok(device.map(|(_, device)| device.name = Some(name.to_string())).is_some())
+    }
+
+    async fn exists(&self, user_id: &str, device_id: &str) -> RepositoryResult<bool> {
+        Ok(self.tables.lock().devices.iter().any(|(owner, device)| owner == user_id && device.id == device_id))
+    }
+
+    async fn release_cooldown_remaining(&self, user_id: &str, cooldown_hours: i64) -> RepositoryResult<Option<i64>> {
+        let tables = self.tables.lock();
+        let released_at = tables.users.get(user_id).and_then(|user| user.last_device_release_at);
+
+        Ok(released_at
+            .map(|released_at| released_at + cooldown_hours * 3600 - now())
+            .filter(|remaining| *remaining > 0))
+    }
+
+    async fn release(&self, user_id: &str, device_id: &str, cooldown_hours: i64) -> RepositoryResult<DeviceRelease> {
+        let mut tables = self.tables.lock();
+        let now = now();
+
+        let Some(released_at) = tables.users.get(user_id).map(|user| user.last_device_release_at) else {
+            return Ok(DeviceRelease::NotFound);
+        };
+        if released_at.is_some_and(|released_at| released_at > now - cooldown_hours * 3600) {
+            return Ok(DeviceRelease::Cooldown);
+        }
+
+        let count = tables.devices.len();
+        tables.devices.retain(|(owner, device)| !(owner == user_id && device.id == device_id));
+        if tables.devices.len() == count {
+            return Ok(DeviceRelease::NotFound);
+        }
+
+        if let Some(user) = tables.users.get_mut(user_id) {
+            user.last_device_release_at = Some(now);
+        }
+
+        Ok(DeviceRelease::Released)
+    }
+}
+
+#[async_trait]
+impl LicenseRepository for MemoryRepository {
+    async fn for_user(&self, user_id: &str) -> RepositoryResult<Vec<License>> {
+        let tables = self.tables.lock();
+
+        Ok(tables
+            .licenses
+            .iter()
+            .filter(|license| license.user_id == user_id)
+            .filter_map(|license| {
+                let product = tables.products.get(&license.product_id)?;
+                Some(License {
+                    product_id: license.product_id.clone(),
+                    expires_at: license.expires_at,
+                    max_sessions: product.max_sessions,
+                    hwid_policy: product.hwid_policy,
+                    hwid_device_limit: product.hwid_device_limit,
+                    tier: product.tier(license.tier.as_deref()),
+                })
+            })
+            .collect())
+    }
+
+    async fn active_for_user(&self, user_id: &str) -> RepositoryResult<Vec<ActiveLicense>> {
+        let tables = self.tables.lock();
+        let now = now();
+
+        let mut licenses: Vec<&MemoryLicense> = tables
+            .licenses
+            .iter()
+            .filter(|license| license.user_id == user_id && !license.expired(now))
+            .collect();
+        licenses.sort_by_key(|license| (license.expires_at.is_some(), std::cmp::Reverse(license.expires_at)));
+
+        Ok(licenses
+            .into_iter()
+            .filter_map(|license| {
+                let product = tables.products.get(&license.product_id)?;
+                Some(ActiveLicense {
+                    product_id: license.product_id.clone(),
+                    product_name: product.name.clone(),
+                    expires_at: license.expires_at.map(timestamp_text),
+                    time_remaining_seconds: license.expires_at.map(|expires_at| expires_at - now),
+                    frozen: product.frozen,
+                    tier: product.tier(license.tier.as_deref()),
+                })
+            })
+            .collect())
+    }
+
+    async fn extend(&self, user_id: &str, product_id: &str, hours: i64) -> RepositoryResult<()> {
+        self.tables
+            .lock()
+            .licenses
+            .iter_mut()
+            .filter(|license| license.user_id == user_id && license.product_id == product_id)
+            .for_each(|license| license.extend(hours));
+
+        Ok(())
+    }
+
+    async fn assign(&self, user_id: &str, product_id: &str, hours: Option<i64>, tier: Option<&str>) -> RepositoryResult<()> {
+        self.tables.lock().licenses.push(MemoryLicense {
+            user_id: user_id.to_string(),
+            product_id: product_id.to_string(),
+            expires_at: hours.map(|hours| now() + hours * 3600),
+            expiry_notified: false,
+            tier: tier.map(str::to_string),
+        });
+
+        Ok(())
+    }
+
+    async fn make_lifetime(&self, user_id: &str, product_id: &str) -> RepositoryResult<()> {
+        self.tables
+            .lock()
+            .licenses
+            .iter_mut()
+            .filter(|license| license.user_id == user_id && license.product_id == product_id)
+            .for_each(|license| {
+                license.expires_at = None;
+                license.expiry_notified = false;
+            });
+
+        Ok(())
+    }
+
+    async fn set_tier(&self, user_id: &str, product_id: &str, tier: Option<&str>) -> RepositoryResult<()> {
+        self.tables
+            .lock()
+            .licenses
+            .iter_mut()
+            .filter(|license| license.user_id == user_id && license.product_id == product_id)
+            .for_each(|license| license.tier = tier.map(str::to_string));
+
+        Ok(())
+    }
+
+    async fn extend_all(&self, product_id: &str, hours: i64) -> RepositoryResult<Vec<String>> {
+        Ok(self
+            .tables
+            .lock()
+            .licenses
+            .iter_mut()
+            .filter(|license| license.product_id == product_id && license.expires_at.is_some())
+            .map(|license| {
+                license.extend(hours);
+                license.user_id.clone()
+            })
+            .collect())
+    }
+
+    async fn holders(&self, product_id: &str, tier: Option<&str>) -> RepositoryResult<Vec<String>> {
+        Ok(self
+            .tables
+            .lock()
+            .licenses
+            .iter()
+            .filter(|license| license.product_id == product_id && tier.is_none_or(|tier| license.tier.as_deref() == Some(tier)))
+            .map(|license| license.user_id.clone())
+            .collect())
+    }
+
+    async fn take_expired(&self) -> RepositoryResult<Vec<ExpiredLicense>> {
+        let now = now();
+
+        Ok(self
+            .tables
+            .lock()
+            .licenses
+            .iter_mut()
+            .filter(|license| !license.expiry_notified && license.expired(now))
+            .filter_map(|license| {
+                license.expiry_notified = true;
+                Some(ExpiredLicense {
+                    user_id: license.user_id.clone(),
+                    product_id: license.product_id.clone(),
+                    expires_at: license.expires_at?,
+                })
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl KeyRepository for MemoryRepository {
+    async fn find(&self, key: &str) -> RepositoryResult<Option<CdKey>> {
+        Ok(self.tables.lock().keys.get(key).cloned())
+    }
+
+    async fn insert(&self, key: &str, product_id: &str, time_hours: Option<i64>, tier: Option<&str>) -> RepositoryResult<bool> {
+        let mut tables = self.tables.lock();
+        if tables.keys.contains_key(key) {
+            return Ok(false);
+        }
+        tables.keys.insert(key.to_string(), CdKey {
+            key: key.to_string(),
+            product_id: product_id.to_string(),
+            time_hours,
+            tier: tier.map(str::to_string),
+        });
+
+        Ok(true)
+    }
+
+    async fn consume(&self, key: &str) -> RepositoryResult<()> {
+        self.tables.lock().keys.remove(key);
+
+        Ok(())
+    }
+
+    async fn unused(&self, product_id: &str) -> RepositoryResult<Vec<CdKey>> {
+        let mut keys: Vec<CdKey> = self
+            .tables
+            .lock()
+            .keys
+            .values()
+            .filter(|key| key.product_id == product_id)
+            .cloned()
+            .collect();
+        keys.sort_by(|a, b| a.key.cmp(&b.key));
+
+        Ok(keys)
+    }
+}
+
+#[async_trait]
+impl ProductRepository for MemoryRepository {
+    async fn exists(&self, product_id: &str) -> RepositoryResult<bool> {
+        Ok(self.tables.lock().products.contains_key(product_id))
+    }
+
+    async fn list(&self) -> RepositoryResult<Vec<Product>> {
+        let mut products: Vec<Product> = self
+            .tables
+            .lock()
+            .products
+            .iter()
+            .map(|(id, product)| Product {
+                id: id.clone(),
+                name: product.name.clone(),
+                frozen: product.frozen,
+                max_sessions: product.max_sessions,
+                hwid_policy: product.hwid_policy,
+                hwid_device_limit: product.hwid_device_limit,
+            })
+            .collect();
+        products.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(products)
+    }
+
+    async fn create(&self, product: &Product) -> RepositoryResult<bool> {
+        let mut tables = self.tables.lock();
+        if tables.products.contains_key(&product.id) {
+            return Ok(false);
+        }
+        tables.products.insert(product.id.clone(), MemoryProduct {
+            name: product.name.clone(),
+            frozen: product.frozen,
+            max_sessions: product.max_sessions,
+            hwid_policy: product.hwid_policy,
+            hwid_device_limit: product.hwid_device_limit,
+            tiers: Vec::new(),
+        });
+
+        Ok(true)
+    }
+
+    async fn set_frozen(&self, product_id: &str, frozen: bool) -> RepositoryResult<bool> {
+        Ok(self.tables.lock().products.get_mut(product_id).map(|product| product.frozen = frozen).is_some())
+    }
+
+    async fn set_max_sessions(&self, product_id: &str, max_sessions: Option<i32>) -> RepositoryResult<bool> {
+        Ok(self.tables.lock().products.get_mut(product_id).map(|product| product.max_sessions = max_sessions).is_some())
+    }
+
+    async fn set_hwid_policy(&self, product_id: &str, policy: HwidPolicy, device_limit: Option<i32>) -> RepositoryResult<bool> {
+        let mut tables = self.tables.lock();
+        let Some(product) = tables.products.get_mut(product_id) else {
+            return Ok(false);
+        };
+        product.hwid_policy = policy;
+        product.hwid_device_limit = device_limit.filter(|_| policy == HwidPolicy::Devices);
+
+        Ok(true)
+    }
+
+    async fn tiers(&self, product_id: &str) -> RepositoryResult<Vec<Tier>> {
+        Ok(self.tables.lock().products.get(product_id).map(|product| product.tiers.clone()).unwrap_or_default())
+    }
+
+    async fn set_tier(&self, product_id: &str, tier: &Tier) -> RepositoryResult<bool> {
+        let mut tables = self.tables.lock();
+        // Postgres fails on the foreign key instead, callers check the product exists first
+        let Some(product) = tables.products.get_mut(product_id) else {
+            return Ok(true);
+        };
+        if product.tiers.iter().any(|other| other.level == tier.level && other.name != tier.name) {
+            return Ok(false);
+        }
+
+        product.tiers.retain(|other| other.name != tier.name);
+        product.tiers.push(tier.clone());
+        product.tiers.sort_by_key(|tier| tier.level);
+
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl BanRepository for MemoryRepository {
+    async fn banned_hwids(&self) -> RepositoryResult<Vec<String>> {
+        Ok(self.tables.lock().banned_hwids.iter().cloned().collect())
+    }
+
+    async fn is_hwid_banned(&self, hwid: &str) -> RepositoryResult<bool> {
+        Ok(self.tables.lock().banned_hwids.contains(hwid))
+    }
+
+    async fn ban(&self, hwid: &str, _reason: Option<&str>, _banned_by: Option<&str>) -> RepositoryResult<bool> {
+        Ok(self.tables.lock().banned_hwids.insert(hwid.to_string()))
+    }
+
+    async fn unban(&self, hwid: &str) -> RepositoryResult<bool> {
+        Ok(self.tables.lock().banned_hwids.remove(hwid))
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for MemoryRepository {
+    async fn create(&self, url: &str, secret: &str, events: &[WebhookEvent]) -> RepositoryResult<WebhookSubscription> {
+        let mut tables = self.tables.lock();
+        tables.next_webhook_id += 1;
+        let webhook = WebhookSubscription {
+            id: format!("webhook-{}", tables.next_webhook_id),
+            url: url.to_string(),
+            secret: secret.to_string(),
+            events: events.to_vec(),
+            created_at: timestamp_text(now()),
+        };
+        tables.webhooks.push(webhook.clone());
+
+        Ok(webhook)
+    }
+
+    async fn list(&self) -> RepositoryResult<Vec<WebhookSubscription>> {
+        Ok(self.tables.lock().webhooks.clone())
+    }
+
+    async fn delete(&self, webhook_id: &str) -> RepositoryResult<bool> {
+        let mut tables = self.tables.lock();
+        let before = tables.webhooks.len();
+        tables.webhooks.retain(|webhook| webhook.id != webhook_id);
+        tables.deliveries.retain(|delivery| delivery.webhook_id != webhook_id);
+
+        Ok(tables.webhooks.len() != before)
+    }
+
+    async fn enqueue(&self, event: WebhookEvent, payload: &str) -> RepositoryResult<usize> {
+        let mut tables = self.tables.lock();
+        let now = now();
+
+        let webhook_ids: Vec<String> = tables
+            .webhooks
+            .iter()
+            .filter(|webhook| webhook.events.contains(&event))
+            .map(|webhook| webhook.id.clone())
+            .collect();
+        for webhook_id in &webhook_ids {
+            tables.next_delivery_id += 1;
+            let id = format!("delivery-{}", tables.next_delivery_id);
+            tables.deliveries.push(MemoryDelivery {
+                id,
+                webhook_id: webhook_id.clone(),
+                event,
+                payload: payload.to_string(),
+                status: DeliveryStatus::Pending,
+                attempts: 0,
+                next_attempt_at: now,
+                last_status_code: None,
+                last_error: None,
+                created_at: now,
+                delivered_at: None,
+            });
+        }
+
+        Ok(webhook_ids.len())
+    }
+
+    async fn claim_due(&self, limit: i64, lease_seconds: i64) -> RepositoryResult<Vec<DueDelivery>> {
+        let mut guard = self.tables.lock();
+        let tables = &mut *guard;
+        let now = now();
+
+        let mut due = Vec::new();
+        for delivery in tables.deliveries.iter_mut() {
+            if due.len() as i64 >= limit {
+                break;
+            }
+            if delivery.status != DeliveryStatus::Pending || delivery.next_attempt_at > now {
+                continue;
+            }
+            let Some(webhook) = tables.webhooks.iter().find(|webhook| webhook.id == delivery.webhook_id) else {
+                continue;
+            };
+
+            delivery.next_attempt_at = now + lease_seconds;
+            due.push(DueDelivery {
+                id: delivery.id.clone(),
+                event: delivery.event,
+                payload: delivery.payload.clone(),
+                url: webhook.url.clone(),
+                secret: webhook.secret.clone(),
+                attempts: delivery.attempts,
+            });
+        }
+
+        Ok(due)
+    }
+
+    async fn record_attempt(&self, delivery_id: &str, attempt: &DeliveryAttempt) -> RepositoryResult<()> {
+        let mut tables = self.tables.lock();
+        let Some(delivery) = tables.deliveries.iter_mut().find(|delivery| delivery.id == delivery_id) else {
+            return Ok(());
+        };
+
+        delivery.attempts += 1;
+        match attempt {
+            DeliveryAttempt::Delivered { status_code } => {
+                delivery.status = DeliveryStatus::Delivered;
+                delivery.last_status_code = Some(*status_code);
+                delivery.last_error = None;
+                delivery.delivered_at = Some(now());
+            }
+            DeliveryAttempt::Retry { status_code, error, retry_in_seconds } => {
+                delivery.last_status_code = *status_code;
+                delivery.last_error = Some(error.clone());
+                delivery.next_attempt_at = now() + retry_in_seconds;
+            }
+            DeliveryAttempt::Failed { status_code, error } => {
+                delivery.status = DeliveryStatus::Failed;
+                delivery.last_status_code = *status_code;
+                delivery.last_error = Some(error.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn deliveries(
+        &self,
+        webhook_id: Option<&str>,
+        status: Option<DeliveryStatus>,
+        limit: i64,
+    ) -> RepositoryResult<Vec<WebhookDelivery>> {
+        Ok(self
+            .tables
+            .lock()
+            .deliveries
+            .iter()
+            .rev()
+            .filter(|delivery| webhook_id.is_none_or(|webhook_id| delivery.webhook_id == webhook_id))
+            .filter(|delivery| status.is_none_or(|status| delivery.status == status))
+            .take(limit.max(0) as usize)
+            .map(|delivery| WebhookDelivery {
+                id: delivery.id.clone(),
+                webhook_id: delivery.webhook_id.clone(),
+                event: delivery.event,
+                status: delivery.status,
+                attempts: delivery.attempts,
+                last_status_code: delivery.last_status_code,
+                last_error: delivery.last_error.clone(),
+                next_attempt_at: (delivery.status == DeliveryStatus::Pending).then(|| timestamp_text(delivery.next_attempt_at)),
+                created_at: timestamp_text(delivery.created_at),
+                delivered_at: delivery.delivered_at.map(timestamp_text),
+            })
+            .collect())
+    }
+
+    async fn redeliver(&self, delivery_id: &str) -> RepositoryResult<bool> {
+        let mut tables = self.tables.lock();
+        let Some(delivery) = tables.deliveries.iter_mut().find(|delivery| delivery.id == delivery_id) else {
+            return Ok(false);
+        };
+
+        delivery.status = DeliveryStatus::Pending;
+        delivery.attempts = 0;
+        delivery.next_attempt_at = now();
+        delivery.delivered_at = None;
+
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl IdentityRepository for MemoryRepository {
+    async fn link(&self, user_id: &str, account: &ExternalAccount) -> RepositoryResult<bool> {
+        let mut tables = self.tables.lock();
+        let taken = tables.identities.iter().any(|(owner, linked)| {
+            owner != user_id && linked.provider == account.provider && linked.external_id == account.external_id
+        });
+        if taken {
+            return Ok(false);
+        }
+
+        tables.identities.retain(|(owner, linked)| !(owner == user_id && linked.provider == account.provider));
+        tables.identities.push((user_id.to_string(), LinkedAccount {
+            provider: account.provider.clone(),
+            external_id: account.external_id.clone(),
+            username: account.username.clone(),
+            linked_at: timestamp_text(now()),
+        }));
+
+        Ok(true)
+    }
+
+    async fn unlink(&self, user_id: &str, provider: &str) -> RepositoryResult<bool> {
+        let mut tables = self.tables.lock();
+        let before = tables.identities.len();
+        tables.identities.retain(|(owner, linked)| !(owner == user_id && linked.provider == provider));
+
+        Ok(tables.identities.len() != before)
+    }
+
+    async fn for_user(&self, user_id: &str) -> RepositoryResult<Vec<LinkedAccount>> {
+        Ok(self.for_users(&[user_id.to_string()]).await?.remove(user_id).unwrap_or_default())
+    }
+
+    async fn for_users(&self, user_ids: &[String]) -> RepositoryResult<HashMap<String, Vec<LinkedAccount>>> {
+        let mut accounts: HashMap<String, Vec<LinkedAccount>> = HashMap::new();
+        for (owner, linked) in &self.tables.lock().identities {
+            if user_ids.contains(owner) {
+                accounts.entry(owner.clone()).or_default().push(linked.clone());
+            }
+        }
+        for linked in accounts.values_mut() {
+            linked.sort_by(|a, b| a.provider.cmp(&b.provider));
+        }
+
+        Ok(accounts)
+    }
+
+    async fn find_user(&self, provider: &str, external_id: &str) -> RepositoryResult<Option<String>> {
+        Ok(self
+            .tables
+            .lock()
+            .identities
+            .iter()
+            .find(|(_, linked)| linked.provider == provider && linked.external_id == external_id)
+            .map(|(owner, _)| owner.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> (Arc<MemoryRepository>, Repositories) {
+        let backend = Arc::new(MemoryRepository::new());
+        backend.add_user("user-1", "user@example.com", "hash", Role::User, 2);
+        backend.add_product("game", "Game", HwidPolicy::PerAccount, None);
+        (backend.clone(), Repositories::memory(backend))
+    }
+
+    #[actix_web::test]
+    async fn bind_respects_slots_per_scope() {
+        let (_, repos) = setup();
+
+        assert!(repos.devices.bind("user-1", None, 2, "hwid-a", None).await.unwrap());
+        // The same HWID can't take a second slot
+        assert!(!repos.devices.bind("user-1", None, 2, "hwid-a", None).await.unwrap());
+        assert!(repos.devices.bind("user-1", None, 2, "hwid-b", None).await.unwrap());
+        assert!(!repos.devices.bind("user-1", None, 2, "hwid-c", None).await.unwrap());
+        // Per product devices are counted separately from the account wide ones
+        assert!(repos.devices.bind("user-1", Some("game"), 1, "hwid-c", None).await.unwrap());
+
+        assert_eq!(repos.devices.list("user-1").await.unwrap().len(), 3);
+    }
+
+    #[actix_web::test]
+    async fn release_starts_cooldown() {
+        let (_, repos) = setup();
+        repos.devices.bind("user-1", None, 2, "hwid-a", None).await.unwrap();
+        repos.devices.bind("user-1", None, 2, "hwid-b", None).await.unwrap();
+        let devices = repos.devices.list("user-1").await.unwrap();
+
+        assert_eq!(repos.devices.release_cooldown_remaining("user-1", 168).await.unwrap(), None);
+        assert_eq!(repos.devices.release("user-1", &devices[0].id, 168).await.unwrap(), DeviceRelease::Released);
+        assert!(!repos.devices.exists("user-1", &devices[0].id).await.unwrap());
+
+        let remaining = repos.devices.release_cooldown_remaining("user-1", 168).await.unwrap();
+        assert!(remaining.is_some_and(|seconds| seconds > 167 * 3600));
+        assert_eq!(repos.devices.release("user-1", &devices[1].id, 168).await.unwrap(), DeviceRelease::Cooldown);
+        assert!(repos.devices.exists("user-1", &devices[1].id).await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn releasing_a_missing_device_keeps_the_cooldown_free() {
+        let (_, repos) = setup();
+
+        assert_eq!(repos.devices.release("user-1", "device-404", 168).await.unwrap(), DeviceRelease::NotFound);
+        assert_eq!(repos.devices.release_cooldown_remaining("user-1", 168).await.unwrap(), None);
+    }
+
+    #[actix_web::test]
+    async fn expired_licenses_are_not_active() {
+        let (backend, repos) = setup();
+        backend.add_product("tool", "Tool", HwidPolicy::None, None);
+        backend.add_license("user-1", "game", now() - 60);
+        repos.licenses.assign("user-1", "tool", Some(24), None).await.unwrap();
+
+        assert_eq!(repos.licenses.for_user("user-1").await.unwrap().len(), 2);
+        let active = repos.licenses.active_for_user("user-1").await.unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].product_id, "tool");
+    }
+
+    #[actix_web::test]
+    async fn extend_all_reports_holders() {
+        let (backend, repos) = setup();
+        backend.add_user("user-2", "other@example.com", "hash", Role::User, 1);
+        backend.add_license("user-1", "game", now() - 60);
+
+        assert_eq!(repos.licenses.extend_all("game", 2).await.unwrap(), vec!["user-1".to_string()]);
+        assert_eq!(repos.licenses.active_for_user("user-1").await.unwrap().len(), 1);
+        assert!(repos.licenses.extend_all("other", 2).await.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn holders_are_listed_by_product_and_tier() {
+        let (backend, repos) = setup();
+        backend.add_user("user-2", "other@example.com", "hash", Role::User, 1);
+        backend.add_tier("game", "premium", 1, &["aimbot"]);
+        backend.add_license("user-1", "game", now() - 60);
+        repos.licenses.assign("user-2", "game", Some(1), Some("premium")).await.unwrap();
+
+        assert_eq!(repos.licenses.holders("game", None).await.unwrap(), vec!["user-1".to_string(), "user-2".to_string()]);
+        assert_eq!(repos.licenses.holders("game", Some("premium")).await.unwrap(), vec!["user-2".to_string()]);
+        assert!(repos.licenses.holders("other", None).await.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn lifetime_licenses_never_expire() {
+        let (backend, repos) = setup();
+        backend.add_product("tool", "Tool", HwidPolicy::None, None);
+        backend.add_license("user-1", "game", now() - 60);
+        repos.licenses.assign("user-1", "tool", None, None).await.unwrap();
+        repos.licenses.make_lifetime("user-1", "game").await.unwrap();
+
+        let active = repos.licenses.active_for_user("user-1").await.unwrap();
+        assert_eq!(active.len(), 2);
+        assert!(active.iter().all(|license| license.expires_at.is_none() && license.time_remaining_seconds.is_none()));
+        assert!(repos.licenses.take_expired().await.unwrap().is_empty());
+        // Compensation only moves expiries, which lifetime licenses don't have
+        assert!(repos.licenses.extend_all("game", 2).await.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn keys_are_single_use() {
+        let (_, repos) = setup();
+
+        assert!(repos.keys.insert("KEY", "game", Some(24), None).await.unwrap());
+        assert!(!repos.keys.insert("KEY", "game", Some(48), None).await.unwrap());
+        assert_eq!(repos.keys.find("KEY").await.unwrap().and_then(|key| key.time_hours), Some(24));
+        repos.keys.consume("KEY").await.unwrap();
+        assert!(repos.keys.find("KEY").await.unwrap().is_none());
+    }
+
+    #[actix_web::test]
+    async fn tier_levels_are_unique_per_product() {
+        let (_, repos) = setup();
+        let tier = |name: &str, level| Tier { name: name.to_string(), level, entitlements: vec![name.to_string()] };
+
+        assert!(repos.products.set_tier("game", &tier("premium", 2)).await.unwrap());
+        assert!(repos.products.set_tier("game", &tier("basic", 1)).await.unwrap());
+        assert!(!repos.products.set_tier("game", &tier("vip", 2)).await.unwrap());
+        // Updating a tier in place keeps its own level
+        assert!(repos.products.set_tier("game", &tier("premium", 2)).await.unwrap());
+
+        let names: Vec<String> = repos.products.tiers("game").await.unwrap().into_iter().map(|tier| tier.name).collect();
+        assert_eq!(names, ["basic", "premium"]);
+
+        repos.licenses.assign("user-1", "game", Some(24), Some("basic")).await.unwrap();
+        repos.licenses.set_tier("user-1", "game", Some("premium")).await.unwrap();
+        let licenses = repos.licenses.for_user("user-1").await.unwrap();
+        assert_eq!(licenses[0].tier, Some(tier("premium", 2)));
+    }
+
+    #[actix_web::test]
+    async fn banned_hwids() {
+        let (backend, repos) = setup();
+        backend.ban_hwid("hwid-a");
+
+        assert!(repos.bans.is_hwid_banned("hwid-a").await.unwrap());
+        assert!(!repos.bans.is_hwid_banned("hwid-b").await.unwrap());
+        assert_eq!(repos.bans.banned_hwids().await.unwrap(), vec!["hwid-a".to_string()]);
+    }
+}
@@ -0,0 +1,423 @@
+pub mod postgres;
+pub use postgres::*;
+/// Backs tests and the in-memory server (`AppState::in_memory`), never a production deployment
+pub mod memory;
+pub use memory::MemoryRepository;
+/// Products' hardware binding, stored with them and read back into licenses
+pub use authit_types::HwidPolicy;
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::auth::HwidComponents;
+use crate::handlers::account::Role;
+use crate::auth::oauth::LinkedAccount;
+use crate::webhook::{DeliveryStatus, WebhookDelivery, WebhookEvent};
+
+#[derive(Debug)]
+pub enum RepositoryError {
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for RepositoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepositoryError::Database(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<sqlx::Error> for RepositoryError {
+    fn from(err: sqlx::Error) -> Self {
+        RepositoryError::Database(err)
+    }
+}
+
+pub type RepositoryResult<T> = Result<T, RepositoryError>;
+
+/// What login needs to check a password
+#[derive(Debug, Clone)]
+pub struct UserCredentials {
+    pub id: String,
+    pub password_hash: String,
+    pub role: Role,
+}
+
+/// A user as administrators see it
+#[derive(Debug, Clone)]
+pub struct User {
+    pub id: String,
+    pub email: String,
+    pub role: Role,
+    pub banned: bool,
+    pub device_slots: i32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct UserStatus {
+    pub banned: bool,
+    pub device_slots: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub id: String,
+    /// `None` for account wide devices
+    pub product_id: Option<String>,
+    pub hwid: String,
+    pub components: Option<HwidComponents>,
+    pub name: Option<String>,
+    pub bound_at: String,
+}
+
+/// A license together with the product settings `/auth` enforces
+#[derive(Debug, Clone)]
+pub struct License {
+    pub product_id: String,
+    /// Unix timestamp, `None` for lifetime licenses
+    pub expires_at: Option<i64>,
+    pub max_sessions: Option<i32>,
+    pub hwid_policy: HwidPolicy,
+    pub hwid_device_limit: Option<i32>,
+    /// `None` for licenses without a tier
+    pub tier: Option<Tier>,
+}
+
+/// A license that hasn't expired yet, as listed to its owner
+#[derive(Debug, Clone)]
+pub struct ActiveLicense {
+    pub product_id: String,
+    pub product_name: String,
+    /// `None` for lifetime licenses, like the remaining time
+    pub expires_at: Option<String>,
+    pub time_remaining_seconds: Option<i64>,
+    pub frozen: bool,
+    pub tier: Option<Tier>,
+}
+
+/// A license `take_expired` found past its expiry
+#[derive(Debug, Clone)]
+pub struct ExpiredLicense {
+    pub user_id: String,
+    pub product_id: String,
+    pub expires_at: i64, // unix timestamp
+}
+
+#[derive(Debug, Clone)]
+pub struct Product {
+    pub id: String,
+    pub name: String,
+    pub frozen: bool,
+    /// `None` means unlimited
+    pub max_sessions: Option<i32>,
+    pub hwid_policy: HwidPolicy,
+    pub hwid_device_limit: Option<i32>,
+}
+
+/// A named tier of a product, licenses of a higher level rank above the lower ones
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tier {
+    pub name: String,
+    pub level: i32,
+    /// Feature names `/auth` returns for licenses of this tier
+    pub entitlements: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CdKey {
+    pub key: String,
+    pub product_id: String,
+    /// `None` for lifetime keys
+    pub time_hours: Option<i64>,
+    /// The tier the key grants, `None` for keys that only add time
+    pub tier: Option<String>,
+}
+
+/// A webhook together with the secret its deliveries are signed with
+#[derive(Debug, Clone)]
+pub struct WebhookSubscription {
+    pub id: String,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<WebhookEvent>,
+    pub created_at: String,
+}
+
+/// What the API shows of a subscription, everything but the secret
+impl From<WebhookSubscription> for crate::webhook::Webhook {
+    fn from(webhook: WebhookSubscription) -> Self {
+        Self { id: webhook.id, url: webhook.url, events: webhook.events, created_at: webhook.created_at }
+    }
+}
+
+/// An account at an OAuth provider, as its userinfo endpoint describes it
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ExternalAccount {
+    pub provider: String,
+    pub external_id: String,
+    pub username: Option<String>,
+}
+
+/// A delivery claimed by the dispatcher, with what's needed to send it
+#[derive(Debug, Clone)]
+pub struct DueDelivery {
+    pub id: String,
+    pub event: WebhookEvent,
+    pub payload: String,
+    pub url: String,
+    pub secret: String,
+    /// Attempts made before this one
+    pub attempts: i32,
+}
+
+#[derive(Debug, Clone)]
+pub enum DeliveryAttempt {
+    Delivered { status_code: i32 },
+    /// Try again after `retry_in_seconds`
+    Retry { status_code: Option<i32>, error: String, retry_in_seconds: i64 },
+    /// Out of attempts
+    Failed { status_code: Option<i32>, error: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceRelease {
+    Released,
+    /// Another release started the cooldown in the meantime
+    Cooldown,
+    /// The device was already gone, the cooldown is left untouched
+    NotFound,
+}
+
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn find_by_email(&self, email: &str) -> RepositoryResult<Option<UserCredentials>>;
+
+    async fn get(&self, user_id: &str) -> RepositoryResult<Option<User>>;
+
+    /// Returns the new user's id, `None` if the email is taken
+    async fn create(&self, email: &str, password_hash: &str, role: Role) -> RepositoryResult<Option<String>>;
+
+    /// `None` if the user doesn't exist
+    async fn status(&self, user_id: &str) -> RepositoryResult<Option<UserStatus>>;
+
+    /// Returns false if the user doesn't exist
+    async fn set_role(&self, user_id: &str, role: Role) -> RepositoryResult<bool>;
+
+    /// Returns false if the user doesn't exist
+    async fn set_banned(&self, user_id: &str, banned: bool) -> RepositoryResult<bool>;
+}
+
+#[async_trait]
+pub trait DeviceRepository: Send + Sync {
+    /// Every device bound to the user, oldest first
+    async fn list(&self, user_id: &str) -> RepositoryResult<Vec<Device>>;
+
+    /// Bind a HWID if fewer than `slots` devices are bound for `product_id`
+    /// Returns false if every slot is taken or the HWID is already bound
+    /// Concurrent binds for the same user are serialised so they can't overfill the slots
+    async fn bind(
+        &self,
+        user_id: &str,
+        product_id: Option<&str>,
+        slots: i32,
+        hwid: &str,
+        components: Option<&HwidComponents>,
+    ) -> RepositoryResult<bool>;
+
+    /// Replace a device's fingerprint after it was recognised by its components
+    async fn update_fingerprint(&self, device_id: &str, hwid: &str, components: &HwidComponents) -> RepositoryResult<()>;
+
+    /// Returns false if the user has no such device
+    async fn rename(&self, user_id: &str, device_id: &str, name: &str) -> RepositoryResult<bool>;
+
+    async fn exists(&self, user_id: &str, device_id: &str) -> RepositoryResult<bool>;
+
+    /// Seconds until the user may release another device, `None` if they can release one now
+    async fn release_cooldown_remaining(&self, user_id: &str, cooldown_hours: i64) -> RepositoryResult<Option<i64>>;
+
+    /// Delete the device and start the cooldown atomically, the cooldown only starts if a device was deleted
+    async fn release(&self, user_id: &str, device_id: &str, cooldown_hours: i64) -> RepositoryResult<DeviceRelease>;
+}
+
+#[async_trait]
+pub trait LicenseRepository: Send + Sync {
+    /// Every license the user holds, expired or not
+    async fn for_user(&self, user_id: &str) -> RepositoryResult<Vec<License>>;
+
+    /// The user's unexpired licenses, lifetime ones first and then by latest expiry
+    async fn active_for_user(&self, user_id: &str) -> RepositoryResult<Vec<ActiveLicense>>;
+
+    /// Push an existing license's expiry back by `hours`, lifetime licenses stay as they are
+    async fn extend(&self, user_id: &str, product_id: &str, hours: i64) -> RepositoryResult<()>;
+
+    /// Give the user a new license running `hours` from now, or a lifetime license for `None`
+    async fn assign(&self, user_id: &str, product_id: &str, hours: Option<i64>, tier: Option<&str>) -> RepositoryResult<()>;
+
+    /// Turn an existing license into a lifetime license, its tier stays as it is
+    async fn make_lifetime(&self, user_id: &str, product_id: &str) -> RepositoryResult<()>;
+
+    /// Change the tier of an existing license, its expiry stays as it is
+    async fn set_tier(&self, user_id: &str, product_id: &str, tier: Option<&str>) -> RepositoryResult<()>;
+
+    /// Extend every timed license for a product, lifetime licenses are left out
+    /// Returns the ids of the users whose licenses were extended
+    async fn extend_all(&self, product_id: &str, hours: i64) -> RepositoryResult<Vec<String>>;
+
+    /// Ids of the users holding a license for a product, expired or not, only those of `tier` if given
+    async fn holders(&self, product_id: &str, tier: Option<&str>) -> RepositoryResult<Vec<String>>;
+
+    /// Licenses that have expired since they were last returned here, each expiry is returned once
+    /// Extending a license past now makes its next expiry count again
+    async fn take_expired(&self) -> RepositoryResult<Vec<ExpiredLicense>>;
+}
+
+#[async_trait]
+pub trait KeyRepository: Send + Sync {
+    async fn find(&self, key: &str) -> RepositoryResult<Option<CdKey>>;
+
+    /// Returns false if the key already exists, `None` hours make a lifetime key
+    async fn insert(&self, key: &str, product_id: &str, time_hours: Option<i64>, tier: Option<&str>) -> RepositoryResult<bool>;
+
+    async fn consume(&self, key: &str) -> RepositoryResult<()>;
+
+    /// The product's keys that haven't been redeemed yet
+    async fn unused(&self, product_id: &str) -> RepositoryResult<Vec<CdKey>>;
+}
+
+#[async_trait]
+pub trait ProductRepository: Send + Sync {
+    async fn exists(&self, product_id: &str) -> RepositoryResult<bool>;
+
+    /// Every product, by name
+    async fn list(&self) -> RepositoryResult<Vec<Product>>;
+
+    /// Returns false if a product with this id already exists
+    async fn create(&self, product: &Product) -> RepositoryResult<bool>;
+
+    /// Returns false if the product doesn't exist
+    async fn set_frozen(&self, product_id: &str, frozen: bool) -> RepositoryResult<bool>;
+
+    /// `None` lifts the limit, returns false if the product doesn't exist
+    async fn set_max_sessions(&self, product_id: &str, max_sessions: Option<i32>) -> RepositoryResult<bool>;
+
+    /// `device_limit` is only kept for `HwidPolicy::Devices`, devices that are already bound stay bound
+    /// Returns false if the product doesn't exist
+    async fn set_hwid_policy(&self, product_id: &str, policy: HwidPolicy, device_limit: Option<i32>) -> RepositoryResult<bool>;
+
+    /// The product's tiers, lowest level first
+    async fn tiers(&self, product_id: &str) -> RepositoryResult<Vec<Tier>>;
+
+    /// Create a tier or update the one with the same name
+    /// Returns false if another of the product's tiers already has this level
+    async fn set_tier(&self, product_id: &str, tier: &Tier) -> RepositoryResult<bool>;
+}
+
+#[async_trait]
+pub trait BanRepository: Send + Sync {
+    async fn banned_hwids(&self) -> RepositoryResult<Vec<String>>;
+
+    async fn is_hwid_banned(&self, hwid: &str) -> RepositoryResult<bool>;
+
+    /// `banned_by` is the admin's user id, if known
+    /// Returns false if the HWID was already banned
+    async fn ban(&self, hwid: &str, reason: Option<&str>, banned_by: Option<&str>) -> RepositoryResult<bool>;
+
+    /// Returns false if the HWID wasn't banned
+    async fn unban(&self, hwid: &str) -> RepositoryResult<bool>;
+}
+
+#[async_trait]
+pub trait WebhookRepository: Send + Sync {
+    async fn create(&self, url: &str, secret: &str, events: &[WebhookEvent]) -> RepositoryResult<WebhookSubscription>;
+
+    /// Oldest first
+    async fn list(&self) -> RepositoryResult<Vec<WebhookSubscription>>;
+
+    /// Deletes its deliveries too, returns false if it doesn't exist
+    async fn delete(&self, webhook_id: &str) -> RepositoryResult<bool>;
+
+    /// Queue `payload` for every webhook subscribed to `event`, returns how many deliveries were queued
+    async fn enqueue(&self, event: WebhookEvent, payload: &str) -> RepositoryResult<usize>;
+
+    /// Take up to `limit` due deliveries, hidden from other dispatchers for `lease_seconds`
+    async fn claim_due(&self, limit: i64, lease_seconds: i64) -> RepositoryResult<Vec<DueDelivery>>;
+
+    async fn record_attempt(&self, delivery_id: &str, attempt: &DeliveryAttempt) -> RepositoryResult<()>;
+
+    /// The delivery log, newest first
+    async fn deliveries(
+        &self,
+        webhook_id: Option<&str>,
+        status: Option<DeliveryStatus>,
+        limit: i64,
+    ) -> RepositoryResult<Vec<WebhookDelivery>>;
+
+    /// Queue a delivery again right away with a fresh set of attempts
+    /// Returns false if it doesn't exist
+    async fn redeliver(&self, delivery_id: &str) -> RepositoryResult<bool>;
+}
+
+/// External accounts linked through OAuth, at most one per provider for each user
+#[async_trait]
+pub trait IdentityRepository: Send + Sync {
+    /// Link `account` to the user, replacing any account they had linked at the same provider
+    /// Returns false if it is already linked to another user
+    async fn link(&self, user_id: &str, account: &ExternalAccount) -> RepositoryResult<bool>;
+
+    /// Returns false if nothing was linked at that provider
+    async fn unlink(&self, user_id: &str, provider: &str) -> RepositoryResult<bool>;
+
+    async fn for_user(&self, user_id: &str) -> RepositoryResult<Vec<LinkedAccount>>;
+
+    /// Every linked account of these users, by user id, users without any are left out
+    async fn for_users(&self, user_ids: &[String]) -> RepositoryResult<HashMap<String, Vec<LinkedAccount>>>;
+
+    /// The user an external account is linked to
+    async fn find_user(&self, provider: &str, external_id: &str) -> RepositoryResult<Option<String>>;
+}
+
+/// Every repository the handlers use, each behind a trait so they can run without Postgres
+#[derive(Clone)]
+pub struct Repositories {
+    pub users: Arc<dyn UserRepository>,
+    pub devices: Arc<dyn DeviceRepository>,
+    pub licenses: Arc<dyn LicenseRepository>,
+    pub keys: Arc<dyn KeyRepository>,
+    pub products: Arc<dyn ProductRepository>,
+    pub bans: Arc<dyn BanRepository>,
+    pub webhooks: Arc<dyn WebhookRepository>,
+    pub identities: Arc<dyn IdentityRepository>,
+}
+
+impl Repositories {
+    pub fn postgres(pool: sqlx::PgPool) -> Self {
+        Self::from_backend(Arc::new(PgRepository::new(pool)))
+    }
+
+    /// Use one backend for every repository
+    fn from_backend<B>(backend: Arc<B>) -> Self
+    where
+        B: UserRepository
+            + DeviceRepository
+            + LicenseRepository
+            + KeyRepository
+            + ProductRepository
+            + BanRepository
+            + WebhookRepository
+            + IdentityRepository
+            + 'static,
+    {
+        Self {
+            users: backend.clone(),
+            devices: backend.clone(),
+            licenses: backend.clone(),
+            keys: backend.clone(),
+            products: backend.clone(),
+            bans: backend.clone(),
+            webhooks: backend.clone(),
+            identities: backend,
+        }
+    }
+}
@@ -0,0 +1,860 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+
+use crate::auth::HwidComponents;
+use crate::handlers::account::Role;
+use crate::auth::oauth::LinkedAccount;
+use crate::webhook::{DeliveryStatus, WebhookDelivery, WebhookEvent};
+use super::{
+    ActiveLicense, BanRepository, CdKey, DeliveryAttempt, Device, DeviceRelease, DeviceRepository, DueDelivery, ExpiredLicense,
+    ExternalAccount, HwidPolicy, IdentityRepository, KeyRepository, License, LicenseRepository, Product, ProductRepository, RepositoryResult, Tier, User,
+    UserCredentials, UserRepository, UserStatus, WebhookRepository, WebhookSubscription,
+};
+
+pub struct PgRepository {
+    pool: sqlx::PgPool,
+}
+
+impl PgRepository {
+    pub fn new(pool: sqlx::PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+/// A license's tier from the columns of a `LEFT JOIN product_tiers`, all NULL without a tier
+fn joined_tier(name: Option<String>, level: Option<i32>, entitlements: Option<Vec<String>>) -> Option<Tier> {
+    Some(Tier { name: name?, level: level?, entitlements: entitlements.unwrap_or_default() })
+}
+
+#[async_trait]
+impl UserRepository for PgRepository {
+    async fn find_by_email(&self, email: &str) -> RepositoryResult<Option<UserCredentials>> {
+        let row = sqlx::query_as::<_, (String, String, Role)>("SELECT id, password, role FROM users WHERE email = $1")
+            .bind(email)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|(id, password_hash, role)| UserCredentials { id, password_hash, role }))
+    }
+
+    async fn get(&self, user_id: &str) -> RepositoryResult<Option<User>> {
+        let row = sqlx::query_as::<_, (String, String, Role, bool, i32)>(
+            "SELECT id, email, role, banned, device_slots FROM users WHERE id = $1"
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|(id, email, role, banned, device_slots)| User { id, email, role, banned, device_slots }))
+    }
+
+    async fn create(&self, email: &str, password_hash: &str, role: Role) -> RepositoryResult<Option<String>> {
+        let row = sqlx::query_as::<_, (String,)>(
+            "INSERT INTO users (email, password, role) VALUES ($1, $2, $3) ON CONFLICT (email) DO NOTHING RETURNING id"
+        )
+        .bind(email)
+        .bind(password_hash)
+        .bind(role)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| row.0))
+    }
+
+    async fn status(&self, user_id: &str) -> RepositoryResult<Option<UserStatus>> {
+        let row = sqlx::query_as::<_, (bool, i32)>("SELECT banned, device_slots FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|(banned, device_slots)| UserStatus { banned, device_slots }))
+    }
+
+    async fn set_role(&self, user_id: &str, role: Role) -> RepositoryResult<bool> {
+        let result = sqlx::query("UPDATE users SET role = $1, updated_at = NOW() WHERE id = $2")
+            .bind(role)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_banned(&self, user_id: &str, banned: bool) -> RepositoryResult<bool> {
+        let result = sqlx::query("UPDATE users SET banned = $1, updated_at = NOW() WHERE id = $2")
+            .bind(banned)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[async_trait]
+impl DeviceRepository for PgRepository {
+    async fn list(&self, user_id: &str) -> RepositoryResult<Vec<Device>> {
+        let rows = sqlx::query_as::<_, (String, Option<String>, String, Option<String>, Option<String>, String)>(
+            "SELECT id, product_id, hwid, components::TEXT, name, bound_at::TEXT FROM user_devices WHERE user_id = $1 ORDER BY bound_at"
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(id, product_id, hwid, components, name, bound_at)| Device {
+                id,
+                product_id,
+                hwid,
+                components: components.and_then(|raw| serde_json::from_str(&raw).ok()),
+                name,
+                bound_at,
+            })
+            .collect())
+    }
+
+    async fn bind(
+        &self,
+        user_id: &str,
+        product_id: Option<&str>,
+        slots: i32,
+        hwid: &str,
+        components: Option<&HwidComponents>,
+    ) -> RepositoryResult<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        // Held until commit, so a concurrent bind for this user waits and then counts our row
+        let user = sqlx::query("SELECT id FROM users WHERE id = $1 FOR UPDATE")
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if user.is_none() {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+
+        let result = sqlx::query(
+            "INSERT INTO user_devices (user_id, product_id, hwid, components)
+             SELECT $1, $2, $3, $4::JSONB
+             WHERE (SELECT COUNT(*) FROM user_devices WHERE user_id = $1 AND product_id IS NOT DISTINCT FROM $2) < $5
+             ON CONFLICT (user_id, product_id, hwid) DO NOTHING"
+        )
+        .bind(user_id)
+        .bind(product_id)
+        .bind(hwid)
+        .bind(components.and_then(|components| serde_json::to_string(components).ok()))
+        .bind(slots)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn update_fingerprint(&self, device_id: &str, hwid: &str, components: &HwidComponents) -> RepositoryResult<()> {
+        sqlx::query("UPDATE user_devices SET hwid = $1, components = $2::JSONB WHERE id = $3")
+            .bind(hwid)
+            .bind(serde_json::to_string(components).ok())
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn rename(&self, user_id: &str, device_id: &str, name: &str) -> RepositoryResult<bool> {
+        let result = sqlx::query("UPDATE user_devices SET name = $1 WHERE id = $2 AND user_id = $3")
+            .bind(name)
+            .bind(device_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn exists(&self, user_id: &str, device_id: &str) -> RepositoryResult<bool> {
+        let row = sqlx::query("SELECT id FROM user_devices WHERE id = $1 AND user_id = $2")
+            .bind(device_id)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    async fn release_cooldown_remaining(&self, user_id: &str, cooldown_hours: i64) -> RepositoryResult<Option<i64>> {
+        let row = sqlx::query_as::<_, (i64,)>(
+            "SELECT EXTRACT(EPOCH FROM (last_device_release_at + ($2 || ' hours')::INTERVAL - NOW()))::BIGINT
+             FROM users
+             WHERE id = $1 AND last_device_release_at > NOW() - ($2 || ' hours')::INTERVAL"
+        )
+        .bind(user_id)
+        .bind(cooldown_hours)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| row.0))
+    }
+
+    async fn release(&self, user_id: &str, device_id: &str, cooldown_hours: i64) -> RepositoryResult<DeviceRelease> {
+        let mut tx = self.pool.begin().await?;
+
+        let cooling_down = sqlx::query_as::<_, (bool,)>(
+            "SELECT COALESCE(last_device_release_at > NOW() - ($2 || ' hours')::INTERVAL, FALSE)
+             FROM users WHERE id = $1 FOR UPDATE"
+        )
+        .bind(user_id)
+        .bind(cooldown_hours)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        match cooling_down {
+            None => {
+                tx.rollback().await?;
+                return Ok(DeviceRelease::NotFound);
+            }
+            Some((true,)) => {
+                tx.rollback().await?;
+                return Ok(DeviceRelease::Cooldown);
+            }
+            Some((false,)) => {}
+        }
+
+        let deleted = sqlx::query("DELETE FROM user_devices WHERE id = $1 AND user_id = $2")
+            .bind(device_id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        if deleted.rows_affected() != 1 {
+            tx.rollback().await?;
+            return Ok(DeviceRelease::NotFound);
+        }
+
+        sqlx::query("UPDATE users SET last_device_release_at = NOW(), updated_at = NOW() WHERE id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(DeviceRelease::Released)
+    }
+}
+
+#[async_trait]
+impl LicenseRepository for PgRepository {
+    async fn for_user(&self, user_id: &str) -> RepositoryResult<Vec<License>> {
+        let rows = sqlx::query_as::<_, (String, Option<i64>, Option<i32>, HwidPolicy, Option<i32>, Option<String>, Option<i32>, Option<Vec<String>>)>(
+            "SELECT ul.product_id, EXTRACT(EPOCH FROM ul.expires_at)::BIGINT, p.max_sessions, p.hwid_policy, p.hwid_device_limit,
+                    t.name, t.level, t.entitlements
+             FROM user_licenses ul
+             JOIN products p ON ul.product_id = p.id
+             LEFT JOIN product_tiers t ON t.product_id = ul.product_id AND t.name = ul.tier
+             WHERE ul.user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(product_id, expires_at, max_sessions, hwid_policy, hwid_device_limit, tier, level, entitlements)| License {
+                product_id,
+                expires_at,
+                max_sessions,
+                hwid_policy,
+                hwid_device_limit,
+                tier: joined_tier(tier, level, entitlements),
+            })
+            .collect())
+    }
+
+    async fn active_for_user(&self, user_id: &str) -> RepositoryResult<Vec<ActiveLicense>> {
+        let rows = sqlx::query_as::<_, (String, String, Option<String>, Option<i64>, bool, Option<String>, Option<i32>, Option<Vec<String>>)>(
+            "SELECT ul.product_id, p.name, ul.expires_at::TEXT, EXTRACT(EPOCH FROM (ul.expires_at - NOW()))::BIGINT, p.frozen,
+                    t.name, t.level, t.entitlements
+             FROM user_licenses ul
+             JOIN products p ON ul.product_id = p.id
+             LEFT JOIN product_tiers t ON t.product_id = ul.product_id AND t.name = ul.tier
+             WHERE ul.user_id = $1 AND (ul.expires_at IS NULL OR ul.expires_at > NOW())
+             ORDER BY ul.expires_at DESC NULLS FIRST"
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(product_id, product_name, expires_at, time_remaining_seconds, frozen, tier, level, entitlements)| ActiveLicense {
+                product_id,
+                product_name,
+                expires_at,
+                time_remaining_seconds,
+                frozen,
+                tier: joined_tier(tier, level, entitlements),
+            })
+            .collect())
+    }
+
+    async fn extend(&self, user_id: &str, product_id: &str, hours: i64) -> RepositoryResult<()> {
+        sqlx::query(
+            "UPDATE user_licenses
+             SET expires_at = expires_at + ($1 || ' hours')::INTERVAL,
+                 expiry_notified = expiry_notified AND expires_at + ($1 || ' hours')::INTERVAL <= NOW(),
+                 updated_at = NOW()
+             WHERE user_id = $2 AND product_id = $3 AND expires_at IS NOT NULL"
+        )
+            .bind(hours)
+            .bind(user_id)
+            .bind(product_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn assign(&self, user_id: &str, product_id: &str, hours: Option<i64>, tier: Option<&str>) -> RepositoryResult<()> {
+        sqlx::query(
+            "INSERT INTO user_licenses (user_id, product_id, expires_at, tier)
+             VALUES ($1, $2, CASE WHEN $3::BIGINT IS NULL THEN NULL ELSE NOW() + ($3 || ' hours')::INTERVAL END, $4)"
+        )
+            .bind(user_id)
+            .bind(product_id)
+            .bind(hours)
+            .bind(tier)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn make_lifetime(&self, user_id: &str, product_id: &str) -> RepositoryResult<()> {
+        sqlx::query(
+            "UPDATE user_licenses SET expires_at = NULL, expiry_notified = FALSE, updated_at = NOW()
+             WHERE user_id = $1 AND product_id = $2"
+        )
+            .bind(user_id)
+            .bind(product_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn set_tier(&self, user_id: &str, product_id: &str, tier: Option<&str>) -> RepositoryResult<()> {
+        sqlx::query("UPDATE user_licenses SET tier = $1, updated_at = NOW() WHERE user_id = $2 AND product_id = $3")
+            .bind(tier)
+            .bind(user_id)
+            .bind(product_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn extend_all(&self, product_id: &str, hours: i64) -> RepositoryResult<Vec<String>> {
+        let rows = sqlx::query_as::<_, (String,)>(
+            "UPDATE user_licenses
+             SET expires_at = expires_at + ($1 || ' hours')::INTERVAL,
+                 expiry_notified = expiry_notified AND expires_at + ($1 || ' hours')::INTERVAL <= NOW(),
+                 updated_at = NOW()
+             WHERE product_id = $2 AND expires_at IS NOT NULL
+             RETURNING user_id"
+        )
+        .bind(hours)
+        .bind(product_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| row.0).collect())
+    }
+
+    async fn holders(&self, product_id: &str, tier: Option<&str>) -> RepositoryResult<Vec<String>> {
+        let rows = sqlx::query_as::<_, (String,)>(
+            "SELECT user_id FROM user_licenses WHERE product_id = $1 AND ($2::TEXT IS NULL OR tier = $2)"
+        )
+        .bind(product_id)
+        .bind(tier)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| row.0).collect())
+    }
+
+    async fn take_expired(&self) -> RepositoryResult<Vec<ExpiredLicense>> {
+        let rows = sqlx::query_as::<_, (String, String, i64)>(
+            "UPDATE user_licenses
+             SET expiry_notified = TRUE
+             WHERE NOT expiry_notified AND expires_at <= NOW()
+             RETURNING user_id, product_id, EXTRACT(EPOCH FROM expires_at)::BIGINT"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(user_id, product_id, expires_at)| ExpiredLicense { user_id, product_id, expires_at })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl KeyRepository for PgRepository {
+    async fn find(&self, key: &str) -> RepositoryResult<Option<CdKey>> {
+        let row = sqlx::query_as::<_, (Option<i64>, String, Option<String>)>("SELECT time_hours, product_id, tier FROM cd_keys WHERE key = $1")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|(time_hours, product_id, tier)| CdKey { key: key.to_string(), product_id, time_hours, tier }))
+    }
+
+    async fn insert(&self, key: &str, product_id: &str, time_hours: Option<i64>, tier: Option<&str>) -> RepositoryResult<bool> {
+        let result = sqlx::query(
+            "INSERT INTO cd_keys (key, product_id, time_hours, tier, lifetime) VALUES ($1, $2, $3, $4, $3 IS NULL)
+             ON CONFLICT (key) DO NOTHING"
+        )
+        .bind(key)
+        .bind(product_id)
+        .bind(time_hours)
+        .bind(tier)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn consume(&self, key: &str) -> RepositoryResult<()> {
+        sqlx::query("DELETE FROM cd_keys WHERE key = $1")
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn unused(&self, product_id: &str) -> RepositoryResult<Vec<CdKey>> {
+        let rows = sqlx::query_as::<_, (String, Option<i64>, Option<String>)>(
+            "SELECT key, time_hours, tier FROM cd_keys WHERE product_id = $1 ORDER BY key"
+        )
+        .bind(product_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(key, time_hours, tier)| CdKey { key, product_id: product_id.to_string(), time_hours, tier })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl ProductRepository for PgRepository {
+    async fn exists(&self, product_id: &str) -> RepositoryResult<bool> {
+        let row = sqlx::query("SELECT id FROM products WHERE id = $1")
+            .bind(product_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    async fn list(&self) -> RepositoryResult<Vec<Product>> {
+        let rows = sqlx::query_as::<_, (String, String, bool, Option<i32>, HwidPolicy, Option<i32>)>(
+            "SELECT id, name, frozen, max_sessions, hwid_policy, hwid_device_limit FROM products ORDER BY name"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(id, name, frozen, max_sessions, hwid_policy, hwid_device_limit)| Product {
+                id,
+                name,
+                frozen,
+                max_sessions,
+                hwid_policy,
+                hwid_device_limit,
+            })
+            .collect())
+    }
+
+    async fn create(&self, product: &Product) -> RepositoryResult<bool> {
+        let result = sqlx::query(
+            "INSERT INTO products (id, name, frozen, max_sessions, hwid_policy, hwid_device_limit)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (id) DO NOTHING"
+        )
+        .bind(&product.id)
+        .bind(&product.name)
+        .bind(product.frozen)
+        .bind(product.max_sessions)
+        .bind(product.hwid_policy)
+        .bind(product.hwid_device_limit)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_frozen(&self, product_id: &str, frozen: bool) -> RepositoryResult<bool> {
+        let result = sqlx::query("UPDATE products SET frozen = $1 WHERE id = $2")
+            .bind(frozen)
+            .bind(product_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_max_sessions(&self, product_id: &str, max_sessions: Option<i32>) -> RepositoryResult<bool> {
+        let result = sqlx::query("UPDATE products SET max_sessions = $1 WHERE id = $2")
+            .bind(max_sessions)
+            .bind(product_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_hwid_policy(&self, product_id: &str, policy: HwidPolicy, device_limit: Option<i32>) -> RepositoryResult<bool> {
+        let result = sqlx::query("UPDATE products SET hwid_policy = $1, hwid_device_limit = $2 WHERE id = $3")
+            .bind(policy)
+            .bind(device_limit.filter(|_| policy == HwidPolicy::Devices))
+            .bind(product_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn tiers(&self, product_id: &str) -> RepositoryResult<Vec<Tier>> {
+        let rows = sqlx::query_as::<_, (String, i32, Vec<String>)>(
+            "SELECT name, level, entitlements FROM product_tiers WHERE product_id = $1 ORDER BY level"
+        )
+        .bind(product_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|(name, level, entitlements)| Tier { name, level, entitlements }).collect())
+    }
+
+    async fn set_tier(&self, product_id: &str, tier: &Tier) -> RepositoryResult<bool> {
+        let result = sqlx::query(
+            "INSERT INTO product_tiers (product_id, name, level, entitlements) VALUES ($1, $2, $3, $4)
+             ON CONFLICT (product_id, name) DO UPDATE SET level = EXCLUDED.level, entitlements = EXCLUDED.entitlements"
+        )
+        .bind(product_id)
+        .bind(&tier.name)
+        .bind(tier.level)
+        .bind(&tier.entitlements)
+        .execute(&self.pool)
+        .await;
+
+        match result {
+            Ok(_) => Ok(true),
+            // The level is unique per product
+            Err(sqlx::Error::Database(err)) if err.is_unique_violation() => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl BanRepository for PgRepository {
+    async fn banned_hwids(&self) -> RepositoryResult<Vec<String>> {
+        let rows = sqlx::query_as::<_, (String,)>("SELECT hwid FROM banned_hwids")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(|row| row.0).collect())
+    }
+
+    async fn is_hwid_banned(&self, hwid: &str) -> RepositoryResult<bool> {
+        let row = sqlx::query("SELECT hwid FROM banned_hwids WHERE hwid = $1")
+            .bind(hwid)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    async fn ban(&self, hwid: &str, reason: Option<&str>, banned_by: Option<&str>) -> RepositoryResult<bool> {
+        let result = sqlx::query(
+            "INSERT INTO banned_hwids (hwid, reason, banned_by) VALUES ($1, $2, $3) ON CONFLICT (hwid) DO NOTHING"
+        )
+        .bind(hwid)
+        .bind(reason)
+        .bind(banned_by)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn unban(&self, hwid: &str) -> RepositoryResult<bool> {
+        let result = sqlx::query("DELETE FROM banned_hwids WHERE hwid = $1")
+            .bind(hwid)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+fn subscription((id, url, secret, events, created_at): (String, String, String, Vec<String>, String)) -> WebhookSubscription {
+    WebhookSubscription {
+        id,
+        url,
+        secret,
+        // Names this version doesn't know (written by a newer one) are skipped
+        events: events.iter().filter_map(|event| WebhookEvent::parse(event)).collect(),
+        created_at,
+    }
+}
+
+type DeliveryRow = (String, String, String, DeliveryStatus, i32, Option<i32>, Option<String>, Option<String>, String, Option<String>);
+
+#[async_trait]
+impl WebhookRepository for PgRepository {
+    async fn create(&self, url: &str, secret: &str, events: &[WebhookEvent]) -> RepositoryResult<WebhookSubscription> {
+        let events: Vec<&str> = events.iter().map(WebhookEvent::as_str).collect();
+        let row = sqlx::query_as::<_, (String, String, String, Vec<String>, String)>(
+            "INSERT INTO webhooks (url, secret, events) VALUES ($1, $2, $3)
+             RETURNING id, url, secret, events, created_at::TEXT"
+        )
+        .bind(url)
+        .bind(secret)
+        .bind(events)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(subscription(row))
+    }
+
+    async fn list(&self) -> RepositoryResult<Vec<WebhookSubscription>> {
+        let rows = sqlx::query_as::<_, (String, String, String, Vec<String>, String)>(
+            "SELECT id, url, secret, events, created_at::TEXT FROM webhooks ORDER BY created_at"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(subscription).collect())
+    }
+
+    async fn delete(&self, webhook_id: &str) -> RepositoryResult<bool> {
+        let result = sqlx::query("DELETE FROM webhooks WHERE id = $1")
+            .bind(webhook_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn enqueue(&self, event: WebhookEvent, payload: &str) -> RepositoryResult<usize> {
+        let result = sqlx::query(
+            "INSERT INTO webhook_deliveries (webhook_id, event, payload)
+             SELECT id, $1, $2 FROM webhooks WHERE $1 = ANY(events)"
+        )
+        .bind(event.as_str())
+        .bind(payload)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() as usize)
+    }
+
+    async fn claim_due(&self, limit: i64, lease_seconds: i64) -> RepositoryResult<Vec<DueDelivery>> {
+        // SKIP LOCKED lets several instances claim at once without handing out the same delivery
+        let rows = sqlx::query_as::<_, (String, String, String, String, String, i32)>(
+            "UPDATE webhook_deliveries d
+             SET next_attempt_at = NOW() + ($2 || ' seconds')::INTERVAL
+             FROM webhooks w
+             WHERE w.id = d.webhook_id AND d.id IN (
+                 SELECT id FROM webhook_deliveries
+                 WHERE status = 'Pending' AND next_attempt_at <= NOW()
+                 ORDER BY next_attempt_at
+                 LIMIT $1
+                 FOR UPDATE SKIP LOCKED
+             )
+             RETURNING d.id, d.event, d.payload, w.url, w.secret, d.attempts"
+        )
+        .bind(limit)
+        .bind(lease_seconds)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(id, event, payload, url, secret, attempts)| {
+                Some(DueDelivery { id, event: WebhookEvent::parse(&event)?, payload, url, secret, attempts })
+            })
+            .collect())
+    }
+
+    async fn record_attempt(&self, delivery_id: &str, attempt: &DeliveryAttempt) -> RepositoryResult<()> {
+        let (status, status_code, error, retry_in_seconds) = match attempt {
+            DeliveryAttempt::Delivered { status_code } => (DeliveryStatus::Delivered, Some(*status_code), None, None),
+            DeliveryAttempt::Retry { status_code, error, retry_in_seconds } => {
+                (DeliveryStatus::Pending, *status_code, Some(error.as_str()), Some(*retry_in_seconds))
+            }
+            DeliveryAttempt::Failed { status_code, error } => (DeliveryStatus::Failed, *status_code, Some(error.as_str()), None),
+        };
+
+        sqlx::query(
+            "UPDATE webhook_deliveries
+             SET status = $2,
+                 attempts = attempts + 1,
+                 last_status_code = $3,
+                 last_error = $4,
+                 next_attempt_at = NOW() + ($5 || ' seconds')::INTERVAL,
+                 delivered_at = CASE WHEN $2 = 'Delivered'::webhook_delivery_status THEN NOW() END
+             WHERE id = $1"
+        )
+        .bind(delivery_id)
+        .bind(status)
+        .bind(status_code)
+        .bind(error)
+        .bind(retry_in_seconds)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn deliveries(
+        &self,
+        webhook_id: Option<&str>,
+        status: Option<DeliveryStatus>,
+        limit: i64,
+    ) -> RepositoryResult<Vec<WebhookDelivery>> {
+        let rows = sqlx::query_as::<_, DeliveryRow>(
+            "SELECT id, webhook_id, event, status, attempts, last_status_code, last_error,
+                    CASE WHEN status = 'Pending' THEN next_attempt_at::TEXT END, created_at::TEXT, delivered_at::TEXT
+             FROM webhook_deliveries
+             WHERE ($1::TEXT IS NULL OR webhook_id = $1) AND ($2::webhook_delivery_status IS NULL OR status = $2)
+             ORDER BY created_at DESC
+             LIMIT $3"
+        )
+        .bind(webhook_id)
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(id, webhook_id, event, status, attempts, last_status_code, last_error, next_attempt_at, created_at, delivered_at)| {
+                Some(WebhookDelivery {
+                    id,
+                    webhook_id,
+                    event: WebhookEvent::parse(&event)?,
+                    status,
+                    attempts,
+                    last_status_code,
+                    last_error,
+                    next_attempt_at,
+                    created_at,
+                    delivered_at,
+                })
+            })
+            .collect())
+    }
+
+    async fn redeliver(&self, delivery_id: &str) -> RepositoryResult<bool> {
+        let result = sqlx::query(
+            "UPDATE webhook_deliveries
+             SET status = 'Pending', attempts = 0, next_attempt_at = NOW(), delivered_at = NULL
+             WHERE id = $1"
+        )
+        .bind(delivery_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[async_trait]
+impl IdentityRepository for PgRepository {
+    async fn link(&self, user_id: &str, account: &ExternalAccount) -> RepositoryResult<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        // Linking a different account at the same provider replaces the old one
+        sqlx::query("DELETE FROM user_identities WHERE user_id = $1 AND provider = $2 AND external_id <> $3")
+            .bind(user_id)
+            .bind(&account.provider)
+            .bind(&account.external_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Only updates the row if it is this user's, otherwise nothing comes back
+        let row = sqlx::query_as::<_, (String,)>(
+            "INSERT INTO user_identities (user_id, provider, external_id, username) VALUES ($1, $2, $3, $4)
+             ON CONFLICT (provider, external_id) DO UPDATE SET username = EXCLUDED.username, linked_at = NOW()
+             WHERE user_identities.user_id = EXCLUDED.user_id
+             RETURNING user_id"
+        )
+        .bind(user_id)
+        .bind(&account.provider)
+        .bind(&account.external_id)
+        .bind(&account.username)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if row.is_none() {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn unlink(&self, user_id: &str, provider: &str) -> RepositoryResult<bool> {
+        let result = sqlx::query("DELETE FROM user_identities WHERE user_id = $1 AND provider = $2")
+            .bind(user_id)
+            .bind(provider)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn for_user(&self, user_id: &str) -> RepositoryResult<Vec<LinkedAccount>> {
+        Ok(self.for_users(&[user_id.to_string()]).await?.remove(user_id).unwrap_or_default())
+    }
+
+    async fn for_users(&self, user_ids: &[String]) -> RepositoryResult<HashMap<String, Vec<LinkedAccount>>> {
+        let rows = sqlx::query_as::<_, (String, String, String, Option<String>, String)>(
+            "SELECT user_id, provider, external_id, username, linked_at::TEXT
+             FROM user_identities
+             WHERE user_id = ANY($1)
+             ORDER BY provider"
+        )
+        .bind(user_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut accounts: HashMap<String, Vec<LinkedAccount>> = HashMap::new();
+        for (user_id, provider, external_id, username, linked_at) in rows {
+            accounts.entry(user_id).or_default().push(LinkedAccount { provider, external_id, username, linked_at });
+        }
+
+        Ok(accounts)
+    }
+
+    async fn find_user(&self, provider: &str, external_id: &str) -> RepositoryResult<Option<String>> {
+        let row = sqlx::query_as::<_, (String,)>("SELECT user_id FROM user_identities WHERE provider = $1 AND external_id = $2")
+            .bind(provider)
+            .bind(external_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|row| row.0))
+    }
+}
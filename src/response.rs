@@ -0,0 +1,47 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, body::BoxBody};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Placeholder data for responses that only carry a message
+#[derive(Serialize, ToSchema)]
+pub struct Empty {}
+
+/// Success envelope shared by every handler, errors go through `ApiError`
+/// `data` is flattened, so responses look like `{"success": true, "message": ..., <fields>}`
+#[derive(Serialize, ToSchema)]
+pub struct ApiResponse<T: Serialize = Empty> {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    #[serde(flatten)]
+    data: T,
+}
+
+impl<T: Serialize> ApiResponse<T> {
+    pub fn new(data: T) -> Self {
+        Self {
+            success: true,
+            message: None,
+            data,
+        }
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+}
+
+impl ApiResponse<Empty> {
+    pub fn message(message: impl Into<String>) -> Self {
+        Self::new(Empty {}).with_message(message)
+    }
+}
+
+impl<T: Serialize> Responder for ApiResponse<T> {
+    type Body = BoxBody;
+
+    fn respond_to(self, _req: &HttpRequest) -> HttpResponse<Self::Body> {
+        HttpResponse::Ok().json(self)
+    }
+}
@@ -0,0 +1,186 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use metrics::{counter, histogram};
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
+use std::time::Instant;
+use tracing::{Instrument, field};
+use tracing_subscriber::EnvFilter;
+
+use crate::config::LogFormat;
+use crate::error::ApiError;
+
+const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+const REQUEST_DURATION_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+/// Install the global log subscriber, filtered by `RUST_LOG` (defaults to `info`)
+pub fn init_logging(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(false).init(),
+    }
+}
+
+/// Reuse the caller's request id if it's sane, otherwise make one up
+fn request_id(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LENGTH
+                && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| URL_SAFE_NO_PAD.encode(rand::random::<[u8; 16]>()))
+}
+
+/// Middleware running each request inside a span with its id, route, client IP and (once known) user id
+///
+/// The id is taken from `X-Request-Id` or generated, and echoed back in the response.
+/// `JwtClaims` fills in `user_id` when the request is authenticated.
+pub async fn request_context(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let request_id = request_id(&req);
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        route = %req.match_pattern().unwrap_or_else(|| "unmatched".to_string()),
+        client_ip = req.connection_info().realip_remote_addr().unwrap_or("unknown"),
+        user_id = field::Empty,
+    );
+
+    let mut res = next.call(req).instrument(span).await?;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    Ok(res)
+}
+
+/// Install the global Prometheus recorder, the handle renders `/metrics`
+pub fn install() -> Result<PrometheusHandle, BuildError> {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Full("http_request_duration_seconds".to_string()), REQUEST_DURATION_BUCKETS)?
+        .install_recorder()
+}
+
+/// A recorder that is never installed, for in-process servers that share the global one with others
+/// Nothing is recorded into it, so `/metrics` renders empty
+pub fn detached() -> PrometheusHandle {
+    PrometheusBuilder::new().build_recorder().handle()
+}
+
+/// Middleware recording request counts and latency per route pattern
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let res = next.call(req).await?;
+
+    // Use the pattern (`/api/v1/session/{session_id}`), raw paths would explode the label set
+    let route = res.request().match_pattern().unwrap_or_else(|| "unmatched".to_string());
+    let status = res.status().as_u16().to_string();
+
+    counter!("http_requests_total", "method" => method.clone(), "route" => route.clone(), "status" => status).increment(1);
+    histogram!("http_request_duration_seconds", "method" => method, "route" => route).record(started.elapsed().as_secs_f64());
+
+    Ok(res)
+}
+
+/// `success` or the error code of a handler result
+pub fn outcome<T>(result: &Result<T, ApiError>) -> &'static str {
+    match result {
+        Ok(_) => "success",
+        Err(err) => err.code().as_str(),
+    }
+}
+
+pub fn record_db_error() {
+    counter!("db_errors_total").increment(1);
+}
+
+pub fn record_redis_error() {
+    counter!("redis_errors_total").increment(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, middleware, test, web};
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// Log lines written while the test runs
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// The request id a handler logged under and the one the response carries
+    async fn request(header: Option<&str>) -> (String, String) {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt().json().with_current_span(true).with_writer(move || writer.clone()).finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = test::init_service(
+            App::new()
+                .wrap(middleware::from_fn(request_context))
+                .route("/", web::get().to(|| async {
+                    tracing::info!("handled");
+                    "ok"
+                })),
+        )
+        .await;
+        let mut request = test::TestRequest::get().uri("/");
+        if let Some(id) = header {
+            request = request.insert_header(("X-Request-Id", id));
+        }
+        let response = test::call_service(&app, request.to_request()).await;
+        let echoed = response.headers().get("X-Request-Id").unwrap().to_str().unwrap().to_string();
+
+        let logs = String::from_utf8(captured.0.lock().clone()).unwrap();
+        let line: serde_json::Value = serde_json::from_str(logs.lines().find(|line| line.contains("handled")).unwrap()).unwrap();
+        (line["span"]["request_id"].as_str().unwrap().to_string(), echoed)
+    }
+
+    #[actix_web::test]
+    async fn incoming_request_ids_are_logged_and_echoed() {
+        assert_eq!(request(Some("lb-7f3a.9")).await, ("lb-7f3a.9".to_string(), "lb-7f3a.9".to_string()));
+    }
+
+    #[actix_web::test]
+    async fn request_ids_are_generated_when_missing_or_unusable() {
+        for header in [None, Some("not an id"), Some("")] {
+            let (logged, echoed) = request(header).await;
+            assert_eq!(logged, echoed);
+            assert_eq!(echoed.len(), 22, "{:?}", header);
+        }
+
+        assert_ne!(request(None).await.1, request(None).await.1);
+    }
+}
@@ -0,0 +1,303 @@
+use chrono::{TimeZone, Utc};
+use serde_json::json;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+use super::{Webhooks, WebhookEvent, signature};
+use crate::config::WebhookConfig;
+use crate::repository::{DeliveryAttempt, DueDelivery, Repositories};
+use crate::telemetry;
+
+/// Deliveries claimed per round
+const BATCH_SIZE: i64 = 20;
+
+/// How often expired licenses are looked for
+const EXPIRY_SCAN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Background task sending queued webhook deliveries and retrying failed ones with backoff
+///
+/// Deliveries are claimed with a lease, so several instances can run side by side
+/// and a crashed one's claims are picked up again once the lease runs out.
+pub struct Dispatcher {
+    repos: Repositories,
+    http: reqwest::Client,
+    config: WebhookConfig,
+}
+
+impl Dispatcher {
+    pub fn new(repos: Repositories, config: WebhookConfig) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent(concat!("authit-webhooks/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("webhook HTTP client");
+
+        Self { repos, http, config }
+    }
+
+    /// Runs forever, spawn it
+    pub async fn run(self) {
+        info!("Webhook dispatcher started");
+        let mut interval = actix_web::rt::time::interval(Duration::from_millis(self.config.poll_interval_ms));
+        let mut last_expiry_scan: Option<Instant> = None;
+
+        loop {
+            interval.tick().await;
+
+            if last_expiry_scan.is_none_or(|scanned| scanned.elapsed() >= EXPIRY_SCAN_INTERVAL) {
+                self.announce_expired_licenses().await;
+                last_expiry_scan = Some(Instant::now());
+            }
+
+            // Keep going while there's a backlog instead of waiting for the next tick
+            while self.deliver_due().await == BATCH_SIZE as usize {}
+        }
+    }
+
+    /// Publish `license.expired` once for each license that ran out since the last scan
+    pub async fn announce_expired_licenses(&self) {
+        let expired = match self.repos.licenses.take_expired().await {
+            Ok(expired) => expired,
+            Err(err) => {
+                error!("Database error looking for expired licenses: {}", err);
+                telemetry::record_db_error();
+                return;
+            }
+        };
+
+        let webhooks = Webhooks::new(self.repos.webhooks.clone());
+        for license in expired {
+            let expired_at = Utc.timestamp_opt(license.expires_at, 0).single().map(|at| at.to_rfc3339());
+            webhooks
+                .publish(
+                    WebhookEvent::LicenseExpired,
+                    json!({ "user_id": license.user_id, "product_id": license.product_id, "expired_at": expired_at }),
+                )
+                .await;
+        }
+    }
+
+    /// Send one batch of due deliveries, returns how many were claimed
+    pub async fn deliver_due(&self) -> usize {
+        // Long enough that a claim never expires while its request is still in flight
+        let lease_seconds = (self.config.timeout_ms / 1000) as i64 * 2 + 30;
+        let due = match self.repos.webhooks.claim_due(BATCH_SIZE, lease_seconds).await {
+            Ok(due) => due,
+            Err(err) => {
+                error!("Database error claiming webhook deliveries: {}", err);
+                telemetry::record_db_error();
+                return 0;
+            }
+        };
+
+        let claimed = due.len();
+        futures_util::future::join_all(due.into_iter().map(|delivery| self.deliver(delivery))).await;
+        claimed
+    }
+
+    async fn deliver(&self, delivery: DueDelivery) {
+        let signature = signature::sign(&delivery.secret, Utc::now().timestamp(), &delivery.payload);
+        let response = self
+            .http
+            .post(&delivery.url)
+            .header("Content-Type", "application/json")
+            .header("X-Authit-Event", delivery.event.as_str())
+            .header("X-Authit-Delivery", &delivery.id)
+            .header("X-Authit-Signature", signature)
+            .body(delivery.payload.clone())
+            .send()
+            .await;
+
+        let (status_code, error) = match response {
+            Ok(response) if response.status().is_success() => (Some(response.status().as_u16() as i32), None),
+            Ok(response) => (Some(response.status().as_u16() as i32), Some(format!("HTTP {}", response.status()))),
+            Err(err) => (None, Some(err.to_string())),
+        };
+
+        let attempt = match (status_code, error) {
+            (Some(status_code), None) => DeliveryAttempt::Delivered { status_code },
+            (status_code, Some(error)) if delivery.attempts + 1 >= self.config.max_attempts => {
+                warn!("Webhook delivery {} to {} failed for good: {}", delivery.id, delivery.url, error);
+                DeliveryAttempt::Failed { status_code, error }
+            }
+            (status_code, Some(error)) => DeliveryAttempt::Retry {
+                status_code,
+                error,
+                retry_in_seconds: self.retry_delay(delivery.attempts),
+            },
+            (None, None) => unreachable!("a delivery without a status code always has an error"),
+        };
+
+        let outcome = match attempt {
+            DeliveryAttempt::Delivered { .. } => "delivered",
+            DeliveryAttempt::Retry { .. } => "retry",
+            DeliveryAttempt::Failed { .. } => "failed",
+        };
+        metrics::counter!("webhook_deliveries_total", "event" => delivery.event.as_str(), "outcome" => outcome)
+            .increment(1);
+
+        if let Err(err) = self.repos.webhooks.record_attempt(&delivery.id, &attempt).await {
+            error!("Database error recording webhook delivery {}: {}", delivery.id, err);
+            telemetry::record_db_error();
+        }
+    }
+
+    /// Exponential backoff, `retry_base_seconds` doubled per attempt up to `retry_max_seconds`
+    fn retry_delay(&self, attempts: i32) -> i64 {
+        let factor = 1i64.checked_shl(attempts.clamp(0, 32) as u32).unwrap_or(i64::MAX);
+        self.config.retry_base_seconds.saturating_mul(factor).min(self.config.retry_max_seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{HttpRequest, HttpResponse, HttpServer, web};
+    use parking_lot::Mutex;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU16, Ordering};
+
+    use crate::repository::HwidPolicy;
+    use crate::repository::MemoryRepository;
+    use crate::webhook::{DeliveryStatus, WebhookPayload};
+
+    const SECRET: &str = "whsec_test_secret";
+
+    /// A local receiver answering with `status`, records every (signature header, body) it gets
+    struct Receiver {
+        url: String,
+        status: Arc<AtomicU16>,
+        received: Arc<Mutex<Vec<(String, String)>>>,
+    }
+
+    fn receiver(status: u16) -> Receiver {
+        let status = Arc::new(AtomicU16::new(status));
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+
+        let (server_status, server_received) = (status.clone(), received.clone());
+        let server = HttpServer::new(move || {
+            let (status, received) = (server_status.clone(), server_received.clone());
+            actix_web::App::new().route(
+                "/hook",
+                web::post().to(move |req: HttpRequest, body: String| {
+                    let (status, received) = (status.clone(), received.clone());
+                    async move {
+                        let signature = req.headers().get("X-Authit-Signature").unwrap().to_str().unwrap().to_string();
+                        received.lock().push((signature, body));
+                        HttpResponse::build(actix_web::http::StatusCode::from_u16(status.load(Ordering::SeqCst)).unwrap())
+                            .finish()
+                    }
+                }),
+            )
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+
+        Receiver { url, status, received }
+    }
+
+    fn dispatcher(repository: Arc<MemoryRepository>) -> Dispatcher {
+        // No backoff so retries are due right away
+        let config = WebhookConfig { max_attempts: 3, retry_base_seconds: 0, ..WebhookConfig::default() };
+        Dispatcher::new(Repositories::memory(repository), config)
+    }
+
+    #[actix_web::test]
+    async fn deliveries_are_signed() {
+        let receiver = receiver(200);
+        let repository = Arc::new(MemoryRepository::new());
+        let dispatcher = dispatcher(repository);
+        dispatcher.repos.webhooks.create(&receiver.url, SECRET, &[WebhookEvent::UserBanned]).await.unwrap();
+
+        let webhooks = Webhooks::new(dispatcher.repos.webhooks.clone());
+        webhooks.publish(WebhookEvent::UserBanned, json!({ "user_id": "user-1" })).await;
+        // Not subscribed to
+        webhooks.publish(WebhookEvent::RoleChanged, json!({ "user_id": "user-1" })).await;
+
+        assert_eq!(dispatcher.deliver_due().await, 1);
+        let received = receiver.received.lock().clone();
+        assert_eq!(received.len(), 1);
+        let (signature, body) = &received[0];
+        assert!(signature::verify(SECRET, signature, body, Utc::now().timestamp(), 300));
+
+        let payload: WebhookPayload = serde_json::from_str(body).unwrap();
+        assert_eq!(payload.event, WebhookEvent::UserBanned);
+        assert_eq!(payload.data["user_id"], "user-1");
+
+        let deliveries = dispatcher.repos.webhooks.deliveries(None, None, 10).await.unwrap();
+        assert_eq!(deliveries[0].status, DeliveryStatus::Delivered);
+        assert_eq!(deliveries[0].last_status_code, Some(200));
+    }
+
+    #[actix_web::test]
+    async fn failures_are_retried_until_out_of_attempts() {
+        let receiver = receiver(500);
+        let repository = Arc::new(MemoryRepository::new());
+        let dispatcher = dispatcher(repository);
+        dispatcher.repos.webhooks.create(&receiver.url, SECRET, &[WebhookEvent::HwidReset]).await.unwrap();
+        Webhooks::new(dispatcher.repos.webhooks.clone()).publish(WebhookEvent::HwidReset, json!({})).await;
+
+        for _ in 0..3 {
+            assert_eq!(dispatcher.deliver_due().await, 1);
+        }
+        assert_eq!(dispatcher.deliver_due().await, 0);
+        assert_eq!(receiver.received.lock().len(), 3);
+
+        let delivery = dispatcher.repos.webhooks.deliveries(None, None, 10).await.unwrap().remove(0);
+        assert_eq!(delivery.status, DeliveryStatus::Failed);
+        assert_eq!(delivery.attempts, 3);
+        assert_eq!(delivery.last_status_code, Some(500));
+
+        // Redelivering after the receiver is fixed starts over
+        receiver.status.store(204, Ordering::SeqCst);
+        assert!(dispatcher.repos.webhooks.redeliver(&delivery.id).await.unwrap());
+        assert_eq!(dispatcher.deliver_due().await, 1);
+        let delivery = dispatcher.repos.webhooks.deliveries(None, None, 10).await.unwrap().remove(0);
+        assert_eq!(delivery.status, DeliveryStatus::Delivered);
+        assert_eq!(delivery.attempts, 1);
+    }
+
+    #[actix_web::test]
+    async fn expired_licenses_are_announced_once() {
+        let receiver = receiver(200);
+        let repository = Arc::new(MemoryRepository::new());
+        repository.add_product("game", "Game", HwidPolicy::PerAccount, None);
+        repository.add_license("user-1", "game", Utc::now().timestamp() - 60);
+        repository.add_license("user-2", "game", Utc::now().timestamp() + 3600);
+        let dispatcher = dispatcher(repository);
+        dispatcher.repos.webhooks.create(&receiver.url, SECRET, &[WebhookEvent::LicenseExpired]).await.unwrap();
+
+        dispatcher.announce_expired_licenses().await;
+        dispatcher.announce_expired_licenses().await;
+        assert_eq!(dispatcher.deliver_due().await, 1);
+
+        let (_, body) = receiver.received.lock()[0].clone();
+        let payload: WebhookPayload = serde_json::from_str(&body).unwrap();
+        assert_eq!(payload.data["user_id"], "user-1");
+        assert_eq!(payload.data["product_id"], "game");
+
+        // Extending it and letting it run out again is a new expiry
+        dispatcher.repos.licenses.extend("user-1", "game", 1).await.unwrap();
+        dispatcher.announce_expired_licenses().await;
+        assert_eq!(dispatcher.deliver_due().await, 0);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let config = WebhookConfig { retry_base_seconds: 30, retry_max_seconds: 600, ..WebhookConfig::default() };
+        let dispatcher = Dispatcher::new(Repositories::memory(Arc::new(MemoryRepository::new())), config);
+
+        assert_eq!(dispatcher.retry_delay(0), 30);
+        assert_eq!(dispatcher.retry_delay(1), 60);
+        assert_eq!(dispatcher.retry_delay(4), 480);
+        assert_eq!(dispatcher.retry_delay(5), 600);
+        assert_eq!(dispatcher.retry_delay(100), 600);
+    }
+}
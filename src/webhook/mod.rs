@@ -0,0 +1,53 @@
+pub mod dispatcher;
+pub use dispatcher::*;
+pub mod signature;
+
+pub use authit_types::{DeliveryStatus, Webhook, WebhookDelivery, WebhookEvent, WebhookPayload};
+
+use chrono::Utc;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use crate::repository::WebhookRepository;
+use crate::telemetry;
+
+/// Queues events for the dispatcher, cheap to create wherever an event happens
+pub struct Webhooks {
+    repo: Arc<dyn WebhookRepository>,
+}
+
+impl Webhooks {
+    pub fn new(repo: Arc<dyn WebhookRepository>) -> Self {
+        Self { repo }
+    }
+
+    /// Queue `event` for every webhook subscribed to it, it is sent in the background
+    /// A failure is logged and never fails the change that caused the event
+    pub async fn publish(&self, event: WebhookEvent, data: serde_json::Value) {
+        let payload = WebhookPayload {
+            id: format!("evt_{:032x}", rand::random::<u128>()),
+            event,
+            created_at: Utc::now().to_rfc3339(),
+            data,
+        };
+        let payload = match serde_json::to_string(&payload) {
+            Ok(payload) => payload,
+            Err(err) => {
+                error!("Failed to serialize {} webhook payload: {}", event, err);
+                return;
+            }
+        };
+
+        match self.repo.enqueue(event, &payload).await {
+            Ok(0) => {}
+            Ok(queued) => {
+                metrics::counter!("webhook_events_total", "event" => event.as_str()).increment(1);
+                info!("Queued {} for {} webhook(s)", event, queued);
+            }
+            Err(err) => {
+                error!("Database error queueing {} webhook: {}", event, err);
+                telemetry::record_db_error();
+            }
+        }
+    }
+}